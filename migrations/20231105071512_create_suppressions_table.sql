@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE suppressions(
+    suppression_id uuid NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('address', 'domain')),
+    value TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (suppression_id),
+    UNIQUE (kind, value)
+);
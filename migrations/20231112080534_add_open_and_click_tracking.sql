@@ -0,0 +1,16 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN tracking_enabled BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE subscriptions ADD COLUMN tracking_enabled BOOLEAN NOT NULL DEFAULT true;
+ALTER TABLE issue_deliveries ADD COLUMN tracking_token TEXT NULL UNIQUE;
+
+CREATE TABLE delivery_events(
+    event_id uuid NOT NULL,
+    newsletter_issue_id uuid NOT NULL,
+    subscriber_email TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('open', 'click')),
+    url TEXT NULL,
+    occurred_at timestamptz NOT NULL,
+    PRIMARY KEY(event_id),
+    FOREIGN KEY (newsletter_issue_id, subscriber_email)
+        REFERENCES issue_deliveries (newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN delivery_state TEXT NOT NULL DEFAULT 'active'
+    CHECK (delivery_state IN ('active', 'paused', 'cancelled'));
+
+ALTER TABLE issue_deliveries DROP CONSTRAINT issue_deliveries_status_check;
+ALTER TABLE issue_deliveries ADD CONSTRAINT issue_deliveries_status_check
+    CHECK (status IN ('pending', 'sent', 'failed', 'skipped', 'cancelled'));
@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE delivery_rate_limits(
+    name TEXT NOT NULL,
+    tokens DOUBLE PRECISION NOT NULL,
+    refilled_at timestamptz NOT NULL,
+    PRIMARY KEY(name)
+);
@@ -0,0 +1,18 @@
+-- Add migration script here
+CREATE FUNCTION notify_issue_delivery_workers() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('issue_delivery_queue', '');
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER issue_delivery_queue_filled
+AFTER INSERT ON issue_delivery_queue
+FOR EACH STATEMENT
+EXECUTE FUNCTION notify_issue_delivery_workers();
+
+CREATE TRIGGER newsletter_issue_delivery_resumed
+AFTER UPDATE OF delivery_state ON newsletter_issues
+FOR EACH ROW
+WHEN (NEW.delivery_state = 'active' AND OLD.delivery_state <> 'active')
+EXECUTE FUNCTION notify_issue_delivery_workers();
@@ -0,0 +1,16 @@
+-- Add migration script here
+-- The seeded admin comes with a publicly known password. Operators create their users with
+-- `zero2prod admin create-user` instead - an admin whose password has been changed is kept.
+DELETE FROM idempotency
+WHERE user_id IN (
+    SELECT user_id
+    FROM users
+    WHERE
+        user_id = '3389aee0-1427-41cc-975d-c094e93c055d' AND
+        password_hash = '$argon2id$v=19$m=15000,t=2,p=1$/UBnXtgoYBiaUPthc9RtVA$Fw6pOwda4N2XH5M8ib/1QFgIAU8yZ+yp9AMycAk4vBM'
+);
+
+DELETE FROM users
+WHERE
+    user_id = '3389aee0-1427-41cc-975d-c094e93c055d' AND
+    password_hash = '$argon2id$v=19$m=15000,t=2,p=1$/UBnXtgoYBiaUPthc9RtVA$Fw6pOwda4N2XH5M8ib/1QFgIAU8yZ+yp9AMycAk4vBM';
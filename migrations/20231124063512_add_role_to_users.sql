@@ -0,0 +1,5 @@
+-- Add migration script here
+-- everybody who could log in so far was allowed to do everything
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'owner'
+    CHECK (role IN ('owner', 'editor', 'viewer'));
+ALTER TABLE users ALTER COLUMN role DROP DEFAULT;
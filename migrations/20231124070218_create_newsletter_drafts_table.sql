@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE newsletter_drafts(
+    draft_id uuid NOT NULL,
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    tracking_enabled BOOLEAN NOT NULL,
+    author_id uuid REFERENCES users (user_id) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY(draft_id)
+);
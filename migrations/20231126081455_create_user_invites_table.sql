@@ -0,0 +1,12 @@
+-- Add migration script here
+ALTER TABLE users ADD COLUMN email TEXT UNIQUE;
+
+CREATE TABLE user_invites(
+    invite_token TEXT NOT NULL,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'editor', 'viewer')),
+    invited_by uuid REFERENCES users (user_id) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    PRIMARY KEY(invite_token)
+);
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- every login is registered, a session whose row is gone is no longer accepted
+CREATE TABLE user_sessions(
+    session_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL
+);
+CREATE INDEX user_sessions_user_id ON user_sessions (user_id);
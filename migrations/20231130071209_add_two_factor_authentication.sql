@@ -0,0 +1,11 @@
+-- Add migration script here
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
+-- a code can only be used once, so later logins have to use a later time step
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT;
+
+CREATE TABLE totp_recovery_codes(
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at timestamptz,
+    PRIMARY KEY(user_id, code_hash)
+);
@@ -0,0 +1,18 @@
+-- Add migration script here
+-- failed logins are counted per username and per client ip, e.g. 'username:alice' or 'ip:10.0.0.1'
+CREATE TABLE login_throttles(
+    throttle_key TEXT NOT NULL,
+    n_failures INT NOT NULL,
+    window_started_at timestamptz NOT NULL,
+    locked_until timestamptz,
+    PRIMARY KEY(throttle_key)
+);
+
+CREATE TABLE login_lockouts(
+    lockout_id uuid NOT NULL,
+    throttle_key TEXT NOT NULL,
+    n_failures INT NOT NULL,
+    locked_at timestamptz NOT NULL,
+    locked_until timestamptz NOT NULL,
+    PRIMARY KEY(lockout_id)
+);
@@ -0,0 +1,9 @@
+-- Add migration script here
+BEGIN;
+    ALTER TABLE user_sessions
+        ADD COLUMN last_seen_at timestamptz,
+        ADD COLUMN ip_address TEXT,
+        ADD COLUMN user_agent TEXT;
+    UPDATE user_sessions SET last_seen_at = created_at;
+    ALTER TABLE user_sessions ALTER COLUMN last_seen_at SET NOT NULL;
+COMMIT;
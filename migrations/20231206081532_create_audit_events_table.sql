@@ -0,0 +1,28 @@
+-- Add migration script here
+-- no foreign key on user_id: the events have to outlive the users that caused them
+CREATE TABLE audit_events(
+    event_id BIGSERIAL NOT NULL,
+    occurred_at timestamptz NOT NULL DEFAULT now(),
+    user_id uuid,
+    action TEXT NOT NULL,
+    ip_address TEXT,
+    payload JSONB NOT NULL,
+    PRIMARY KEY(event_id)
+);
+CREATE INDEX audit_events_action_idx ON audit_events (action, occurred_at);
+CREATE INDEX audit_events_user_id_idx ON audit_events (user_id, occurred_at);
+
+-- the log is append-only, not even the application may rewrite the history
+CREATE FUNCTION reject_audit_event_changes() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit events cannot be changed or deleted';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only
+    BEFORE UPDATE OR DELETE ON audit_events
+    FOR EACH ROW EXECUTE FUNCTION reject_audit_event_changes();
+
+CREATE TRIGGER audit_events_no_truncate
+    BEFORE TRUNCATE ON audit_events
+    FOR EACH STATEMENT EXECUTE FUNCTION reject_audit_event_changes();
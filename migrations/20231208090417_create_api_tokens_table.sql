@@ -0,0 +1,14 @@
+-- Add migration script here
+-- only a hash of the token is stored, the token itself is shown once when it is created
+CREATE TABLE api_tokens(
+    token_id uuid NOT NULL,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    last_used_at timestamptz,
+    PRIMARY KEY(token_id)
+);
+CREATE INDEX api_tokens_user_id_idx ON api_tokens (user_id);
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- only a hash of the invite token is stored, like for the api tokens
+ALTER TABLE user_invites RENAME COLUMN invite_token TO token_hash;
+UPDATE user_invites SET token_hash = encode(sha256(convert_to(token_hash, 'UTF8')), 'hex');
@@ -1,7 +1,25 @@
+mod api_tokens;
+mod audit;
 mod dashboard;
+mod drafts;
+mod issues;
 mod newsletters;
 mod password;
+mod sessions;
+mod subscribers;
+mod suppressions;
+mod two_factor;
+mod users;
 
+pub use api_tokens::*;
+pub use audit::*;
 pub use dashboard::*;
+pub use drafts::*;
+pub use issues::*;
 pub use newsletters::*;
 pub use password::*;
+pub use sessions::*;
+pub use subscribers::*;
+pub use suppressions::*;
+pub use two_factor::*;
+pub use users::*;
@@ -0,0 +1,112 @@
+use actix_web::{
+    http::header::ContentType,
+    web::{self, ReqData},
+    HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::{csrf_field, list_api_tokens, UserId, MAX_API_TOKEN_VALIDITY_DAYS},
+    session_state::TypedSession,
+    utils::e500,
+};
+
+pub async fn api_tokens_page(
+    user_id: ReqData<UserId>,
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session).map_err(e500)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap();
+    }
+
+    let tokens = list_api_tokens(*user_id.into_inner(), &pool)
+        .await
+        .map_err(e500)?;
+    let mut rows_html = String::new();
+    for t in tokens {
+        let scopes: Vec<&str> = t.scopes.iter().map(|s| s.as_str()).collect();
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{name}</td>
+                <td>{scopes}</td>
+                <td>{created_at}</td>
+                <td>{expires_at}</td>
+                <td>{last_used_at}</td>
+                <td>
+                    <form action="/admin/api-tokens/revoke" method="post">
+                        {csrf_field}
+                        <input hidden type="text" name="token_id" value="{token_id}">
+                        <button type="submit">Revoke</button>
+                    </form>
+                </td>
+            </tr>"#,
+            name = htmlescape::encode_minimal(&t.name),
+            scopes = scopes.join(", "),
+            created_at = t.created_at.format("%Y-%m-%d %H:%M"),
+            expires_at = t.expires_at.format("%Y-%m-%d %H:%M"),
+            last_used_at = t
+                .last_used_at
+                .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| "never".to_string()),
+            token_id = t.token_id,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>API Tokens</title>
+    </head>
+
+    <body>
+        {msg_html}
+        <p>API tokens let scripts use the admin area on your behalf - send them in an
+        <code>Authorization: Bearer</code> header.</p>
+        <table>
+            <tr>
+                <th>Name</th>
+                <th>Scopes</th>
+                <th>Created</th>
+                <th>Expires</th>
+                <th>Last used</th>
+                <th></th>
+            </tr>
+            {rows_html}
+        </table>
+        <form action="/admin/api-tokens" method="post">
+            {csrf_field}
+            <label>Name
+                <input type="text" placeholder="Enter the name of the token" name="name">
+            </label>
+            <label>
+                <input type="checkbox" name="read_scope" value="on" checked>
+                Read
+            </label>
+            <label>
+                <input type="checkbox" name="write_scope" value="on">
+                Write
+            </label>
+            <label>Valid for (days)
+                <input type="number" name="valid_for_days" value="30" min="1" max="{MAX_API_TOKEN_VALIDITY_DAYS}">
+            </label>
+            <button type="submit">Create token</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+
+    </html>"#
+        )))
+}
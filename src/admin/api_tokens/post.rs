@@ -0,0 +1,171 @@
+use actix_web::{
+    http::header::ContentType,
+    web::{self, ReqData},
+    HttpResponse,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Duration;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditAction},
+    authentication::{
+        create_api_token, revoke_api_token, ApiScope, ClientIp, UserId, MAX_API_TOKEN_VALIDITY_DAYS,
+    },
+    utils::{e500, see_other},
+};
+
+const MAX_API_TOKEN_NAME_LENGTH: usize = 100;
+
+#[derive(serde::Deserialize)]
+pub struct CreateFormData {
+    name: String,
+    // html checkboxes are only submitted when they are ticked
+    read_scope: Option<String>,
+    write_scope: Option<String>,
+    valid_for_days: i64,
+}
+
+/// Responds with the token right away, as it cannot be shown again.
+#[tracing::instrument(name = "create an api token", skip(form, pool, user_id, client_ip))]
+pub async fn add_api_token(
+    user_id: ReqData<UserId>,
+    client_ip: ClientIp,
+    form: web::Form<CreateFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let CreateFormData {
+        name,
+        read_scope,
+        write_scope,
+        valid_for_days,
+    } = form.into_inner();
+    let name = name.trim();
+    let scopes: Vec<ApiScope> = [(read_scope, ApiScope::Read), (write_scope, ApiScope::Write)]
+        .into_iter()
+        .filter_map(|(ticked, scope)| ticked.map(|_| scope))
+        .collect();
+    let error = if name.is_empty() || name.chars().count() > MAX_API_TOKEN_NAME_LENGTH {
+        Some(format!(
+            "The name must be between 1 and {MAX_API_TOKEN_NAME_LENGTH} characters long."
+        ))
+    } else if scopes.is_empty() {
+        Some("Choose at least one scope.".to_string())
+    } else if !(1..=MAX_API_TOKEN_VALIDITY_DAYS).contains(&valid_for_days) {
+        Some(format!(
+            "Tokens can be valid for 1 to {MAX_API_TOKEN_VALIDITY_DAYS} days."
+        ))
+    } else {
+        None
+    };
+    if let Some(error) = error {
+        FlashMessage::error(error).send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    let token = create_api_token(
+        **user_id,
+        name,
+        &scopes,
+        Duration::days(valid_for_days),
+        &mut transaction,
+    )
+    .await
+    .map_err(e500)?;
+    let scopes: Vec<&str> = scopes.iter().map(ApiScope::as_str).collect();
+    record_audit_event(
+        Some(**user_id),
+        client_ip.0,
+        AuditAction::ApiTokenCreated,
+        serde_json::json!({
+            "name": name,
+            "scopes": scopes,
+            "valid_for_days": valid_for_days,
+        }),
+        &mut transaction,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit the new api token")
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>New API Token</title>
+    </head>
+
+    <body>
+        <p>The token {name} has been created.</p>
+        <p>Copy it now, it will not be shown again:</p>
+        <p><code>{token}</code></p>
+        <p><a href="/admin/api-tokens">&lt;- Back</a></p>
+    </body>
+
+    </html>"#,
+            name = htmlescape::encode_minimal(name),
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RevokeTokenFormData {
+    token_id: Uuid,
+}
+
+#[tracing::instrument(name = "revoke an api token", skip(form, pool, user_id, client_ip))]
+pub async fn remove_api_token(
+    user_id: ReqData<UserId>,
+    client_ip: ClientIp,
+    form: web::Form<RevokeTokenFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    // tokens of other users are not found, just like already revoked ones
+    match revoke_api_token(**user_id, form.token_id, &mut transaction)
+        .await
+        .map_err(e500)?
+    {
+        Some(name) => {
+            record_audit_event(
+                Some(**user_id),
+                client_ip.0,
+                AuditAction::ApiTokenRevoked,
+                serde_json::json!({ "name": name }),
+                &mut transaction,
+            )
+            .await
+            .map_err(e500)?;
+            transaction
+                .commit()
+                .await
+                .context("failed to commit the revocation of the api token")
+                .map_err(e500)?;
+            FlashMessage::info(format!(
+                "The token {} has been revoked.",
+                htmlescape::encode_minimal(&name)
+            ))
+            .send();
+        }
+        None => FlashMessage::error("The token does not exist anymore.").send(),
+    }
+    Ok(see_other("/admin/api-tokens"))
+}
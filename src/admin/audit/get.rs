@@ -0,0 +1,102 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    audit::{list_audit_events, AuditAction},
+    utils::e500,
+};
+
+/// Older events are only reachable by narrowing down the filters.
+const MAX_EVENTS_SHOWN: i64 = 500;
+
+#[derive(serde::Deserialize)]
+pub struct AuditFilter {
+    action: Option<String>,
+    username: Option<String>,
+}
+
+pub async fn audit_page(
+    filter: web::Query<AuditFilter>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // the filter form submits empty fields for "any" - unknown actions are treated the same
+    let AuditFilter { action, username } = filter.into_inner();
+    let action: Option<AuditAction> = action.and_then(|a| a.parse().ok());
+    let username = username.filter(|u| !u.trim().is_empty());
+
+    let events = list_audit_events(action, username.as_deref(), MAX_EVENTS_SHOWN, &pool)
+        .await
+        .map_err(e500)?;
+    let mut rows_html = String::new();
+    for e in events {
+        let user = match (&e.username, e.user_id) {
+            (Some(username), _) => htmlescape::encode_minimal(username),
+            (None, Some(user_id)) => format!("deleted user {user_id}"),
+            (None, None) => "-".to_string(),
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{occurred_at}</td>
+                <td>{user}</td>
+                <td>{action}</td>
+                <td>{ip_address}</td>
+                <td><code>{payload}</code></td>
+            </tr>"#,
+            occurred_at = e.occurred_at.format("%Y-%m-%d %H:%M:%S"),
+            action = htmlescape::encode_minimal(&e.action),
+            ip_address = htmlescape::encode_minimal(e.ip_address.as_deref().unwrap_or("-")),
+            payload = htmlescape::encode_minimal(&e.payload.to_string()),
+        )
+        .unwrap();
+    }
+
+    let mut action_options = String::from(r#"<option value="">Any</option>"#);
+    for a in AuditAction::ALL {
+        let selected = if Some(a) == action { " selected" } else { "" };
+        write!(
+            action_options,
+            r#"<option value="{a}"{selected}>{a}</option>"#
+        )
+        .unwrap();
+    }
+    let username = htmlescape::encode_minimal(username.as_deref().unwrap_or(""));
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Audit Log</title>
+    </head>
+
+    <body>
+        <form action="/admin/audit" method="get">
+            <label>Action
+                <select name="action">{action_options}</select>
+            </label>
+            <label>User
+                <input type="text" name="username" value="{username}">
+            </label>
+            <button type="submit">Filter</button>
+        </form>
+        <table>
+            <tr>
+                <th>Time</th>
+                <th>User</th>
+                <th>Action</th>
+                <th>IP address</th>
+                <th>Details</th>
+            </tr>
+            {rows_html}
+        </table>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+
+    </html>"#
+        )))
+}
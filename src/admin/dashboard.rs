@@ -7,14 +7,35 @@ use anyhow::Context;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{authentication::UserId, utils::e500};
+use crate::{
+    authentication::{csrf_field, get_role, Role, UserId},
+    session_state::TypedSession,
+    utils::e500,
+};
 
 pub async fn admin_dashboard(
     user_id: ReqData<UserId>,
+    session: TypedSession,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session).map_err(e500)?;
     let user_id = user_id.into_inner();
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let role = get_role(*user_id, &pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e500("the user does not exist anymore"))?;
+    let newsletter_action = match role {
+        Role::Owner => r#"<li><a href="/admin/newsletters">Send a newsletter issue</a></li>"#,
+        Role::Editor => r#"<li><a href="/admin/newsletters">Draft a newsletter issue</a></li>"#,
+        Role::Viewer => "",
+    };
+    let users_action = if role == Role::Owner {
+        r#"<li><a href="/admin/users">Manage and invite admin users</a></li>
+        <li><a href="/admin/audit">Review the audit log</a></li>"#
+    } else {
+        ""
+    };
     let response = HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -28,14 +49,23 @@ pub async fn admin_dashboard(
 </head>
 
 <body>
-    <p>Welcome, {username}!</p>
+    <p>Welcome, {username}! You are signed in as {role}.</p>
 
     <p>Available actions:</p>
     <ol>
         <li><a href="/admin/password">Change password</a></li>
-        <li><a href="/admin/newsletters">Send a newsletter issue</a></li>
+        <li><a href="/admin/two-factor">Set up two-factor authentication</a></li>
+        <li><a href="/admin/sessions">See where you are logged in</a></li>
+        <li><a href="/admin/api-tokens">Manage API tokens</a></li>
+        {newsletter_action}
+        <li><a href="/admin/drafts">Review drafts</a></li>
+        <li><a href="/admin/issues">Track the delivery of published issues</a></li>
+        <li><a href="/admin/subscribers">Manage subscribers</a></li>
+        <li><a href="/admin/suppressions">Manage the suppression list</a></li>
+        {users_action}
         <li>
             <form name="logoutForm" action="/admin/logout" method="post">
+                {csrf_field}
                 <input type="submit" value="Logout">
             </form>
         </li>
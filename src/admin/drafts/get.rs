@@ -0,0 +1,118 @@
+use actix_web::{
+    http::header::ContentType,
+    web::{self, ReqData},
+    HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{csrf_field, get_role, Role, UserId},
+    session_state::TypedSession,
+    utils::e500,
+};
+
+pub async fn drafts_page(
+    user_id: ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session).map_err(e500)?;
+    let role = get_role(**user_id, &pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e500("the user does not exist anymore"))?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap();
+    }
+
+    let drafts = list_drafts(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for draft in drafts {
+        let publish_form = if role == Role::Owner {
+            format!(
+                r#"<form action="/admin/drafts/{draft_id}/publish" method="post">
+                        {csrf_field}
+                        <button type="submit">Publish</button>
+                    </form>"#,
+                draft_id = draft.draft_id
+            )
+        } else {
+            String::new()
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{title}</td>
+                <td>{content}</td>
+                <td>{author}</td>
+                <td>{created_at}</td>
+                <td>{publish_form}</td>
+            </tr>"#,
+            title = htmlescape::encode_minimal(&draft.title),
+            content = htmlescape::encode_minimal(&draft.content),
+            author = htmlescape::encode_minimal(draft.author.as_deref().unwrap_or("-")),
+            created_at = draft.created_at.format("%Y-%m-%d %H:%M"),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Drafts</title>
+    </head>
+
+    <body>
+        {msg_html}
+        <table>
+            <tr>
+                <th>Title</th>
+                <th>Content</th>
+                <th>Author</th>
+                <th>Created</th>
+                <th></th>
+            </tr>
+            {rows_html}
+        </table>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+
+    </html>"#
+        )))
+}
+
+struct Draft {
+    draft_id: Uuid,
+    title: String,
+    content: String,
+    author: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "list drafts", skip(pool))]
+async fn list_drafts(pool: &PgPool) -> Result<Vec<Draft>, anyhow::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT d.draft_id, d.title, d.content, u.username AS "author?", d.created_at
+        FROM newsletter_drafts d
+        LEFT JOIN users u ON u.user_id = d.author_id
+        ORDER BY d.created_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to list the drafts")
+}
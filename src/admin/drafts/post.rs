@@ -0,0 +1,126 @@
+use actix_web::{
+    web::{self, ReqData},
+    HttpResponse,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    admin::newsletters::{enqueue_delivery_tasks, insert_newsletter_issue},
+    audit::{record_audit_event, AuditAction},
+    authentication::{ClientIp, UserId},
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    title: String,
+    content: String,
+    // html checkboxes are only submitted when they are ticked
+    tracking_enabled: Option<String>,
+}
+
+#[tracing::instrument(name = "save a draft", skip(form, pool))]
+pub async fn save_draft(
+    user_id: ReqData<UserId>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let DraftFormData {
+        title,
+        content,
+        tracking_enabled,
+    } = form.into_inner();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_drafts (
+            draft_id,
+            title,
+            content,
+            tracking_enabled,
+            author_id,
+            created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        title,
+        content,
+        tracking_enabled.is_some(),
+        **user_id
+    )
+    .execute(pool.as_ref())
+    .await
+    .context("failed to store the draft")
+    .map_err(e500)?;
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other("/admin/drafts"))
+}
+
+/// The draft is consumed by publishing it, so it cannot be published twice.
+#[tracing::instrument(name = "publish a draft", skip(pool, user_id, client_ip))]
+pub async fn publish_draft(
+    user_id: ReqData<UserId>,
+    client_ip: ClientIp,
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    let draft = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_drafts
+        WHERE draft_id = $1
+        RETURNING title, content, tracking_enabled
+        "#,
+        draft_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("failed to retrieve the draft")
+    .map_err(e500)?;
+    let Some(draft) = draft else {
+        FlashMessage::error("The draft does not exist or has already been published.").send();
+        return Ok(see_other("/admin/drafts"));
+    };
+
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &draft.title,
+        &draft.content,
+        draft.tracking_enabled,
+    )
+    .await
+    .context("failed to store newsletter issue details")
+    .map_err(e500)?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("failed to enqueue delivery tasks")
+        .map_err(e500)?;
+    record_audit_event(
+        Some(**user_id),
+        client_ip.0,
+        AuditAction::IssuePublished,
+        serde_json::json!({
+            "newsletter_issue_id": issue_id,
+            "title": draft.title,
+            "draft_id": draft_id,
+        }),
+        &mut transaction,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit the publication of the draft")
+        .map_err(e500)?;
+    FlashMessage::info("The newsletter issue has been published.").send();
+    Ok(see_other("/admin/drafts"))
+}
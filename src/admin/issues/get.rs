@@ -0,0 +1,271 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    authentication::csrf_field,
+    issue_deliveries::{get_delivery_stats, list_failed_deliveries, IssueDeliveryState},
+    session_state::TypedSession,
+    tracking::get_engagement_stats,
+    utils::e500,
+};
+
+pub async fn issues_page(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let issues = list_issues(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for issue in issues {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td><a href="/admin/issues/{issue_id}">{title}</a></td>
+                <td>{published_at}</td>
+                <td>{delivery_state}</td>
+                <td>{n_sent}</td>
+                <td>{n_failed}</td>
+                <td>{n_skipped}</td>
+                <td>{n_cancelled}</td>
+                <td>{n_pending}</td>
+            </tr>"#,
+            issue_id = issue.newsletter_issue_id,
+            title = htmlescape::encode_minimal(&issue.title),
+            published_at = issue.published_at,
+            delivery_state = issue.delivery_state,
+            n_sent = issue.n_sent,
+            n_failed = issue.n_failed,
+            n_skipped = issue.n_skipped,
+            n_cancelled = issue.n_cancelled,
+            n_pending = issue.n_pending,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Newsletter Issues</title>
+    </head>
+
+    <body>
+        <table>
+            <tr>
+                <th>Title</th>
+                <th>Published</th>
+                <th>Delivery</th>
+                <th>Sent</th>
+                <th>Failed</th>
+                <th>Skipped</th>
+                <th>Cancelled</th>
+                <th>Pending</th>
+            </tr>
+            {rows_html}
+        </table>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+
+    </html>"#
+        )))
+}
+
+pub async fn issue_status_page(
+    issue_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session).map_err(e500)?;
+    let issue_id = issue_id.into_inner();
+    let Some(issue) = get_issue_summary(&pool, issue_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let delivery_state: IssueDeliveryState = issue.delivery_state.try_into().map_err(e500)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap();
+    }
+    let stats = get_delivery_stats(&pool, issue_id).await.map_err(e500)?;
+    let failures = list_failed_deliveries(&pool, issue_id)
+        .await
+        .map_err(e500)?;
+    let engagement_html = if issue.tracking_enabled {
+        let engagement = get_engagement_stats(&pool, issue_id).await.map_err(e500)?;
+        format!(
+            r#"<p>Engagement (among {n_tracked} tracked recipients):</p>
+        <ul>
+            <li>Opened: {n_opened} ({open_rate:.1}%)</li>
+            <li>Clicked: {n_clicked} ({click_rate:.1}%)</li>
+        </ul>"#,
+            n_tracked = engagement.n_tracked,
+            n_opened = engagement.n_opened,
+            open_rate = engagement.open_rate(),
+            n_clicked = engagement.n_clicked,
+            click_rate = engagement.click_rate(),
+        )
+    } else {
+        "<p>Open and click tracking is disabled for this issue.</p>".to_string()
+    };
+
+    let mut failures_html = String::new();
+    for f in failures {
+        writeln!(
+            failures_html,
+            "<tr><td>{email}</td><td>{n_attempts}</td><td>{error}</td></tr>",
+            email = htmlescape::encode_minimal(&f.subscriber_email),
+            n_attempts = f.n_attempts,
+            error = htmlescape::encode_minimal(f.last_error.as_deref().unwrap_or_default()),
+        )
+        .unwrap();
+    }
+    let mut actions_html = String::new();
+    let actions: &[(&str, &str)] = match delivery_state {
+        IssueDeliveryState::Active => &[("pause", "Pause delivery"), ("cancel", "Cancel delivery")],
+        IssueDeliveryState::Paused => {
+            &[("resume", "Resume delivery"), ("cancel", "Cancel delivery")]
+        }
+        IssueDeliveryState::Cancelled => &[],
+    };
+    if stats.n_pending > 0 {
+        for (action, label) in actions {
+            writeln!(
+                actions_html,
+                r#"<form action="/admin/issues/{issue_id}/{action}" method="post">
+            {csrf_field}
+            <button type="submit">{label}</button>
+        </form>"#
+            )
+            .unwrap();
+        }
+    }
+    // keep the numbers fresh while the fan-out is still running
+    let refresh_html = if stats.is_running() && delivery_state == IssueDeliveryState::Active {
+        r#"<meta http-equiv="refresh" content="5">"#
+    } else {
+        ""
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        {refresh_html}
+        <title>Issue Delivery Status</title>
+    </head>
+
+    <body>
+        {msg_html}
+        <h1>{title}</h1>
+        <p>Published at {published_at}</p>
+        <p>Delivery state: {delivery_state}</p>
+        {actions_html}
+        <p>Progress: {n_processed} of {n_total} recipients processed ({progress:.1}%)</p>
+        <progress max="100" value="{progress:.0}"></progress>
+        <ul>
+            <li>Sent: {n_sent}</li>
+            <li>Failed: {n_failed}</li>
+            <li>Skipped: {n_skipped}</li>
+            <li>Cancelled: {n_cancelled}</li>
+            <li>Pending: {n_pending}</li>
+        </ul>
+        {engagement_html}
+
+        <p>Failed deliveries:</p>
+        <table>
+            <tr>
+                <th>Recipient</th>
+                <th>Attempts</th>
+                <th>Last error</th>
+            </tr>
+            {failures_html}
+        </table>
+        <p><a href="/admin/issues">&lt;- Back</a></p>
+    </body>
+
+    </html>"#,
+            title = htmlescape::encode_minimal(&issue.title),
+            published_at = issue.published_at,
+            delivery_state = delivery_state.as_str(),
+            n_processed = stats.n_processed(),
+            n_total = stats.n_total(),
+            progress = stats.progress(),
+            n_sent = stats.n_sent,
+            n_failed = stats.n_failed,
+            n_skipped = stats.n_skipped,
+            n_cancelled = stats.n_cancelled,
+            n_pending = stats.n_pending,
+        )))
+}
+
+struct IssueSummary {
+    title: String,
+    published_at: String,
+    tracking_enabled: bool,
+    delivery_state: String,
+}
+
+#[tracing::instrument(name = "get issue summary", skip(pool))]
+async fn get_issue_summary(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<IssueSummary>, anyhow::Error> {
+    sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT title, published_at, tracking_enabled, delivery_state
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to retrieve the newsletter issue")
+}
+
+struct IssueListEntry {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: String,
+    delivery_state: String,
+    n_sent: i64,
+    n_failed: i64,
+    n_skipped: i64,
+    n_cancelled: i64,
+    n_pending: i64,
+}
+
+#[tracing::instrument(name = "list issues", skip(pool))]
+async fn list_issues(pool: &PgPool) -> Result<Vec<IssueListEntry>, anyhow::Error> {
+    sqlx::query_as!(
+        IssueListEntry,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            i.delivery_state,
+            COUNT(d.subscriber_email) FILTER (WHERE d.status = 'sent') AS "n_sent!",
+            COUNT(d.subscriber_email) FILTER (WHERE d.status = 'failed') AS "n_failed!",
+            COUNT(d.subscriber_email) FILTER (WHERE d.status = 'skipped') AS "n_skipped!",
+            COUNT(d.subscriber_email) FILTER (WHERE d.status = 'cancelled') AS "n_cancelled!",
+            COUNT(d.subscriber_email) FILTER (WHERE d.status = 'pending') AS "n_pending!"
+        FROM newsletter_issues i
+        LEFT JOIN issue_deliveries d USING (newsletter_issue_id)
+        GROUP BY i.newsletter_issue_id
+        ORDER BY i.published_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to list the newsletter issues")
+}
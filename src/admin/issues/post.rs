@@ -0,0 +1,138 @@
+use actix_web::{
+    web::{self, ReqData},
+    HttpResponse,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditAction},
+    authentication::{ClientIp, UserId},
+    issue_deliveries::{cancel_delivery, pause_delivery, resume_delivery},
+    utils::{e500, see_other},
+};
+
+#[tracing::instrument(
+    name = "pause the delivery of an issue",
+    skip(pool, user_id, client_ip)
+)]
+pub async fn pause_issue_delivery(
+    user_id: ReqData<UserId>,
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    if pause_delivery(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?
+    {
+        record_audit_event(
+            Some(**user_id),
+            client_ip.0,
+            AuditAction::IssueDeliveryPaused,
+            serde_json::json!({ "issue_id": issue_id }),
+            &mut transaction,
+        )
+        .await
+        .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("failed to commit pausing the delivery")
+            .map_err(e500)?;
+        FlashMessage::info("The delivery of the issue has been paused.").send();
+    } else {
+        FlashMessage::error("Only active deliveries can be paused.").send();
+    }
+    Ok(see_other(&format!("/admin/issues/{issue_id}")))
+}
+
+#[tracing::instrument(
+    name = "resume the delivery of an issue",
+    skip(pool, user_id, client_ip)
+)]
+pub async fn resume_issue_delivery(
+    user_id: ReqData<UserId>,
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    if resume_delivery(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?
+    {
+        record_audit_event(
+            Some(**user_id),
+            client_ip.0,
+            AuditAction::IssueDeliveryResumed,
+            serde_json::json!({ "issue_id": issue_id }),
+            &mut transaction,
+        )
+        .await
+        .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("failed to commit resuming the delivery")
+            .map_err(e500)?;
+        FlashMessage::info("The delivery of the issue has been resumed.").send();
+    } else {
+        FlashMessage::error("Only paused deliveries can be resumed.").send();
+    }
+    Ok(see_other(&format!("/admin/issues/{issue_id}")))
+}
+
+#[tracing::instrument(
+    name = "cancel the delivery of an issue",
+    skip(pool, user_id, client_ip)
+)]
+pub async fn cancel_issue_delivery(
+    user_id: ReqData<UserId>,
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    if cancel_delivery(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?
+    {
+        record_audit_event(
+            Some(**user_id),
+            client_ip.0,
+            AuditAction::IssueDeliveryCancelled,
+            serde_json::json!({ "issue_id": issue_id }),
+            &mut transaction,
+        )
+        .await
+        .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("failed to commit cancelling the delivery")
+            .map_err(e500)?;
+        FlashMessage::info("The delivery of the issue has been cancelled.").send();
+    } else {
+        FlashMessage::error("The delivery of the issue has already been cancelled.").send();
+    }
+    Ok(see_other(&format!("/admin/issues/{issue_id}")))
+}
@@ -1,17 +1,42 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{
+    http::header::ContentType,
+    web::{self, ReqData},
+    HttpResponse,
+};
 use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
 use std::fmt::Write;
 use uuid::Uuid;
 
+use crate::{
+    authentication::{csrf_field, get_role, Role, UserId},
+    session_state::TypedSession,
+    utils::e500,
+};
+
 pub async fn newsletter_form(
+    user_id: ReqData<UserId>,
     flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session).map_err(e500)?;
+    let role = get_role(**user_id, &pool)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e500("the user does not exist anymore"))?;
     let mut flash_msg_html = String::new();
     for m in flash_messages.iter() {
         writeln!(flash_msg_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap()
     }
 
     let idempotency_key = Uuid::new_v4().to_string();
+    // only owners may publish, editors have to leave their issues for review
+    let publish_button = if role == Role::Owner {
+        r#"<button type="submit">Send Newsletter</button>"#
+    } else {
+        ""
+    };
     let response = HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -29,15 +54,21 @@ pub async fn newsletter_form(
         <p>Enter the newsletter issue below!</p>
     
         <form name="newsletterForm" action="/admin/newsletters" method="post">
+            {csrf_field}
             <label>Newsletter Title
                 <input type="text" placeholder="enter the newsletter title" name="title">
             </label>
             <label>Newsletter Content
                 <input type="text" placeholder="enter the newsletter content" name="content">
             </label>
+            <label>
+                <input type="checkbox" name="tracking_enabled">
+                Track opens and clicks
+            </label>
             <br>
             <input hidden type="text" name="idempotency_key" value={idempotency_key}>
-            <button type="submit">Send Newsletter</button>
+            {publish_button}
+            <button type="submit" formaction="/admin/drafts">Save as Draft</button>
         </form>
     </body>
     
@@ -8,7 +8,8 @@ use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
-    authentication::UserId,
+    audit::{record_audit_event, AuditAction},
+    authentication::{ClientIp, UserId},
     idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
     utils::{e400, e500, see_other},
 };
@@ -18,10 +19,12 @@ pub struct BodyData {
     title: String,
     content: String,
     idempotency_key: String,
+    // html checkboxes are only submitted when they are ticked
+    tracking_enabled: Option<String>,
 }
 
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+pub(crate) async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
 ) -> Result<(), sqlx::Error> {
@@ -37,16 +40,34 @@ async fn enqueue_delivery_tasks(
         "#,
         newsletter_issue_id
     )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries(
+            newsletter_issue_id,
+            subscriber_email,
+            status,
+            created_at,
+            updated_at
+        )
+        SELECT newsletter_issue_id, subscriber_email, 'pending', now(), now()
+        FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
     .execute(transaction)
     .await?;
     Ok(())
 }
 
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub(crate) async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
     content: &str,
+    tracking_enabled: bool,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     sqlx::query!(
@@ -55,13 +76,15 @@ async fn insert_newsletter_issue(
             newsletter_issue_id,
             title,
             content,
-            published_at
+            published_at,
+            tracking_enabled
         )
-        VALUES($1, $2, $3, now())
+        VALUES($1, $2, $3, now(), $4)
         "#,
         newsletter_issue_id,
         title,
-        content
+        content,
+        tracking_enabled
     )
     .execute(transaction)
     .await?;
@@ -70,11 +93,12 @@ async fn insert_newsletter_issue(
 
 #[tracing::instrument(
     name = "publish a newsletter issue",
-    skip(body, pool, user_id),
+    skip(body, pool, user_id, client_ip),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn publish_newsletter(
     user_id: ReqData<UserId>,
+    client_ip: ClientIp,
     body: web::Form<BodyData>,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
@@ -82,6 +106,7 @@ pub async fn publish_newsletter(
         title,
         content,
         idempotency_key,
+        tracking_enabled,
     } = body.into_inner();
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
     let user_id = *user_id.into_inner();
@@ -99,15 +124,29 @@ pub async fn publish_newsletter(
         }
     };
 
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &content)
-        .await
-        .context("failed to store newsletter issue details")
-        .map_err(e500)?;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &content,
+        tracking_enabled.is_some(),
+    )
+    .await
+    .context("failed to store newsletter issue details")
+    .map_err(e500)?;
 
     enqueue_delivery_tasks(&mut transaction, issue_id)
         .await
         .context("failed to enqueue delivery tasks")
         .map_err(e500)?;
+    record_audit_event(
+        Some(user_id),
+        client_ip.0,
+        AuditAction::IssuePublished,
+        serde_json::json!({ "newsletter_issue_id": issue_id, "title": title }),
+        &mut transaction,
+    )
+    .await
+    .map_err(e500)?;
     success_message.send();
     let response = see_other("/admin/newsletters");
     let response = save_response(&idempotency_key, user_id, response, transaction)
@@ -2,9 +2,13 @@ use actix_web::{http::header::ContentType, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
 
+use crate::{authentication::csrf_field, session_state::TypedSession, utils::e500};
+
 pub async fn change_password_form(
     flash_messages: IncomingFlashMessages,
+    session: TypedSession,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session).map_err(e500)?;
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
         writeln!(msg_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap();
@@ -22,6 +26,7 @@ pub async fn change_password_form(
         <body>
             {msg_html}
             <form action="/admin/password" method="post">
+                {csrf_field}
                 <label>Current password
                     <input type="password" placeholder="enter current password" name="current_password">
                 </label>
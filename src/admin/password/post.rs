@@ -3,12 +3,19 @@ use actix_web::{
     HttpResponse,
 };
 use actix_web_flash_messages::FlashMessage;
-use secrecy::{ExposeSecret, Secret};
+use anyhow::Context;
+use secrecy::Secret;
 use sqlx::PgPool;
 
 use crate::{
     admin::get_username,
-    authentication::{self, validate_credentials, AuthError, Credentials, UserId},
+    audit::{record_audit_event, AuditAction},
+    authentication::{
+        self, check_new_password, validate_credentials, AuthError, ClientIp, Credentials,
+        PasswordPolicy, UserId,
+    },
+    configuration::PasswordHashingSettings,
+    session_state::TypedSession,
     utils::{e500, see_other},
 };
 
@@ -22,39 +29,67 @@ pub struct FormData {
 pub async fn change_password(
     form: web::Form<FormData>,
     user_id: ReqData<UserId>,
+    session: TypedSession,
     pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashingSettings>,
+    password_policy: web::Data<PasswordPolicy>,
+    client_ip: ClientIp,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
-    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
-        FlashMessage::error(
-            "You entered two different new passwords - the field values must match.",
-        )
-        .send();
-        return Ok(see_other("/admin/password"));
-    }
-
-    if form.new_password.expose_secret().len() < 12 {
-        FlashMessage::error("The password must contain at least 12 characters.").send();
+    let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    if let Err(e) = check_new_password(
+        &form.new_password,
+        &form.new_password_check,
+        &username,
+        &password_policy,
+    ) {
+        FlashMessage::error(e).send();
         return Ok(see_other("/admin/password"));
     }
 
-    let username = get_username(*user_id, &pool).await.map_err(e500)?;
     let credentials = Credentials {
         username,
         password: form.0.current_password,
     };
-    if let Err(e) = validate_credentials(credentials, &pool).await {
+    if let Err(e) = validate_credentials(credentials, &hashing, &pool).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("The current password is incorrect.").send();
                 Ok(see_other("/admin/password"))
             }
-            AuthError::UnexpectedError(_) => Err(e500(e).into()),
+            AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
 
-    authentication::change_password(*user_id, form.0.new_password, &pool)
+    // the other sessions are logged out, this one has just proven to know the password
+    let session_id = session.get_session_id().map_err(e500)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    authentication::change_password(
+        *user_id,
+        form.0.new_password,
+        session_id,
+        &hashing,
+        &mut transaction,
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_event(
+        Some(*user_id),
+        client_ip.0,
+        AuditAction::PasswordChanged,
+        serde_json::json!({ "via": "settings" }),
+        &mut transaction,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
         .await
+        .context("failed to commit the password change")
         .map_err(e500)?;
     FlashMessage::error("Your password has been changed.").send();
     Ok(see_other("/admin/password"))
@@ -0,0 +1,98 @@
+use actix_web::{
+    http::header::ContentType,
+    web::{self, ReqData},
+    HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::{csrf_field, list_sessions, UserId},
+    configuration::SessionSettings,
+    session_state::TypedSession,
+    utils::e500,
+};
+
+pub async fn sessions_page(
+    user_id: ReqData<UserId>,
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    session_settings: web::Data<SessionSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session).map_err(e500)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap();
+    }
+
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    let sessions = list_sessions(*user_id.into_inner(), &session_settings, &pool)
+        .await
+        .map_err(e500)?;
+    let mut rows_html = String::new();
+    for s in sessions {
+        let action = if Some(s.session_id) == current_session_id {
+            "This session".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/revoke" method="post">
+                        {csrf_field}
+                        <input hidden type="text" name="session_id" value="{}">
+                        <button type="submit">Log out</button>
+                    </form>"#,
+                s.session_id
+            )
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{created_at}</td>
+                <td>{last_seen_at}</td>
+                <td>{ip_address}</td>
+                <td>{user_agent}</td>
+                <td>{action}</td>
+            </tr>"#,
+            created_at = s.created_at.format("%Y-%m-%d %H:%M"),
+            last_seen_at = s.last_seen_at.format("%Y-%m-%d %H:%M"),
+            ip_address = htmlescape::encode_minimal(s.ip_address.as_deref().unwrap_or("unknown")),
+            user_agent = htmlescape::encode_minimal(s.user_agent.as_deref().unwrap_or("unknown")),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Active Sessions</title>
+    </head>
+
+    <body>
+        {msg_html}
+        <p>You are logged in here:</p>
+        <table>
+            <tr>
+                <th>Logged in</th>
+                <th>Last seen</th>
+                <th>IP address</th>
+                <th>Browser</th>
+                <th></th>
+            </tr>
+            {rows_html}
+        </table>
+        <form action="/admin/sessions/revoke-others" method="post">
+            {csrf_field}
+            <button type="submit">Log out all other sessions</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+
+    </html>"#
+        )))
+}
@@ -0,0 +1,97 @@
+use actix_web::{
+    web::{self, ReqData},
+    HttpResponse,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditAction},
+    authentication::{revoke_session, revoke_sessions, ClientIp, UserId},
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct RevokeFormData {
+    session_id: Uuid,
+}
+
+#[tracing::instrument(name = "revoke a session", skip(form, pool, user_id, client_ip))]
+pub async fn revoke_user_session(
+    user_id: ReqData<UserId>,
+    form: web::Form<RevokeFormData>,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    // sessions of other users are not found, just like already revoked ones
+    if revoke_session(*user_id, form.session_id, &mut transaction)
+        .await
+        .map_err(e500)?
+    {
+        record_audit_event(
+            Some(*user_id),
+            client_ip.0,
+            AuditAction::SessionRevoked,
+            serde_json::json!({ "session_id": form.session_id }),
+            &mut transaction,
+        )
+        .await
+        .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("failed to commit revoking the session")
+            .map_err(e500)?;
+        FlashMessage::info("The session has been logged out.").send();
+    } else {
+        FlashMessage::error("The session does not exist anymore.").send();
+    }
+    Ok(see_other("/admin/sessions"))
+}
+
+#[tracing::instrument(
+    name = "revoke the other sessions",
+    skip(session, pool, user_id, client_ip)
+)]
+pub async fn revoke_other_sessions(
+    user_id: ReqData<UserId>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let session_id = session.get_session_id().map_err(e500)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    revoke_sessions(*user_id, session_id, &mut transaction)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        Some(*user_id),
+        client_ip.0,
+        AuditAction::OtherSessionsRevoked,
+        serde_json::json!({}),
+        &mut transaction,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit revoking the other sessions")
+        .map_err(e500)?;
+    FlashMessage::info("All other sessions have been logged out.").send();
+    Ok(see_other("/admin/sessions"))
+}
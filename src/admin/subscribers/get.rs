@@ -0,0 +1,104 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{authentication::csrf_field, session_state::TypedSession, utils::e500};
+
+pub async fn subscribers_page(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session).map_err(e500)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap();
+    }
+
+    let subscribers = list_subscribers(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for s in subscribers {
+        let (tracking, toggle_label, toggle_value) = if s.tracking_enabled {
+            ("enabled", "Disable tracking", "false")
+        } else {
+            ("disabled", "Enable tracking", "true")
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{email}</td>
+                <td>{name}</td>
+                <td>{status}</td>
+                <td>{tracking}</td>
+                <td>
+                    <form action="/admin/subscribers/tracking" method="post">
+                        {csrf_field}
+                        <input hidden type="text" name="subscriber_id" value="{id}">
+                        <input hidden type="text" name="tracking_enabled" value="{toggle_value}">
+                        <button type="submit">{toggle_label}</button>
+                    </form>
+                </td>
+            </tr>"#,
+            email = htmlescape::encode_minimal(&s.email),
+            name = htmlescape::encode_minimal(&s.name),
+            status = s.status,
+            id = s.id,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Subscribers</title>
+    </head>
+
+    <body>
+        {msg_html}
+        <table>
+            <tr>
+                <th>Email</th>
+                <th>Name</th>
+                <th>Status</th>
+                <th>Open/click tracking</th>
+                <th></th>
+            </tr>
+            {rows_html}
+        </table>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+
+    </html>"#
+        )))
+}
+
+struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    tracking_enabled: bool,
+}
+
+#[tracing::instrument(name = "list subscribers", skip(pool))]
+async fn list_subscribers(pool: &PgPool) -> Result<Vec<Subscriber>, anyhow::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, tracking_enabled
+        FROM subscriptions
+        ORDER BY subscribed_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to list the subscribers")
+}
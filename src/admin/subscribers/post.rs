@@ -0,0 +1,75 @@
+use actix_web::{
+    web::{self, ReqData},
+    HttpResponse,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditAction},
+    authentication::{ClientIp, UserId},
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct TrackingFormData {
+    subscriber_id: Uuid,
+    tracking_enabled: bool,
+}
+
+#[tracing::instrument(
+    name = "change subscriber tracking preference",
+    skip(form, pool, user_id, client_ip)
+)]
+pub async fn change_subscriber_tracking(
+    user_id: ReqData<UserId>,
+    client_ip: ClientIp,
+    form: web::Form<TrackingFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET tracking_enabled = $1
+        WHERE id = $2
+        "#,
+        form.tracking_enabled,
+        form.subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("failed to update the tracking preference of a subscriber")
+    .map_err(e500)?;
+    record_audit_event(
+        Some(**user_id),
+        client_ip.0,
+        AuditAction::SubscriberTrackingChanged,
+        serde_json::json!({
+            "subscriber_id": form.subscriber_id,
+            "tracking_enabled": form.tracking_enabled,
+        }),
+        &mut transaction,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit the tracking preference")
+        .map_err(e500)?;
+
+    let msg = if form.tracking_enabled {
+        "Open and click tracking has been enabled for the subscriber."
+    } else {
+        "Open and click tracking has been disabled for the subscriber."
+    };
+    FlashMessage::info(msg).send();
+    Ok(see_other("/admin/subscribers"))
+}
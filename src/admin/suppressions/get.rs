@@ -0,0 +1,108 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::csrf_field, session_state::TypedSession, suppression::list_suppressions,
+    utils::e500,
+};
+
+pub async fn suppressions_page(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session).map_err(e500)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap();
+    }
+
+    let suppressions = list_suppressions(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for s in suppressions {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{value}</td>
+                <td>{kind}</td>
+                <td>{reason}</td>
+                <td>{created_at}</td>
+                <td>
+                    <form action="/admin/suppressions/remove" method="post">
+                        {csrf_field}
+                        <input hidden type="text" name="suppression_id" value="{suppression_id}">
+                        <button type="submit">Remove</button>
+                    </form>
+                </td>
+            </tr>"#,
+            value = htmlescape::encode_minimal(&s.value),
+            kind = s.kind,
+            reason = s.reason,
+            created_at = s.created_at.format("%Y-%m-%d %H:%M"),
+            suppression_id = s.suppression_id,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Suppression List</title>
+    </head>
+
+    <body>
+        {msg_html}
+        <p>Addresses and domains on this list never receive any mail.</p>
+
+        <form action="/admin/suppressions" method="post">
+            {csrf_field}
+            <label>Address or domain
+                <input type="text" placeholder="user@example.com or example.com" name="entry">
+            </label>
+            {reason_select}
+            <button type="submit">Add</button>
+        </form>
+
+        <form action="/admin/suppressions/import" method="post">
+            {csrf_field}
+            <label>Import (one address or domain per line)
+                <br>
+                <textarea name="entries" rows="10" cols="50"></textarea>
+            </label>
+            <br>
+            {reason_select}
+            <button type="submit">Import</button>
+        </form>
+
+        <table>
+            <tr>
+                <th>Entry</th>
+                <th>Kind</th>
+                <th>Reason</th>
+                <th>Added</th>
+                <th></th>
+            </tr>
+            {rows_html}
+        </table>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+
+    </html>"#,
+            reason_select = REASON_SELECT,
+        )))
+}
+
+const REASON_SELECT: &str = r#"<label>Reason
+                <select name="reason">
+                    <option value="manual">Manual</option>
+                    <option value="bounce">Bounce</option>
+                    <option value="legal">Legal request</option>
+                </select>
+            </label>"#;
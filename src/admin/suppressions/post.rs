@@ -0,0 +1,219 @@
+use actix_web::{
+    web::{self, ReqData},
+    HttpResponse,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditAction},
+    authentication::{ClientIp, UserId},
+    domain::suppression_entry::{SuppressionEntry, SuppressionReason},
+    suppression::{add_suppression, remove_suppression},
+    utils::{e400, e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct AddFormData {
+    entry: String,
+    reason: String,
+}
+
+#[tracing::instrument(name = "add a suppression entry", skip(form, pool, user_id, client_ip))]
+pub async fn add_suppression_entry(
+    user_id: ReqData<UserId>,
+    client_ip: ClientIp,
+    form: web::Form<AddFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let AddFormData { entry, reason } = form.into_inner();
+    let reason: SuppressionReason = reason.try_into().map_err(e400)?;
+    let entry = match SuppressionEntry::parse(&entry) {
+        Ok(entry) => entry,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/suppressions"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    let inserted = add_suppression(&mut transaction, &entry, reason)
+        .await
+        .map_err(e500)?;
+    if inserted {
+        record_audit_event(
+            Some(**user_id),
+            client_ip.0,
+            AuditAction::SuppressionAdded,
+            serde_json::json!({ "entry": entry.to_string(), "reason": reason.as_str() }),
+            &mut transaction,
+        )
+        .await
+        .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("failed to commit the suppression entry")
+            .map_err(e500)?;
+    }
+    let msg = if inserted {
+        format!("'{entry}' has been added to the suppression list.")
+    } else {
+        format!("'{entry}' is already on the suppression list.")
+    };
+    FlashMessage::info(htmlescape::encode_minimal(&msg)).send();
+    Ok(see_other("/admin/suppressions"))
+}
+
+/// Flash messages are stored in a cookie, so only the first few invalid lines of an import are
+/// reported - and only their beginning.
+const MAX_REPORTED_INVALID_LINES: usize = 5;
+const MAX_REPORTED_LINE_LENGTH: usize = 50;
+
+#[derive(serde::Deserialize)]
+pub struct ImportFormData {
+    entries: String,
+    reason: String,
+}
+
+#[tracing::instrument(
+    name = "import suppression entries",
+    skip(form, pool, user_id, client_ip)
+)]
+pub async fn import_suppression_entries(
+    user_id: ReqData<UserId>,
+    client_ip: ClientIp,
+    form: web::Form<ImportFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ImportFormData { entries, reason } = form.into_inner();
+    let reason: SuppressionReason = reason.try_into().map_err(e400)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    let mut n_added = 0;
+    let mut n_present = 0;
+    let mut invalid = Vec::new();
+    for line in entries.lines().filter(|l| !l.trim().is_empty()) {
+        match SuppressionEntry::parse(line) {
+            Ok(entry) => {
+                if add_suppression(&mut transaction, &entry, reason)
+                    .await
+                    .map_err(e500)?
+                {
+                    n_added += 1;
+                } else {
+                    n_present += 1;
+                }
+            }
+            Err(_) => invalid.push(line.trim()),
+        }
+    }
+
+    record_audit_event(
+        Some(**user_id),
+        client_ip.0,
+        AuditAction::SuppressionsImported,
+        serde_json::json!({
+            "reason": reason.as_str(),
+            "n_added": n_added,
+            "n_present": n_present,
+            "n_invalid": invalid.len(),
+        }),
+        &mut transaction,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit the imported suppression entries")
+        .map_err(e500)?;
+    FlashMessage::info(format!(
+        "Imported {n_added} entries ({n_present} were already on the suppression list)."
+    ))
+    .send();
+    if !invalid.is_empty() {
+        FlashMessage::error(htmlescape::encode_minimal(&describe_invalid_lines(
+            &invalid,
+        )))
+        .send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
+
+fn describe_invalid_lines(invalid: &[&str]) -> String {
+    let reported: Vec<String> = invalid
+        .iter()
+        .take(MAX_REPORTED_INVALID_LINES)
+        .map(
+            |line| match line.char_indices().nth(MAX_REPORTED_LINE_LENGTH) {
+                Some((end, _)) => format!("{}...", &line[..end]),
+                None => line.to_string(),
+            },
+        )
+        .collect();
+    let mut description = format!(
+        "Skipped {} invalid lines: {}",
+        invalid.len(),
+        reported.join(", ")
+    );
+    if invalid.len() > reported.len() {
+        description.push_str(&format!(" and {} more", invalid.len() - reported.len()));
+    }
+    description
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveFormData {
+    suppression_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "remove a suppression entry",
+    skip(form, pool, user_id, client_ip)
+)]
+pub async fn remove_suppression_entry(
+    user_id: ReqData<UserId>,
+    client_ip: ClientIp,
+    form: web::Form<RemoveFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    let removed = remove_suppression(&mut transaction, form.suppression_id)
+        .await
+        .map_err(e500)?;
+    if let Some(entry) = removed {
+        record_audit_event(
+            Some(**user_id),
+            client_ip.0,
+            AuditAction::SuppressionRemoved,
+            serde_json::json!({ "entry": entry }),
+            &mut transaction,
+        )
+        .await
+        .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("failed to commit the removal of the suppression entry")
+            .map_err(e500)?;
+        FlashMessage::info("The entry has been removed from the suppression list.").send();
+    } else {
+        FlashMessage::error("The entry is not on the suppression list anymore.").send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
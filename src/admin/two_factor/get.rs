@@ -0,0 +1,95 @@
+use actix_web::{
+    http::header::ContentType,
+    web::{self, ReqData},
+    HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    admin::get_username,
+    authentication::{
+        count_unused_recovery_codes, csrf_field, generate_totp_secret, is_two_factor_enabled,
+        qr_code_svg, totp_setup_url, UserId,
+    },
+    session_state::TypedSession,
+    utils::e500,
+};
+
+pub async fn two_factor_page(
+    user_id: ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session).map_err(e500)?;
+    let user_id = user_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap();
+    }
+
+    let body_html = if is_two_factor_enabled(*user_id, &pool).await.map_err(e500)? {
+        let n_recovery_codes = count_unused_recovery_codes(*user_id, &pool)
+            .await
+            .map_err(e500)?;
+        format!(
+            r#"<p>Two-factor authentication is enabled. You have {n_recovery_codes} unused recovery codes left.</p>
+        <form action="/admin/two-factor/disable" method="post">
+            {csrf_field}
+            <label>Current password
+                <input type="password" placeholder="enter current password" name="current_password">
+            </label>
+            <button type="submit">Disable two-factor authentication</button>
+        </form>"#
+        )
+    } else {
+        // the secret only gets stored for the user once a code generated from it is confirmed
+        let secret = match session.get_pending_totp_secret().map_err(e500)? {
+            Some(secret) => secret,
+            None => {
+                let secret = generate_totp_secret();
+                session.insert_pending_totp_secret(&secret).map_err(e500)?;
+                secret
+            }
+        };
+        let username = get_username(*user_id, &pool).await.map_err(e500)?;
+        let setup_url = totp_setup_url(&secret, &username).map_err(e500)?;
+        let qr_code = qr_code_svg(&setup_url).map_err(e500)?;
+        format!(
+            r#"<p>Scan the QR code with your authenticator app, or enter the secret manually:</p>
+        {qr_code}
+        <p><code>{secret}</code></p>
+        <form action="/admin/two-factor/enable" method="post">
+            {csrf_field}
+            <label>Code shown by the app
+                <input type="text" autocomplete="one-time-code" name="code">
+            </label>
+            <button type="submit">Enable two-factor authentication</button>
+        </form>"#,
+            secret = secret.expose_secret(),
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Two-Factor Authentication</title>
+    </head>
+
+    <body>
+        {msg_html}
+        {body_html}
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+
+    </html>"#
+        )))
+}
@@ -0,0 +1,158 @@
+use actix_web::{
+    http::header::ContentType,
+    web::{self, ReqData},
+    HttpResponse,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    admin::get_username,
+    audit::{record_audit_event, AuditAction},
+    authentication::{
+        disable_two_factor, enable_two_factor, validate_credentials, verify_totp_code, AuthError,
+        ClientIp, Credentials, UserId,
+    },
+    configuration::PasswordHashingSettings,
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct EnableFormData {
+    code: String,
+}
+
+/// Responds with the recovery codes right away, as they cannot be shown again.
+#[tracing::instrument(name = "enable two-factor authentication", skip_all)]
+pub async fn enable_two_factor_authentication(
+    user_id: ReqData<UserId>,
+    form: web::Form<EnableFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(secret) = session.get_pending_totp_secret().map_err(e500)? else {
+        FlashMessage::error("Please scan the QR code again.").send();
+        return Ok(see_other("/admin/two-factor"));
+    };
+    if verify_totp_code(&secret, &form.code)
+        .map_err(e500)?
+        .is_none()
+    {
+        FlashMessage::error("The code is incorrect - please check the time of your device.").send();
+        return Ok(see_other("/admin/two-factor"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    let recovery_codes = enable_two_factor(**user_id, &secret, &mut transaction)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        Some(**user_id),
+        client_ip.0,
+        AuditAction::TwoFactorEnabled,
+        serde_json::json!({}),
+        &mut transaction,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit enabling two-factor authentication")
+        .map_err(e500)?;
+    session.remove_pending_totp_secret();
+
+    let mut codes_html = String::new();
+    for code in recovery_codes {
+        writeln!(codes_html, "<li><code>{code}</code></li>").unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Recovery Codes</title>
+    </head>
+
+    <body>
+        <p>Two-factor authentication has been enabled.</p>
+        <p>Store these recovery codes somewhere safe. Each of them can be used once to log in
+        without your authenticator app, and they will not be shown again:</p>
+        <ul>
+            {codes_html}
+        </ul>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+
+    </html>"#
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct DisableFormData {
+    current_password: Secret<String>,
+}
+
+#[tracing::instrument(name = "disable two-factor authentication", skip_all)]
+pub async fn disable_two_factor_authentication(
+    user_id: ReqData<UserId>,
+    form: web::Form<DisableFormData>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashingSettings>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let credentials = Credentials {
+        username,
+        password: form.into_inner().current_password,
+    };
+    if let Err(e) = validate_credentials(credentials, &hashing, &pool).await {
+        return match e {
+            AuthError::InvalidCredentials(_) => {
+                FlashMessage::error("The current password is incorrect.").send();
+                Ok(see_other("/admin/two-factor"))
+            }
+            AuthError::UnexpectedError(_) => Err(e500(e)),
+        };
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    if disable_two_factor(*user_id, &mut transaction)
+        .await
+        .map_err(e500)?
+    {
+        record_audit_event(
+            Some(*user_id),
+            client_ip.0,
+            AuditAction::TwoFactorDisabled,
+            serde_json::json!({}),
+            &mut transaction,
+        )
+        .await
+        .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("failed to commit disabling two-factor authentication")
+            .map_err(e500)?;
+    }
+    FlashMessage::info("Two-factor authentication has been disabled.").send();
+    Ok(see_other("/admin/two-factor"))
+}
@@ -0,0 +1,127 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    authentication::{csrf_field, list_pending_invites, list_users, Role},
+    session_state::TypedSession,
+    utils::e500,
+};
+
+pub async fn users_page(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session).map_err(e500)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap();
+    }
+
+    let users = list_users(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for user in users {
+        let username = htmlescape::encode_minimal(&user.username);
+        let mut role_options = String::new();
+        for role in [Role::Owner, Role::Editor, Role::Viewer] {
+            let selected = if role == user.role { " selected" } else { "" };
+            write!(
+                role_options,
+                r#"<option value="{role}"{selected}>{role}</option>"#
+            )
+            .unwrap();
+        }
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{username}</td>
+                <td>
+                    <form action="/admin/users/role" method="post">
+                        {csrf_field}
+                        <input hidden type="text" name="username" value="{username}">
+                        <select name="role">{role_options}</select>
+                        <button type="submit">Change role</button>
+                    </form>
+                </td>
+                <td>
+                    <form action="/admin/users/delete" method="post">
+                        {csrf_field}
+                        <input hidden type="text" name="username" value="{username}">
+                        <button type="submit">Delete</button>
+                    </form>
+                </td>
+            </tr>"#,
+        )
+        .unwrap();
+    }
+
+    let invites = list_pending_invites(&pool).await.map_err(e500)?;
+    let mut invites_html = String::new();
+    for invite in invites {
+        writeln!(
+            invites_html,
+            r#"<tr>
+                <td>{email}</td>
+                <td>{role}</td>
+                <td>{expires_at}</td>
+            </tr>"#,
+            email = htmlescape::encode_minimal(&invite.email),
+            role = invite.role,
+            expires_at = invite.expires_at.format("%Y-%m-%d %H:%M"),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Admin Users</title>
+    </head>
+
+    <body>
+        {msg_html}
+        <table>
+            <tr>
+                <th>Username</th>
+                <th>Role</th>
+                <th></th>
+            </tr>
+            {rows_html}
+        </table>
+
+        <p>Invite a teammate:</p>
+        <form action="/admin/users/invite" method="post">
+            {csrf_field}
+            <label>Email
+                <input type="text" placeholder="enter their email" name="email">
+            </label>
+            <select name="role">
+                <option value="viewer">viewer</option>
+                <option value="editor">editor</option>
+                <option value="owner">owner</option>
+            </select>
+            <button type="submit">Send invite</button>
+        </form>
+
+        <p>Pending invites:</p>
+        <table>
+            <tr>
+                <th>Email</th>
+                <th>Role</th>
+                <th>Expires</th>
+            </tr>
+            {invites_html}
+        </table>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+
+    </html>"#
+        )))
+}
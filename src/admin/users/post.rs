@@ -0,0 +1,241 @@
+use actix_web::{
+    web::{self, ReqData},
+    HttpResponse,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    admin::get_username,
+    audit::{record_audit_event, AuditAction},
+    authentication::{
+        create_invite, delete_user, set_role, ClientIp, InviteError, Role, UserError, UserId,
+    },
+    domain::subscriber_email::SubscriberEmail,
+    email_client::EmailClient,
+    startup::ApplicationBaseUrl,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct RoleFormData {
+    username: String,
+    role: String,
+}
+
+#[tracing::instrument(
+    name = "change the role of a user",
+    skip(form, pool, user_id, client_ip)
+)]
+pub async fn change_user_role(
+    user_id: ReqData<UserId>,
+    form: web::Form<RoleFormData>,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let RoleFormData { username, role } = form.into_inner();
+    let role: Role = match role.parse() {
+        Ok(role) => role,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    // owners could otherwise lock everybody out of managing the users
+    if is_current_user(&username, &user_id, &pool).await? {
+        FlashMessage::error("You cannot change your own role.").send();
+        return Ok(see_other("/admin/users"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    match set_role(&username, role, &mut transaction).await {
+        Ok(()) => {
+            record_audit_event(
+                Some(**user_id),
+                client_ip.0,
+                AuditAction::UserRoleChanged,
+                serde_json::json!({ "username": username, "role": role.as_str() }),
+                &mut transaction,
+            )
+            .await
+            .map_err(e500)?;
+            transaction
+                .commit()
+                .await
+                .context("failed to commit the role change")
+                .map_err(e500)?;
+            FlashMessage::info(format!(
+                "The role of {} has been changed to {role}.",
+                htmlescape::encode_minimal(&username)
+            ))
+            .send()
+        }
+        Err(e @ (UserError::UnknownUser(_) | UserError::LastOwner(_))) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e.to_string())).send()
+        }
+        Err(e) => return Err(e500(e)),
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeleteFormData {
+    username: String,
+}
+
+#[tracing::instrument(name = "delete a user", skip(form, pool, user_id, client_ip))]
+pub async fn remove_user(
+    user_id: ReqData<UserId>,
+    form: web::Form<DeleteFormData>,
+    pool: web::Data<PgPool>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let username = form.into_inner().username;
+    if is_current_user(&username, &user_id, &pool).await? {
+        FlashMessage::error("You cannot delete yourself.").send();
+        return Ok(see_other("/admin/users"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    match delete_user(&username, &mut transaction).await {
+        Ok(()) => {
+            record_audit_event(
+                Some(**user_id),
+                client_ip.0,
+                AuditAction::UserDeleted,
+                serde_json::json!({ "username": username }),
+                &mut transaction,
+            )
+            .await
+            .map_err(e500)?;
+            transaction
+                .commit()
+                .await
+                .context("failed to commit the deletion of the user")
+                .map_err(e500)?;
+            FlashMessage::info(format!(
+                "{} has been deleted.",
+                htmlescape::encode_minimal(&username)
+            ))
+            .send()
+        }
+        Err(e @ (UserError::UnknownUser(_) | UserError::LastOwner(_))) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e.to_string())).send()
+        }
+        Err(e) => return Err(e500(e)),
+    }
+    Ok(see_other("/admin/users"))
+}
+
+async fn is_current_user(
+    username: &str,
+    user_id: &UserId,
+    pool: &PgPool,
+) -> Result<bool, actix_web::Error> {
+    let current_username = get_username(**user_id, pool).await.map_err(e500)?;
+    Ok(current_username == username)
+}
+
+#[derive(serde::Deserialize)]
+pub struct InviteFormData {
+    email: String,
+    role: String,
+}
+
+#[tracing::instrument(
+    name = "invite a user",
+    skip(form, pool, email_client, base_url, user_id, client_ip)
+)]
+pub async fn invite_user(
+    user_id: ReqData<UserId>,
+    client_ip: ClientIp,
+    form: web::Form<InviteFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let InviteFormData { email, role } = form.into_inner();
+    let role: Role = match role.parse() {
+        Ok(role) => role,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    let email = match SubscriberEmail::parse(email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    let invite_token = match create_invite(&email, role, **user_id, &mut transaction).await {
+        Ok(invite_token) => invite_token,
+        Err(e @ InviteError::EmailTaken(_)) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e.to_string())).send();
+            return Ok(see_other("/admin/users"));
+        }
+        Err(e) => return Err(e500(e)),
+    };
+    record_audit_event(
+        Some(**user_id),
+        client_ip.0,
+        AuditAction::UserInvited,
+        serde_json::json!({ "email": email.as_ref(), "role": role.as_str() }),
+        &mut transaction,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit the invite")
+        .map_err(e500)?;
+    send_invite_email(&email_client, &email, role, &base_url.0, &invite_token)
+        .await
+        .context("failed to send the invite email")
+        .map_err(e500)?;
+    FlashMessage::info(format!(
+        "An invite has been sent to {}.",
+        htmlescape::encode_minimal(email.as_ref())
+    ))
+    .send();
+    Ok(see_other("/admin/users"))
+}
+
+async fn send_invite_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    role: Role,
+    base_url: &str,
+    invite_token: &str,
+) -> Result<(), reqwest::Error> {
+    let invite_link = format!("{base_url}/invites/accept?invite_token={invite_token}");
+    let html_body = format!(
+        "You have been invited to manage the newsletter as {role}. \
+        <a href=\"{invite_link}\">Accept the invite</a> to choose your username and password."
+    );
+    let plain_body = format!(
+        "You have been invited to manage the newsletter as {role}. \
+        Accept the invite to choose your username and password: {invite_link}"
+    );
+    email_client
+        .send_email(email, "You have been invited", &html_body, &plain_body)
+        .await
+        .map(|_| ())
+}
@@ -0,0 +1,7 @@
+mod error;
+mod issues;
+mod middleware;
+
+pub use error::*;
+pub use issues::*;
+pub use middleware::*;
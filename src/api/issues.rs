@@ -0,0 +1,7 @@
+mod get;
+mod post;
+mod put;
+
+pub use get::*;
+pub use post::*;
+pub use put::*;
@@ -0,0 +1,196 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::api::ApiError;
+
+/// Drafts and published issues share one representation, told apart by their `status`.
+#[derive(serde::Serialize)]
+pub struct IssueResponse {
+    pub issue_id: Uuid,
+    pub status: &'static str,
+    pub title: String,
+    pub content: String,
+    pub tracking_enabled: bool,
+    /// Only set for drafts.
+    pub created_at: Option<String>,
+    /// Only set for published issues.
+    pub published_at: Option<String>,
+    /// Only set for published issues.
+    pub delivery: Option<DeliveryResponse>,
+}
+
+#[derive(serde::Serialize)]
+pub struct DeliveryResponse {
+    pub state: String,
+    pub n_pending: i64,
+    pub n_sent: i64,
+    pub n_failed: i64,
+    pub n_skipped: i64,
+    pub n_cancelled: i64,
+}
+
+#[derive(serde::Serialize)]
+struct IssueListResponse {
+    issues: Vec<IssueResponse>,
+}
+
+/// Lists the drafts, the newest first, followed by the published issues.
+#[tracing::instrument(name = "list issues via the api", skip(pool))]
+pub async fn list_issues_api(pool: web::Data<PgPool>) -> Result<HttpResponse, ApiError> {
+    let mut issues = list_drafts(&pool).await?;
+    issues.extend(fetch_published_issues(None, pool.as_ref()).await?);
+    Ok(HttpResponse::Ok().json(IssueListResponse { issues }))
+}
+
+#[tracing::instrument(name = "get an issue via the api", skip(pool))]
+pub async fn get_issue_api(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let issue_id = issue_id.into_inner();
+    let issue = match get_draft(issue_id, pool.as_ref()).await? {
+        Some(draft) => draft,
+        None => get_published_issue(issue_id, pool.as_ref())
+            .await?
+            .ok_or_else(|| ApiError::NotFound(format!("there is no issue {issue_id}")))?,
+    };
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+pub(crate) fn draft_response(
+    draft_id: Uuid,
+    title: String,
+    content: String,
+    tracking_enabled: bool,
+    created_at: DateTime<Utc>,
+) -> IssueResponse {
+    IssueResponse {
+        issue_id: draft_id,
+        status: "draft",
+        title,
+        content,
+        tracking_enabled,
+        created_at: Some(created_at.to_rfc3339()),
+        published_at: None,
+        delivery: None,
+    }
+}
+
+#[tracing::instrument(name = "get draft", skip(executor))]
+pub(crate) async fn get_draft(
+    draft_id: Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<Option<IssueResponse>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT draft_id, title, content, tracking_enabled, created_at
+        FROM newsletter_drafts
+        WHERE draft_id = $1
+        "#,
+        draft_id
+    )
+    .fetch_optional(executor)
+    .await
+    .context("failed to retrieve the draft")?;
+    Ok(row.map(|r| {
+        draft_response(
+            r.draft_id,
+            r.title,
+            r.content,
+            r.tracking_enabled,
+            r.created_at,
+        )
+    }))
+}
+
+#[tracing::instrument(name = "list drafts", skip(pool))]
+async fn list_drafts(pool: &PgPool) -> Result<Vec<IssueResponse>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT draft_id, title, content, tracking_enabled, created_at
+        FROM newsletter_drafts
+        ORDER BY created_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to list the drafts")?;
+    Ok(rows
+        .into_iter()
+        .map(|r| {
+            draft_response(
+                r.draft_id,
+                r.title,
+                r.content,
+                r.tracking_enabled,
+                r.created_at,
+            )
+        })
+        .collect())
+}
+
+/// Takes an executor, so that a transaction can read the issue it has just published.
+pub(crate) async fn get_published_issue(
+    issue_id: Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<Option<IssueResponse>, anyhow::Error> {
+    Ok(fetch_published_issues(Some(issue_id), executor)
+        .await?
+        .pop())
+}
+
+/// Returns either the issue with the given id or all of them, the newest first.
+#[tracing::instrument(name = "fetch published issues", skip(executor))]
+async fn fetch_published_issues(
+    issue_id: Option<Uuid>,
+    executor: impl PgExecutor<'_>,
+) -> Result<Vec<IssueResponse>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.content,
+            i.tracking_enabled,
+            i.published_at,
+            i.delivery_state,
+            COUNT(d.subscriber_email) FILTER (WHERE d.status = 'pending') AS "n_pending!",
+            COUNT(d.subscriber_email) FILTER (WHERE d.status = 'sent') AS "n_sent!",
+            COUNT(d.subscriber_email) FILTER (WHERE d.status = 'failed') AS "n_failed!",
+            COUNT(d.subscriber_email) FILTER (WHERE d.status = 'skipped') AS "n_skipped!",
+            COUNT(d.subscriber_email) FILTER (WHERE d.status = 'cancelled') AS "n_cancelled!"
+        FROM newsletter_issues i
+        LEFT JOIN issue_deliveries d USING (newsletter_issue_id)
+        WHERE $1::uuid IS NULL OR i.newsletter_issue_id = $1
+        GROUP BY i.newsletter_issue_id
+        ORDER BY i.published_at DESC
+        "#,
+        issue_id
+    )
+    .fetch_all(executor)
+    .await
+    .context("failed to retrieve the newsletter issues")?;
+    Ok(rows
+        .into_iter()
+        .map(|r| IssueResponse {
+            issue_id: r.newsletter_issue_id,
+            status: "published",
+            title: r.title,
+            content: r.content,
+            tracking_enabled: r.tracking_enabled,
+            created_at: None,
+            published_at: Some(r.published_at),
+            delivery: Some(DeliveryResponse {
+                state: r.delivery_state,
+                n_pending: r.n_pending,
+                n_sent: r.n_sent,
+                n_failed: r.n_failed,
+                n_skipped: r.n_skipped,
+                n_cancelled: r.n_cancelled,
+            }),
+        })
+        .collect())
+}
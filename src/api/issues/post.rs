@@ -0,0 +1,173 @@
+use actix_web::{
+    http::header::LOCATION,
+    web::{self, ReqData},
+    HttpRequest, HttpResponse,
+};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    admin::{enqueue_delivery_tasks, insert_newsletter_issue},
+    api::{draft_response, get_published_issue, ApiError},
+    audit::{record_audit_event, AuditAction},
+    authentication::{ClientIp, UserId},
+    idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+};
+
+#[derive(serde::Deserialize)]
+pub struct DraftBody {
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub tracking_enabled: bool,
+}
+
+impl DraftBody {
+    pub(crate) fn validate(&self) -> Result<(), ApiError> {
+        if self.title.trim().is_empty() || self.content.trim().is_empty() {
+            return Err(ApiError::ValidationError(
+                "the title and the content must not be empty".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Creating and publishing are not idempotent by themselves, so the client has to send an
+/// `Idempotency-Key` header to be able to retry them safely.
+fn idempotency_key(request: &HttpRequest) -> Result<IdempotencyKey, ApiError> {
+    let key = request
+        .headers()
+        .get("Idempotency-Key")
+        .ok_or_else(|| ApiError::ValidationError("the Idempotency-Key header is missing".into()))?
+        .to_str()
+        .map_err(|_| ApiError::ValidationError("the Idempotency-Key header is not ASCII".into()))?;
+    key.to_owned()
+        .try_into()
+        .map_err(|e: anyhow::Error| ApiError::ValidationError(e.to_string()))
+}
+
+#[tracing::instrument(
+    name = "create a draft via the api",
+    skip(request, body, pool, user_id)
+)]
+pub async fn create_draft_api(
+    request: HttpRequest,
+    user_id: ReqData<UserId>,
+    body: web::Json<DraftBody>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let idempotency_key = idempotency_key(&request)?;
+    body.validate()?;
+    let DraftBody {
+        title,
+        content,
+        tracking_enabled,
+    } = body.into_inner();
+    let user_id = **user_id;
+
+    let mut transaction = match try_processing(&pool, &idempotency_key, user_id).await? {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+    };
+    let draft_id = Uuid::new_v4();
+    let draft = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_drafts (
+            draft_id,
+            title,
+            content,
+            tracking_enabled,
+            author_id,
+            created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        RETURNING created_at
+        "#,
+        draft_id,
+        title,
+        content,
+        tracking_enabled,
+        user_id
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("failed to store the draft")?;
+    let draft = draft_response(draft_id, title, content, tracking_enabled, draft.created_at);
+
+    let response = HttpResponse::Created()
+        .insert_header((LOCATION, format!("/api/v1/issues/{}", draft.issue_id)))
+        .json(draft);
+    let response = save_response(&idempotency_key, user_id, response, transaction).await?;
+    Ok(response)
+}
+
+/// The draft is consumed by publishing it, the published issue gets an id of its own.
+#[tracing::instrument(
+    name = "publish a draft via the api",
+    skip(request, pool, user_id, client_ip)
+)]
+pub async fn publish_issue_api(
+    request: HttpRequest,
+    user_id: ReqData<UserId>,
+    client_ip: ClientIp,
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let idempotency_key = idempotency_key(&request)?;
+    let draft_id = draft_id.into_inner();
+    let user_id = **user_id;
+
+    let mut transaction = match try_processing(&pool, &idempotency_key, user_id).await? {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+    };
+    // dropping the transaction on an error releases the idempotency key again
+    let draft = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_drafts
+        WHERE draft_id = $1
+        RETURNING title, content, tracking_enabled
+        "#,
+        draft_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("failed to retrieve the draft")?
+    .ok_or_else(|| ApiError::NotFound(format!("there is no draft {draft_id}")))?;
+
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &draft.title,
+        &draft.content,
+        draft.tracking_enabled,
+    )
+    .await
+    .context("failed to store newsletter issue details")?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("failed to enqueue delivery tasks")?;
+    record_audit_event(
+        Some(user_id),
+        client_ip.0,
+        AuditAction::IssuePublished,
+        serde_json::json!({
+            "newsletter_issue_id": issue_id,
+            "title": draft.title,
+            "draft_id": draft_id,
+            "via": "api",
+        }),
+        &mut transaction,
+    )
+    .await?;
+    let issue = get_published_issue(issue_id, &mut transaction)
+        .await?
+        .context("the issue has not been stored")?;
+
+    let response = HttpResponse::Created()
+        .insert_header((LOCATION, format!("/api/v1/issues/{issue_id}")))
+        .json(issue);
+    let response = save_response(&idempotency_key, user_id, response, transaction).await?;
+    Ok(response)
+}
@@ -0,0 +1,56 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api::{draft_response, get_published_issue, ApiError, DraftBody};
+
+/// Replaces the title, content and tracking preference of a draft. Published issues cannot be
+/// changed anymore.
+#[tracing::instrument(name = "update a draft via the api", skip(body, pool))]
+pub async fn update_draft_api(
+    draft_id: web::Path<Uuid>,
+    body: web::Json<DraftBody>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    body.validate()?;
+    let draft_id = draft_id.into_inner();
+    let DraftBody {
+        title,
+        content,
+        tracking_enabled,
+    } = body.into_inner();
+
+    let draft = sqlx::query!(
+        r#"
+        UPDATE newsletter_drafts
+        SET title = $2, content = $3, tracking_enabled = $4
+        WHERE draft_id = $1
+        RETURNING created_at
+        "#,
+        draft_id,
+        title,
+        content,
+        tracking_enabled
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("failed to update the draft")?;
+    let Some(draft) = draft else {
+        let is_published = get_published_issue(draft_id, pool.as_ref())
+            .await?
+            .is_some();
+        return Err(if is_published {
+            ApiError::Conflict(format!("the issue {draft_id} has already been published"))
+        } else {
+            ApiError::NotFound(format!("there is no draft {draft_id}"))
+        });
+    };
+    Ok(HttpResponse::Ok().json(draft_response(
+        draft_id,
+        title,
+        content,
+        tracking_enabled,
+        draft.created_at,
+    )))
+}
@@ -0,0 +1,84 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::JsonPayloadError,
+    web, HttpMessage, HttpRequest,
+};
+use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    api::ApiError,
+    authentication::{authenticate_bearer_token, get_role, ApiTokenRejection, Role, UserId},
+};
+
+/// The JSON api is only meant for scripts, so it does not fall back to the session: every
+/// request has to carry an `Authorization: Bearer` header with a valid API token.
+pub async fn require_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let user_id = authenticate_bearer_token(&req)
+        .await
+        .map_err(|e| match e {
+            ApiTokenRejection::UnexpectedError(e) => ApiError::UnexpectedError(e),
+            ApiTokenRejection::MissingScope(_) => ApiError::Forbidden(e.to_string()),
+            _ => ApiError::Unauthorized(e.to_string()),
+        })?
+        .ok_or_else(|| ApiError::Unauthorized("the request carries no api token".into()))?;
+
+    req.extensions_mut().insert(user_id);
+    next.call(req).await
+}
+
+/// Has to be layered inside of `require_api_tokens`, which provides the id of the user.
+pub async fn reject_api_viewers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_api_role(req, next, Role::Editor).await
+}
+
+/// Has to be layered inside of `require_api_tokens`, which provides the id of the user.
+pub async fn reject_api_non_owners(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_api_role(req, next, Role::Owner).await
+}
+
+async fn require_api_role(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+    required_role: Role,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let user_id = req
+        .extensions()
+        .get::<UserId>()
+        .copied()
+        .context("the id of the user is missing from the request")
+        .map_err(ApiError::from)?;
+    let role = get_role(*user_id, database_pool(&req)?)
+        .await
+        .map_err(ApiError::from)?;
+    match role {
+        Some(role) if role >= required_role => next.call(req).await,
+        Some(role) => Err(ApiError::Forbidden(format!(
+            "a user with the role {role} needs to be at least {required_role}"
+        ))
+        .into()),
+        None => Err(ApiError::Unauthorized("the user does not exist anymore".into()).into()),
+    }
+}
+
+fn database_pool(req: &ServiceRequest) -> Result<&web::Data<PgPool>, ApiError> {
+    req.app_data::<web::Data<PgPool>>()
+        .context("the database pool is missing from the application data")
+        .map_err(ApiError::from)
+}
+
+/// Reports bodies which cannot be deserialized in the same format as the other errors.
+pub fn json_error_handler(e: JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::ValidationError(e.to_string()).into()
+}
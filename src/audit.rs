@@ -0,0 +1,200 @@
+use std::{fmt::Display, net::IpAddr};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// The actions that end up in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Login,
+    LoginFailed,
+    LoginLockedOut,
+    Logout,
+    SessionRevoked,
+    OtherSessionsRevoked,
+    PasswordChanged,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    IssuePublished,
+    IssueDeliveryPaused,
+    IssueDeliveryResumed,
+    IssueDeliveryCancelled,
+    SubscriberTrackingChanged,
+    SuppressionAdded,
+    SuppressionsImported,
+    SuppressionRemoved,
+    UserRoleChanged,
+    UserDeleted,
+    UserInvited,
+    ApiTokenCreated,
+    ApiTokenRevoked,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 22] = [
+        AuditAction::Login,
+        AuditAction::LoginFailed,
+        AuditAction::LoginLockedOut,
+        AuditAction::Logout,
+        AuditAction::SessionRevoked,
+        AuditAction::OtherSessionsRevoked,
+        AuditAction::PasswordChanged,
+        AuditAction::TwoFactorEnabled,
+        AuditAction::TwoFactorDisabled,
+        AuditAction::IssuePublished,
+        AuditAction::IssueDeliveryPaused,
+        AuditAction::IssueDeliveryResumed,
+        AuditAction::IssueDeliveryCancelled,
+        AuditAction::SubscriberTrackingChanged,
+        AuditAction::SuppressionAdded,
+        AuditAction::SuppressionsImported,
+        AuditAction::SuppressionRemoved,
+        AuditAction::UserRoleChanged,
+        AuditAction::UserDeleted,
+        AuditAction::UserInvited,
+        AuditAction::ApiTokenCreated,
+        AuditAction::ApiTokenRevoked,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::LoginLockedOut => "login_locked_out",
+            AuditAction::Logout => "logout",
+            AuditAction::SessionRevoked => "session_revoked",
+            AuditAction::OtherSessionsRevoked => "other_sessions_revoked",
+            AuditAction::PasswordChanged => "password_changed",
+            AuditAction::TwoFactorEnabled => "two_factor_enabled",
+            AuditAction::TwoFactorDisabled => "two_factor_disabled",
+            AuditAction::IssuePublished => "issue_published",
+            AuditAction::IssueDeliveryPaused => "issue_delivery_paused",
+            AuditAction::IssueDeliveryResumed => "issue_delivery_resumed",
+            AuditAction::IssueDeliveryCancelled => "issue_delivery_cancelled",
+            AuditAction::SubscriberTrackingChanged => "subscriber_tracking_changed",
+            AuditAction::SuppressionAdded => "suppression_added",
+            AuditAction::SuppressionsImported => "suppressions_imported",
+            AuditAction::SuppressionRemoved => "suppression_removed",
+            AuditAction::UserRoleChanged => "user_role_changed",
+            AuditAction::UserDeleted => "user_deleted",
+            AuditAction::UserInvited => "user_invited",
+            AuditAction::ApiTokenCreated => "api_token_created",
+            AuditAction::ApiTokenRevoked => "api_token_revoked",
+        }
+    }
+}
+
+impl Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for AuditAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|a| a.as_str() == s)
+            .ok_or_else(|| format!("{s} is not a known audit action"))
+    }
+}
+
+pub struct AuditEvent {
+    pub event_id: i64,
+    pub occurred_at: DateTime<Utc>,
+    pub user_id: Option<Uuid>,
+    /// `None` if the user has been deleted in the meantime.
+    pub username: Option<String>,
+    pub action: String,
+    pub ip_address: Option<String>,
+    pub payload: serde_json::Value,
+}
+
+/// Appends an event to the audit log. Pass the transaction of the change itself where there
+/// is one, so that the change is not committed without its event.
+#[tracing::instrument(name = "record audit event", skip(payload, executor))]
+pub async fn record_audit_event(
+    user_id: Option<Uuid>,
+    ip_address: Option<IpAddr>,
+    action: AuditAction,
+    payload: serde_json::Value,
+    executor: impl PgExecutor<'_>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_events (user_id, action, ip_address, payload)
+        VALUES ($1, $2, $3, $4::text::jsonb)
+        "#,
+        user_id,
+        action.as_str(),
+        ip_address.map(|ip| ip.to_string()),
+        payload.to_string()
+    )
+    .execute(executor)
+    .await
+    .context("failed to record an audit event")?;
+    Ok(())
+}
+
+/// Returns the most recent events first, optionally only those of one action and/or user.
+#[tracing::instrument(name = "list audit events", skip(pool))]
+pub async fn list_audit_events(
+    action: Option<AuditAction>,
+    username: Option<&str>,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<AuditEvent>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT e.event_id, e.occurred_at, e.user_id, u.username AS "username?", e.action,
+            e.ip_address, e.payload::text AS "payload!"
+        FROM audit_events e
+        LEFT JOIN users u ON u.user_id = e.user_id
+        WHERE ($1::text IS NULL OR e.action = $1)
+            AND ($2::text IS NULL OR u.username = $2)
+        ORDER BY e.event_id DESC
+        LIMIT $3
+        "#,
+        action.map(|a| a.as_str()),
+        username,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to list the audit events")?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(AuditEvent {
+                event_id: r.event_id,
+                occurred_at: r.occurred_at,
+                user_id: r.user_id,
+                username: r.username,
+                action: r.action,
+                ip_address: r.ip_address,
+                payload: serde_json::from_str(&r.payload)
+                    .context("failed to parse the payload of an audit event")?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AuditAction;
+
+    #[test]
+    fn every_action_roundtrips_through_its_name() {
+        for action in AuditAction::ALL {
+            assert_eq!(action.as_str().parse::<AuditAction>(), Ok(action));
+        }
+    }
+
+    #[test]
+    fn unknown_actions_are_rejected() {
+        assert!("drop_table".parse::<AuditAction>().is_err());
+    }
+}
@@ -1,5 +1,28 @@
+mod api_tokens;
+mod csrf;
+mod invites;
 mod middleware;
 mod password;
+mod password_policy;
+mod password_reset;
+mod roles;
+mod sessions;
+mod throttling;
+mod two_factor;
+mod users;
 
-pub use middleware::{reject_anonymous_users, UserId};
+pub use api_tokens::*;
+pub use csrf::*;
+pub use invites::*;
+pub use middleware::{
+    authenticate_api_tokens, authenticate_bearer_token, reject_anonymous_users, reject_api_tokens,
+    reject_non_owners, reject_viewers, ApiTokenAuthenticated, ApiTokenRejection, UserId,
+};
 pub use password::*;
+pub use password_policy::*;
+pub use password_reset::*;
+pub use roles::*;
+pub use sessions::*;
+pub use throttling::*;
+pub use two_factor::*;
+pub use users::*;
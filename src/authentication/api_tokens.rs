@@ -0,0 +1,215 @@
+use std::fmt::Display;
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// Makes the tokens recognizable, e.g. for secret scanners.
+const API_TOKEN_PREFIX: &str = "z2p_";
+/// Every token has to expire, at the latest after this many days.
+pub const MAX_API_TOKEN_VALIDITY_DAYS: i64 = 365;
+
+/// What a token may be used for. The role of its user still applies on top of the scopes, so
+/// a token never allows more than its user could do in the browser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiScope {
+    /// Safe requests, i.e. `GET` and `HEAD`.
+    Read,
+    /// Everything else.
+    Write,
+}
+
+impl ApiScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::Read => "read",
+            ApiScope::Write => "write",
+        }
+    }
+}
+
+impl Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for ApiScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Self::Read),
+            "write" => Ok(Self::Write),
+            other => Err(format!(
+                "{other} is not a valid scope, use one of read or write"
+            )),
+        }
+    }
+}
+
+pub struct ApiToken {
+    pub token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// The user and the scopes of a valid token.
+pub struct ApiTokenGrant {
+    pub user_id: Uuid,
+    pub scopes: Vec<ApiScope>,
+}
+
+/// Returns the token, which cannot be shown again as only its hash is stored.
+#[tracing::instrument(name = "create api token", skip(executor))]
+pub async fn create_api_token(
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+    valid_for: Duration,
+    executor: impl PgExecutor<'_>,
+) -> Result<String, anyhow::Error> {
+    let token = generate_api_token();
+    let scopes: Vec<&str> = scopes.iter().map(ApiScope::as_str).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (token_id, user_id, name, token_hash, scopes, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, now(), now() + make_interval(secs => $6))
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_api_token(&token),
+        &scopes as &[&str],
+        valid_for.num_seconds() as f64
+    )
+    .execute(executor)
+    .await
+    .context("failed to store the api token")?;
+    Ok(token)
+}
+
+/// Returns the tokens of the user which have not expired yet, the newest first.
+#[tracing::instrument(name = "list api tokens", skip(pool))]
+pub async fn list_api_tokens(user_id: Uuid, pool: &PgPool) -> Result<Vec<ApiToken>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT token_id, name, scopes, created_at, expires_at, last_used_at
+        FROM api_tokens
+        WHERE user_id = $1 AND expires_at > now()
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to list the api tokens")?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(ApiToken {
+                token_id: r.token_id,
+                name: r.name,
+                scopes: parse_scopes(&r.scopes)?,
+                created_at: r.created_at,
+                expires_at: r.expires_at,
+                last_used_at: r.last_used_at,
+            })
+        })
+        .collect()
+}
+
+/// Returns the name of the revoked token, `None` if the user has no such token.
+#[tracing::instrument(name = "revoke api token", skip(executor))]
+pub async fn revoke_api_token(
+    user_id: Uuid,
+    token_id: Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        DELETE FROM api_tokens
+        WHERE user_id = $1 AND token_id = $2
+        RETURNING name
+        "#,
+        user_id,
+        token_id
+    )
+    .fetch_optional(executor)
+    .await
+    .context("failed to revoke the api token")?;
+    Ok(row.map(|r| r.name))
+}
+
+/// Looks up an unexpired token and records that it has been used.
+#[tracing::instrument(name = "authenticate api token", skip_all)]
+pub async fn authenticate_api_token(
+    token: &str,
+    pool: &PgPool,
+) -> Result<Option<ApiTokenGrant>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET last_used_at = now()
+        WHERE token_hash = $1 AND expires_at > now()
+        RETURNING user_id, scopes
+        "#,
+        hash_api_token(token)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to look up the api token")?;
+    row.map(|r| {
+        Ok(ApiTokenGrant {
+            user_id: r.user_id,
+            scopes: parse_scopes(&r.scopes)?,
+        })
+    })
+    .transpose()
+}
+
+fn parse_scopes(scopes: &[String]) -> Result<Vec<ApiScope>, anyhow::Error> {
+    scopes
+        .iter()
+        .map(|s| s.parse().map_err(anyhow::Error::msg))
+        .collect()
+}
+
+fn generate_api_token() -> String {
+    let mut rng = thread_rng();
+    let token: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect();
+    format!("{API_TOKEN_PREFIX}{token}")
+}
+
+/// The tokens are random enough that a fast hash is sufficient.
+fn hash_api_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_api_token, ApiScope, API_TOKEN_PREFIX};
+
+    #[test]
+    fn tokens_are_prefixed_and_unique() {
+        let token = generate_api_token();
+        assert!(token.starts_with(API_TOKEN_PREFIX));
+        assert_ne!(token, generate_api_token());
+    }
+
+    #[test]
+    fn scopes_roundtrip_through_their_names() {
+        for scope in [ApiScope::Read, ApiScope::Write] {
+            assert_eq!(scope.as_str().parse::<ApiScope>(), Ok(scope));
+        }
+        assert!("admin".parse::<ApiScope>().is_err());
+    }
+}
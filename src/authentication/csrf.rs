@@ -0,0 +1,104 @@
+use actix_http::h1;
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::Method,
+    web, FromRequest, HttpMessage, HttpResponse,
+};
+use actix_web_lab::middleware::Next;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+use crate::{authentication::ApiTokenAuthenticated, session_state::TypedSession, utils::e500};
+
+/// The name of the form field which has to carry the token.
+const CSRF_FIELD_NAME: &str = "csrf_token";
+
+/// The token of the session, which is created on first use. It stays the same until the
+/// session is purged on logout.
+pub fn csrf_token(session: &TypedSession) -> Result<String, anyhow::Error> {
+    if let Some(token) = session.get_csrf_token()? {
+        return Ok(token);
+    }
+    let mut rng = thread_rng();
+    let token: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect();
+    session.insert_csrf_token(&token)?;
+    Ok(token)
+}
+
+/// The hidden input which every form posting to a protected route has to contain.
+pub fn csrf_field(session: &TypedSession) -> Result<String, anyhow::Error> {
+    Ok(format!(
+        r#"<input hidden type="text" name="{CSRF_FIELD_NAME}" value="{}">"#,
+        csrf_token(session)?
+    ))
+}
+
+#[derive(serde::Deserialize)]
+struct CsrfFormData {
+    csrf_token: Option<String>,
+}
+
+/// Rejects form posts which do not carry the token of the session - a cross-site form only
+/// gets the session cookie, not the token. Safe methods pass through.
+pub async fn reject_invalid_csrf_tokens<B: MessageBody>(
+    mut req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    // a cross-site request cannot set the authorization header of an api token
+    let is_api_token_request = req.extensions().contains::<ApiTokenAuthenticated>();
+    if is_api_token_request || matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+    {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let expected_token = session.get_csrf_token().map_err(e500)?;
+    // the handler still has to read the form, so the body is put back after the check
+    let body = req.extract::<web::Bytes>().await?;
+    let submitted_token = serde_urlencoded::from_bytes::<CsrfFormData>(&body)
+        .ok()
+        .and_then(|f| f.csrf_token);
+    let (_, mut payload) = h1::Payload::create(true);
+    payload.unread_data(body);
+    req.set_payload(payload.into());
+
+    match (expected_token, submitted_token) {
+        (Some(expected), Some(submitted)) if tokens_match(&expected, &submitted) => {
+            Ok(next.call(req).await?.map_into_left_body())
+        }
+        _ => {
+            let e = anyhow::anyhow!("the CSRF token is missing or invalid");
+            Err(InternalError::from_response(e, HttpResponse::Forbidden().finish()).into())
+        }
+    }
+}
+
+/// Compares in constant time, so that the token cannot be guessed byte by byte.
+fn tokens_match(expected: &str, submitted: &str) -> bool {
+    expected.len() == submitted.len()
+        && expected
+            .bytes()
+            .zip(submitted.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokens_match;
+
+    #[test]
+    fn only_identical_tokens_match() {
+        assert!(tokens_match("abc123", "abc123"));
+        assert!(!tokens_match("abc123", "abc124"));
+        assert!(!tokens_match("abc123", "abc12"));
+        assert!(!tokens_match("abc123", ""));
+    }
+}
@@ -0,0 +1,195 @@
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    configuration::PasswordHashingSettings, domain::subscriber_email::SubscriberEmail,
+    telemetry::spawn_blocking_with_tracing,
+};
+
+use super::{
+    password::compute_password_hash,
+    roles::Role,
+    users::{email_is_taken, store_user},
+};
+
+/// How long an invitee has to accept the invite.
+const INVITE_VALIDITY_HOURS: i64 = 72;
+
+pub struct PendingInvite {
+    pub email: String,
+    pub role: Role,
+    pub expires_at: chrono::DateTime<Utc>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum InviteError {
+    #[error("the invite is invalid or has expired")]
+    InvalidInvite,
+    #[error("an admin user with the email {0} already exists")]
+    EmailTaken(String),
+    #[error("a user called {0} already exists")]
+    UsernameTaken(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// Stores an invite for the email and returns the token the invitee can accept it with.
+/// Only a hash of the token is stored.
+#[tracing::instrument(name = "create invite", skip(transaction))]
+pub async fn create_invite(
+    email: &SubscriberEmail,
+    role: Role,
+    invited_by: Uuid,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<String, InviteError> {
+    if email_is_taken(email.as_ref(), &mut *transaction).await? {
+        return Err(InviteError::EmailTaken(email.to_string()));
+    }
+    let invite_token = generate_invite_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invites (token_hash, email, role, invited_by, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, now(), $5)
+        "#,
+        hash_invite_token(&invite_token),
+        email.as_ref(),
+        role.as_str(),
+        invited_by,
+        Utc::now() + Duration::hours(INVITE_VALIDITY_HOURS)
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("failed to store the invite")?;
+    Ok(invite_token)
+}
+
+/// Returns `None` if there is no unexpired invite with the token.
+#[tracing::instrument(name = "get invite", skip(invite_token, pool))]
+pub async fn get_pending_invite(
+    invite_token: &str,
+    pool: &PgPool,
+) -> Result<Option<PendingInvite>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email, role, expires_at
+        FROM user_invites
+        WHERE token_hash = $1 AND expires_at > now()
+        "#,
+        hash_invite_token(invite_token)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to retrieve the invite")?;
+    row.map(|r| {
+        Ok(PendingInvite {
+            email: r.email,
+            role: Role::try_from(r.role).map_err(anyhow::Error::msg)?,
+            expires_at: r.expires_at,
+        })
+    })
+    .transpose()
+}
+
+#[tracing::instrument(name = "list pending invites", skip(pool))]
+pub async fn list_pending_invites(pool: &PgPool) -> Result<Vec<PendingInvite>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT email, role, expires_at
+        FROM user_invites
+        WHERE expires_at > now()
+        ORDER BY created_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to list the pending invites")?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(PendingInvite {
+                email: r.email,
+                role: Role::try_from(r.role).map_err(anyhow::Error::msg)?,
+                expires_at: r.expires_at,
+            })
+        })
+        .collect()
+}
+
+/// Creates the invited user and uses up the invite. The invite stays valid if the username
+/// turns out to be taken, so that the invitee can pick another one.
+#[tracing::instrument(name = "accept invite", skip(invite_token, password, hashing, pool))]
+pub async fn accept_invite(
+    invite_token: &str,
+    username: &str,
+    password: Secret<String>,
+    hashing: &PasswordHashingSettings,
+    pool: &PgPool,
+) -> Result<Uuid, InviteError> {
+    let hashing = *hashing;
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &hashing))
+            .await
+            .context("failed to spawn blocking task")?
+            .context("failed to hash password")?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")?;
+    // deleting the invite right away makes sure that it can only be used once
+    let invite = sqlx::query!(
+        r#"
+        DELETE FROM user_invites
+        WHERE token_hash = $1 AND expires_at > now()
+        RETURNING email, role
+        "#,
+        hash_invite_token(invite_token)
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("failed to use up the invite")?
+    .ok_or(InviteError::InvalidInvite)?;
+    let role = Role::try_from(invite.role).map_err(anyhow::Error::msg)?;
+
+    let user_id = Uuid::new_v4();
+    let inserted = store_user(
+        &mut transaction,
+        user_id,
+        username,
+        Some(&invite.email),
+        password_hash,
+        role,
+    )
+    .await
+    .context("failed to store the new user")?;
+    if !inserted {
+        // the unique constraints tell us which one is taken, even if the email was taken
+        // by a concurrent request
+        return Err(if email_is_taken(&invite.email, &mut transaction).await? {
+            InviteError::EmailTaken(invite.email)
+        } else {
+            InviteError::UsernameTaken(username.into())
+        });
+    }
+    transaction
+        .commit()
+        .await
+        .context("failed to commit the acceptance of the invite")?;
+    Ok(user_id)
+}
+
+fn generate_invite_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(25)
+        .collect()
+}
+
+fn hash_invite_token(invite_token: &str) -> String {
+    hex::encode(Sha256::digest(invite_token.as_bytes()))
+}
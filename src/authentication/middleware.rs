@@ -1,15 +1,25 @@
 use std::{fmt::Display, ops::Deref};
 
 use actix_web::{
-    body::MessageBody,
+    body::{EitherBody, MessageBody},
     dev::{ServiceRequest, ServiceResponse},
     error::InternalError,
-    FromRequest, HttpMessage,
+    http::{
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        Method,
+    },
+    web, FromRequest, HttpMessage, HttpResponse,
 };
+use actix_web_flash_messages::FlashMessage;
 use actix_web_lab::middleware::Next;
+use sqlx::PgPool;
 use uuid::Uuid;
 
 use crate::{
+    authentication::{
+        authenticate_api_token, get_role, touch_session, ApiScope, Role, SessionStatus,
+    },
+    configuration::SessionSettings,
     session_state::TypedSession,
     utils::{e500, see_other},
 };
@@ -31,25 +41,190 @@ impl Deref for UserId {
     }
 }
 
-pub async fn reject_anonymous_users(
-    mut req: ServiceRequest,
+/// Marks requests which have been authenticated with an API token rather than the session.
+#[derive(Debug, Clone, Copy)]
+pub struct ApiTokenAuthenticated;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ApiTokenRejection {
+    #[error("the authorization header is malformed")]
+    MalformedHeader,
+    #[error("the api token is invalid or has expired")]
+    InvalidToken,
+    #[error("the api token lacks the {0} scope")]
+    MissingScope(ApiScope),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// Checks the API token of the `Authorization: Bearer` header and the scope the method of the
+/// request needs. Requests without the header yield `None`.
+pub async fn authenticate_bearer_token(
+    req: &ServiceRequest,
+) -> Result<Option<UserId>, ApiTokenRejection> {
+    let Some(authorization) = req.headers().get(AUTHORIZATION) else {
+        return Ok(None);
+    };
+    let token = authorization
+        .to_str()
+        .ok()
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(str::trim)
+        .ok_or(ApiTokenRejection::MalformedHeader)?;
+
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| anyhow::anyhow!("the database pool is missing from the application data"))?;
+    let grant = authenticate_api_token(token, pool)
+        .await?
+        .ok_or(ApiTokenRejection::InvalidToken)?;
+
+    let required_scope = if matches!(*req.method(), Method::GET | Method::HEAD) {
+        ApiScope::Read
+    } else {
+        ApiScope::Write
+    };
+    if !grant.scopes.contains(&required_scope) {
+        return Err(ApiTokenRejection::MissingScope(required_scope));
+    }
+    Ok(Some(UserId(grant.user_id)))
+}
+
+/// Authenticates requests carrying an `Authorization: Bearer` header by their API token. Has
+/// to be layered outside of `reject_anonymous_users`, which lets the requests through that
+/// already have the id of the user. Requests without the header are left to the session.
+pub async fn authenticate_api_tokens(
+    req: ServiceRequest,
     next: Next<impl MessageBody>,
 ) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let user_id = match authenticate_bearer_token(&req).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return next.call(req).await,
+        Err(ApiTokenRejection::UnexpectedError(e)) => return Err(e500(e)),
+        Err(e @ ApiTokenRejection::MissingScope(_)) => {
+            return Err(InternalError::from_response(e, HttpResponse::Forbidden().finish()).into())
+        }
+        Err(e) => {
+            let response = HttpResponse::Unauthorized()
+                .insert_header((WWW_AUTHENTICATE, r#"Bearer realm="admin""#))
+                .finish();
+            return Err(InternalError::from_response(e, response).into());
+        }
+    };
+
+    req.extensions_mut().insert(user_id);
+    req.extensions_mut().insert(ApiTokenAuthenticated);
+    next.call(req).await
+}
+
+/// Tokens must not be used for the security settings of their user, e.g. to extend their own
+/// lifetime, change the password or revoke the sessions. Has to be layered inside of
+/// `authenticate_api_tokens`.
+pub async fn reject_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if req.extensions().contains::<ApiTokenAuthenticated>() {
+        let e = anyhow::anyhow!("api tokens cannot be used for this route");
+        return Err(InternalError::from_response(e, HttpResponse::Forbidden().finish()).into());
+    }
+    next.call(req).await
+}
+
+pub async fn reject_anonymous_users<B: MessageBody>(
+    mut req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    // authenticated by an api token
+    if req.extensions().contains::<UserId>() {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
     let session = {
         let (http_request, payload) = req.parts_mut();
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
+    let user_id = session.get_user_id().map_err(e500)?;
+    let session_id = session.get_session_id().map_err(e500)?;
+    let status = match (user_id, session_id) {
+        (Some(user_id), Some(session_id)) => {
+            let pool = req
+                .app_data::<web::Data<PgPool>>()
+                .ok_or_else(|| e500("the database pool is missing from the application data"))?;
+            let settings = req
+                .app_data::<web::Data<SessionSettings>>()
+                .ok_or_else(|| {
+                    e500("the session settings are missing from the application data")
+                })?;
+            touch_session(session_id, user_id, settings, pool)
+                .await
+                .map_err(e500)?
+        }
+        _ => SessionStatus::Revoked,
+    };
+
+    match (user_id, status) {
+        (Some(user_id), SessionStatus::Active) => {
             req.extensions_mut().insert(UserId(user_id));
-            next.call(req).await
+            Ok(next.call(req).await?.map_into_left_body())
         }
-        None => {
+        (_, status) => {
+            if let SessionStatus::Expired = status {
+                FlashMessage::error("Your session has expired - please log in again.").send();
+            }
+            // an anonymous session might hold a login waiting for its second factor
+            if user_id.is_some() {
+                session.log_out();
+            }
+            // a response rather than an error, the outer middlewares only add the flash
+            // message and purge the session on responses
             let response = see_other("/login");
-            dbg!(&response);
-            let e = anyhow::anyhow!("the user has not logged in");
-            Err(InternalError::from_response(e, response).into())
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+/// Has to be layered inside of `reject_anonymous_users`, which provides the id of the user.
+pub async fn reject_viewers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(req, next, Role::Editor).await
+}
+
+/// Has to be layered inside of `reject_anonymous_users`, which provides the id of the user.
+pub async fn reject_non_owners(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(req, next, Role::Owner).await
+}
+
+async fn require_role(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+    required_role: Role,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let user_id = req
+        .extensions()
+        .get::<UserId>()
+        .copied()
+        .ok_or_else(|| e500("the id of the user is missing from the request"))?;
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("the database pool is missing from the application data"))?;
+    let role = get_role(*user_id, pool).await.map_err(e500)?;
+    match role {
+        Some(role) if role >= required_role => next.call(req).await,
+        Some(role) => {
+            let e =
+                anyhow::anyhow!("a user with the role {role} needs to be at least {required_role}");
+            Err(InternalError::from_response(e, HttpResponse::Forbidden().finish()).into())
+        }
+        None => {
+            let e = anyhow::anyhow!("the user does not exist anymore");
+            Err(InternalError::from_response(e, see_other("/login")).into())
         }
     }
 }
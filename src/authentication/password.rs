@@ -4,9 +4,11 @@ use argon2::{
     PasswordVerifier, Version,
 };
 use secrecy::{ExposeSecret, Secret};
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 
-use crate::telemetry::spawn_blocking_with_tracing;
+use crate::{configuration::PasswordHashingSettings, telemetry::spawn_blocking_with_tracing};
+
+use super::{revoke_sessions, PasswordPolicy};
 
 #[derive(thiserror::Error, Debug)]
 pub enum AuthError {
@@ -21,18 +23,23 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
-#[tracing::instrument(name = "validate credentials", skip(credentials, pool))]
+/// Hashes that were created with other parameters than the configured ones are upgraded after
+/// a successful check, so that the hashing cost can be raised without resetting any passwords.
+#[tracing::instrument(name = "validate credentials", skip(credentials, hashing, pool))]
 pub async fn validate_credentials(
     credentials: Credentials,
+    hashing: &PasswordHashingSettings,
     pool: &PgPool,
 ) -> Result<uuid::Uuid, AuthError> {
     let mut user_id = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
+    // unknown usernames are checked against a dummy hash with the configured parameters, so
+    // that they take as long as known ones
+    let mut expected_password_hash = Secret::new(format!(
+        "$argon2id$v=19$m={},t={},p={}$\
     gziV/M1gPc22ElAH/Jh1Hw$\
-    CWorkoo7oJBQ/iyh7uJ0lO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
-    );
+    CWorkoo7oJBQ/iyh7uJ0lO2aLEfrHwTWllSAxT0zRno",
+        hashing.memory_cost_kib, hashing.time_cost, hashing.parallelism
+    ));
 
     if let Some((stored_user_id, stored_password_hash)) =
         get_stored_credentials(&credentials.username, pool).await?
@@ -41,26 +48,72 @@ pub async fn validate_credentials(
         expected_password_hash = stored_password_hash;
     }
 
-    spawn_blocking_with_tracing(move || {
-        verify_password_hash(expected_password_hash, credentials.password)
+    let hashing = *hashing;
+    let stored_password_hash = expected_password_hash.clone();
+    let upgraded_password_hash = spawn_blocking_with_tracing(move || {
+        verify_password_hash(&expected_password_hash, &credentials.password)?;
+        if !needs_rehash(&expected_password_hash, &hashing) {
+            return Ok(None);
+        }
+        match compute_password_hash(credentials.password, &hashing) {
+            Ok(password_hash) => Ok(Some(password_hash)),
+            Err(e) => {
+                tracing::warn!(error.cause_chain = ?e, "failed to rehash a password");
+                Ok::<_, AuthError>(None)
+            }
+        }
     })
     .await
     .context("failed to spawn blocking task")??;
 
-    user_id
+    let user_id = user_id
         .ok_or_else(|| anyhow::anyhow!("unknown username"))
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+
+    if let Some(password_hash) = upgraded_password_hash {
+        // the login must not fail just because the upgrade did
+        if let Err(e) =
+            upgrade_password_hash(user_id, &stored_password_hash, &password_hash, pool).await
+        {
+            tracing::warn!(error.cause_chain = ?e, "failed to upgrade a password hash");
+        }
+    }
+    Ok(user_id)
+}
+
+/// Checks a password chosen in a form against the password policy. The error explains the
+/// problem to the user.
+pub fn check_new_password(
+    new_password: &Secret<String>,
+    new_password_check: &Secret<String>,
+    username: &str,
+    policy: &PasswordPolicy,
+) -> Result<(), String> {
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        return Err(
+            "You entered two different new passwords - the field values must match.".into(),
+        );
+    }
+    policy
+        .check(new_password, username)
+        .map_err(|e| e.to_string())
 }
 
-#[tracing::instrument(name = "change password", skip(password, pool))]
+/// Also revokes all sessions of the user except for `keep_session`, somebody else might have
+/// logged in with the old password.
+#[tracing::instrument(name = "change password", skip(password, hashing, transaction))]
 pub async fn change_password(
     user_id: uuid::Uuid,
     password: Secret<String>,
-    pool: &PgPool,
+    keep_session: Option<uuid::Uuid>,
+    hashing: &PasswordHashingSettings,
+    transaction: &mut Transaction<'_, Postgres>,
 ) -> Result<(), anyhow::Error> {
-    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
-        .await?
-        .context("failed to hash password")?;
+    let hashing = *hashing;
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &hashing))
+            .await?
+            .context("failed to hash password")?;
 
     sqlx::query!(
         r#"
@@ -71,24 +124,70 @@ pub async fn change_password(
         password_hash.expose_secret(),
         user_id
     )
-    .execute(pool)
+    .execute(&mut *transaction)
     .await
     .context("failed to change user's password in the database")?;
+    revoke_sessions(user_id, keep_session, &mut *transaction).await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "upgrade password hash", skip_all)]
+async fn upgrade_password_hash(
+    user_id: uuid::Uuid,
+    old_password_hash: &Secret<String>,
+    new_password_hash: &Secret<String>,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    // a password changed in the meantime is left alone
+    sqlx::query!(
+        r#"
+            UPDATE users
+            SET password_hash = $1
+            WHERE user_id = $2 AND password_hash = $3
+        "#,
+        new_password_hash.expose_secret(),
+        user_id,
+        old_password_hash.expose_secret()
+    )
+    .execute(pool)
+    .await
+    .context("failed to store the upgraded password hash")?;
     Ok(())
 }
 
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+pub(super) fn compute_password_hash(
+    password: Secret<String>,
+    hashing: &PasswordHashingSettings,
+) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
+    let params = Params::new(
+        hashing.memory_cost_kib,
+        hashing.time_cost,
+        hashing.parallelism,
+        None,
     )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
+    .map_err(|e| anyhow::anyhow!("invalid password hashing parameters: {e}"))?;
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
     Ok(Secret::new(password_hash))
 }
 
+/// Whether a (valid) stored hash was created with other parameters than the configured ones.
+fn needs_rehash(password_hash: &Secret<String>, hashing: &PasswordHashingSettings) -> bool {
+    let Ok(password_hash) = PasswordHash::new(password_hash.expose_secret()) else {
+        return true;
+    };
+    let Ok(params) = Params::try_from(&password_hash) else {
+        return true;
+    };
+    password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+        || params.m_cost() != hashing.memory_cost_kib
+        || params.t_cost() != hashing.time_cost
+        || params.p_cost() != hashing.parallelism
+}
+
 #[tracing::instrument(name = "get stored credentials", skip(username, pool))]
 async fn get_stored_credentials(
     username: &str,
@@ -110,8 +209,8 @@ async fn get_stored_credentials(
 }
 
 fn verify_password_hash(
-    expected_password_hash: Secret<String>,
-    password_candidate: Secret<String>,
+    expected_password_hash: &Secret<String>,
+    password_candidate: &Secret<String>,
 ) -> Result<(), AuthError> {
     let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
         .context("failed to parse hash in phc string format")?;
@@ -124,3 +223,43 @@ fn verify_password_hash(
         .context("invalid password")
         .map_err(AuthError::InvalidCredentials)
 }
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::{compute_password_hash, needs_rehash, PasswordHashingSettings};
+
+    fn hashing(memory_cost_kib: u32, time_cost: u32) -> PasswordHashingSettings {
+        PasswordHashingSettings {
+            memory_cost_kib,
+            time_cost,
+            parallelism: 1,
+        }
+    }
+
+    #[test]
+    fn hashes_with_the_configured_parameters_are_kept() {
+        let hash =
+            compute_password_hash(Secret::new("password".into()), &hashing(4096, 1)).unwrap();
+        assert!(!needs_rehash(&hash, &hashing(4096, 1)));
+    }
+
+    #[test]
+    fn hashes_with_other_parameters_are_rehashed() {
+        let hash =
+            compute_password_hash(Secret::new("password".into()), &hashing(4096, 1)).unwrap();
+        assert!(needs_rehash(&hash, &hashing(8192, 1)));
+        assert!(needs_rehash(&hash, &hashing(4096, 2)));
+    }
+
+    #[test]
+    fn hashes_of_other_algorithms_are_rehashed() {
+        let hash = Secret::new(
+            "$argon2i$v=19$m=4096,t=1,p=1$gziV/M1gPc22ElAH/Jh1Hw$\
+            CWorkoo7oJBQ/iyh7uJ0lO2aLEfrHwTWllSAxT0zRno"
+                .to_string(),
+        );
+        assert!(needs_rehash(&hash, &hashing(4096, 1)));
+    }
+}
@@ -0,0 +1,224 @@
+use std::{collections::HashSet, sync::OnceLock};
+
+use secrecy::{ExposeSecret, Secret};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::configuration::PasswordPolicySettings;
+
+/// Passwords which show up in the lists of leaked passwords that attackers try first.
+fn common_passwords() -> &'static HashSet<&'static str> {
+    static COMMON_PASSWORDS: OnceLock<HashSet<&'static str>> = OnceLock::new();
+    COMMON_PASSWORDS.get_or_init(|| {
+        include_str!("common_passwords.txt")
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect()
+    })
+}
+
+/// Shorter usernames are too likely to appear in a password by chance - a password is only
+/// rejected for being the username then.
+const MIN_USERNAME_GRAPHEMES_TO_SEARCH: usize = 4;
+
+/// The messages are shown to the user choosing the password.
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum PasswordPolicyViolation {
+    #[error("The password must contain at least {0} characters.")]
+    TooShort(usize),
+    #[error("The password must not contain more than {0} characters.")]
+    TooLong(usize),
+    #[error("The password must not contain the username.")]
+    ContainsUsername,
+    #[error("The password is too common - it appears in lists of leaked passwords.")]
+    TooCommon,
+    #[error("The password is too easy to guess - use a longer one with more varied characters.")]
+    TooWeak,
+}
+
+/// The rules for every newly chosen password, no matter where it is set.
+#[derive(Clone)]
+pub struct PasswordPolicy {
+    settings: PasswordPolicySettings,
+}
+
+impl PasswordPolicy {
+    pub fn new(settings: PasswordPolicySettings) -> Self {
+        Self { settings }
+    }
+
+    pub fn check(
+        &self,
+        password: &Secret<String>,
+        username: &str,
+    ) -> Result<(), PasswordPolicyViolation> {
+        let password = password.expose_secret();
+        // a grapheme is what the user perceives as a character
+        let length = password.graphemes(true).count();
+        if length < self.settings.min_length {
+            return Err(PasswordPolicyViolation::TooShort(self.settings.min_length));
+        }
+        if length > self.settings.max_length {
+            return Err(PasswordPolicyViolation::TooLong(self.settings.max_length));
+        }
+
+        // mixing cases does not help against the lookups, but it does against brute force
+        let lowercase_password = password.to_lowercase();
+        let username = username.trim().to_lowercase();
+        let contains_username =
+            if username.graphemes(true).count() < MIN_USERNAME_GRAPHEMES_TO_SEARCH {
+                lowercase_password == username
+            } else {
+                lowercase_password.contains(&username)
+            };
+        if contains_username {
+            return Err(PasswordPolicyViolation::ContainsUsername);
+        }
+        if common_passwords().contains(lowercase_password.as_str()) {
+            return Err(PasswordPolicyViolation::TooCommon);
+        }
+        if estimate_strength_bits(password) < self.settings.min_strength_bits {
+            return Err(PasswordPolicyViolation::TooWeak);
+        }
+        Ok(())
+    }
+}
+
+/// A rough estimate of how many guesses (as a power of two) a brute force attack needs.
+///
+/// Every character adds the bits of the character classes used in the password, unless it
+/// repeats an earlier character or continues a sequence like "abc" or "321" - those add a
+/// single bit only.
+fn estimate_strength_bits(password: &str) -> f64 {
+    let pool_size = character_pool_size(password);
+    if pool_size == 0 {
+        return 0.0;
+    }
+    let bits_per_character = (pool_size as f64).log2();
+
+    let mut seen = HashSet::new();
+    let mut previous: Option<char> = None;
+    let mut bits = 0.0;
+    for c in password.chars() {
+        let continues_sequence =
+            previous.is_some_and(|p| (c as i64 - p as i64).abs() == 1 || c == p);
+        bits += if seen.insert(c) && !continues_sequence {
+            bits_per_character
+        } else {
+            1.0
+        };
+        previous = Some(c);
+    }
+    bits
+}
+
+fn character_pool_size(password: &str) -> u32 {
+    let mut size = 0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        size += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        size += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        size += 10;
+    }
+    if password
+        .chars()
+        .any(|c| c.is_ascii_punctuation() || c == ' ')
+    {
+        size += 33;
+    }
+    if !password.is_ascii() {
+        size += 100;
+    }
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    use super::{estimate_strength_bits, PasswordPolicy, PasswordPolicyViolation};
+    use crate::configuration::PasswordPolicySettings;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy::new(PasswordPolicySettings {
+            min_length: 12,
+            max_length: 128,
+            min_strength_bits: 50.0,
+        })
+    }
+
+    fn check(password: &str, username: &str) -> Result<(), PasswordPolicyViolation> {
+        policy().check(&Secret::new(password.into()), username)
+    }
+
+    #[test]
+    fn a_long_and_varied_password_is_accepted() {
+        assert_ok!(check("everythinghastostartsomewhere", "ursula"));
+        assert_ok!(check("a brand new password", "ursula"));
+    }
+
+    #[test]
+    fn the_length_is_counted_in_graphemes() {
+        // "é" as "e" and a combining accent - 22 chars, but 11 graphemes
+        assert_eq!(
+            check(&"e\u{301}".repeat(11), "ursula"),
+            Err(PasswordPolicyViolation::TooShort(12))
+        );
+        assert_ok!(check("ëä öü ß€ éà ìò", "ursula"));
+    }
+
+    #[test]
+    fn overlong_passwords_are_rejected() {
+        assert_eq!(
+            check(&"abcdefghijklmnopqrstuvwxyz!".repeat(5), "ursula"),
+            Err(PasswordPolicyViolation::TooLong(128))
+        );
+    }
+
+    #[test]
+    fn passwords_containing_the_username_are_rejected() {
+        assert_eq!(
+            check("Ursula-wants-a-password", "ursula"),
+            Err(PasswordPolicyViolation::ContainsUsername)
+        );
+    }
+
+    #[test]
+    fn short_usernames_may_appear_in_a_password() {
+        assert_ok!(check("a brand new password", "a"));
+        assert_ok!(check("everythinghastostartsomewhere", "e"));
+    }
+
+    #[test]
+    fn common_passwords_are_rejected() {
+        assert_eq!(
+            check("Password1234", "ursula"),
+            Err(PasswordPolicyViolation::TooCommon)
+        );
+    }
+
+    #[test]
+    fn repetitive_and_sequential_passwords_are_rejected() {
+        assert_err!(check("zzzzzzzzzzzzzzzz", "ursula"));
+        assert_eq!(
+            check("mnopqrstuvwx", "ursula"),
+            Err(PasswordPolicyViolation::TooWeak)
+        );
+        assert_eq!(
+            check("98765432109876", "ursula"),
+            Err(PasswordPolicyViolation::TooWeak)
+        );
+    }
+
+    #[test]
+    fn mixing_cases_makes_a_password_stronger() {
+        assert!(
+            estimate_strength_bits("CorrectHorseBattery")
+                > estimate_strength_bits("correcthorsebattery")
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use anyhow::Context;
+use chrono::{Duration, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// How long a reset link can be used for.
+const RESET_TOKEN_VALIDITY_MINUTES: i64 = 60;
+
+pub struct PasswordResetRecipient {
+    pub user_id: Uuid,
+    pub email: String,
+}
+
+/// Returns `None` if there is no user with the username or if the user has no email address
+/// to send the reset link to.
+#[tracing::instrument(name = "get password reset recipient", skip(pool))]
+pub async fn get_password_reset_recipient(
+    username: &str,
+    pool: &PgPool,
+) -> Result<Option<PasswordResetRecipient>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id, email
+        FROM users
+        WHERE username = $1
+        "#,
+        username
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to look up the user")?;
+    Ok(row.and_then(|r| {
+        r.email.map(|email| PasswordResetRecipient {
+            user_id: r.user_id,
+            email,
+        })
+    }))
+}
+
+/// The token is signed over the current password hash of the user, so that it stops being
+/// valid as soon as it has been used to set a new password.
+#[tracing::instrument(name = "generate password reset token", skip(hmac_secret, pool))]
+pub async fn generate_password_reset_token(
+    user_id: Uuid,
+    hmac_secret: &Secret<String>,
+    pool: &PgPool,
+) -> Result<String, anyhow::Error> {
+    let password_hash = get_password_hash(user_id, pool)
+        .await?
+        .context("the user does not exist")?;
+    let expires_at = (Utc::now() + Duration::minutes(RESET_TOKEN_VALIDITY_MINUTES)).timestamp();
+    let payload = format!("{user_id}.{expires_at}");
+    let signature = sign(&payload, &password_hash, hmac_secret)?.finalize();
+    Ok(format!(
+        "{payload}.{signature}",
+        signature = hex::encode(signature.into_bytes())
+    ))
+}
+
+/// Returns the id of the user the token was issued for, or `None` if the token is invalid,
+/// expired or has already been used.
+#[tracing::instrument(name = "verify password reset token", skip_all)]
+pub async fn verify_password_reset_token(
+    token: &str,
+    hmac_secret: &Secret<String>,
+    pool: &PgPool,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let Some((payload, signature)) = token.rsplit_once('.') else {
+        return Ok(None);
+    };
+    let Some((user_id, expires_at)) = payload.split_once('.') else {
+        return Ok(None);
+    };
+    let (Ok(user_id), Ok(expires_at), Ok(signature)) = (
+        Uuid::parse_str(user_id),
+        expires_at.parse::<i64>(),
+        hex::decode(signature),
+    ) else {
+        return Ok(None);
+    };
+    let Some(expires_at) = Utc.timestamp_opt(expires_at, 0).single() else {
+        return Ok(None);
+    };
+    if expires_at < Utc::now() {
+        return Ok(None);
+    }
+    let Some(password_hash) = get_password_hash(user_id, pool).await? else {
+        return Ok(None);
+    };
+    let is_valid = sign(payload, &password_hash, hmac_secret)?
+        .verify_slice(&signature)
+        .is_ok();
+    Ok(is_valid.then_some(user_id))
+}
+
+fn sign(
+    payload: &str,
+    password_hash: &Secret<String>,
+    hmac_secret: &Secret<String>,
+) -> Result<Hmac<Sha256>, anyhow::Error> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())
+        .context("failed to initialise the hmac")?;
+    mac.update(payload.as_bytes());
+    mac.update(password_hash.expose_secret().as_bytes());
+    Ok(mac)
+}
+
+async fn get_password_hash(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<Secret<String>>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT password_hash
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to retrieve the password hash")?;
+    Ok(row.map(|r| Secret::new(r.password_hash)))
+}
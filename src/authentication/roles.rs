@@ -0,0 +1,95 @@
+use std::fmt::Display;
+
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// What an admin user is allowed to do. Every role can do everything the roles below it can:
+/// viewers can look at issues, subscribers and drafts, editors can also draft issues and owners
+/// can also publish issues, manage subscribers and manage the other users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Self::Viewer),
+            "editor" => Ok(Self::Editor),
+            "owner" => Ok(Self::Owner),
+            other => Err(format!(
+                "{other} is not a valid role, use one of owner, editor or viewer"
+            )),
+        }
+    }
+}
+
+/// Returns `None` if the user does not exist (anymore).
+#[tracing::instrument(name = "get user role", skip(pool))]
+pub async fn get_role(user_id: Uuid, pool: &PgPool) -> Result<Option<Role>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT role
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to perform a query to retrieve the role of a user")?;
+    row.map(|r| Role::try_from(r.role).map_err(anyhow::Error::msg))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Role;
+
+    #[test]
+    fn roles_round_trip_through_their_names() {
+        for role in [Role::Viewer, Role::Editor, Role::Owner] {
+            assert_eq!(role.as_str().parse::<Role>(), Ok(role));
+        }
+    }
+
+    #[test]
+    fn owners_outrank_editors_who_outrank_viewers() {
+        assert!(Role::Owner > Role::Editor);
+        assert!(Role::Editor > Role::Viewer);
+    }
+
+    #[test]
+    fn unknown_roles_are_rejected() {
+        assert!("admin".parse::<Role>().is_err());
+    }
+}
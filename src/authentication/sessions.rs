@@ -0,0 +1,194 @@
+use std::net::IpAddr;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::configuration::SessionSettings;
+
+/// The user agent is only shown to help the user recognize the session.
+const MAX_USER_AGENT_LENGTH: usize = 256;
+
+pub struct ActiveSession {
+    pub session_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Registers a login of the user. The returned id is stored in the session - sessions whose
+/// id is no longer registered have been revoked.
+#[tracing::instrument(name = "register session", skip(executor))]
+pub async fn register_session(
+    user_id: Uuid,
+    ip_address: Option<IpAddr>,
+    user_agent: Option<&str>,
+    executor: impl PgExecutor<'_>,
+) -> Result<Uuid, anyhow::Error> {
+    let session_id = Uuid::new_v4();
+    let user_agent: Option<String> =
+        user_agent.map(|a| a.chars().take(MAX_USER_AGENT_LENGTH).collect());
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (session_id, user_id, created_at, last_seen_at, ip_address, user_agent)
+        VALUES ($1, $2, now(), now(), $3, $4)
+        "#,
+        session_id,
+        user_id,
+        ip_address.map(|ip| ip.to_string()),
+        user_agent
+    )
+    .execute(executor)
+    .await
+    .context("failed to register the session")?;
+    Ok(session_id)
+}
+
+pub enum SessionStatus {
+    Active,
+    /// Idle for too long or past its maximum lifetime.
+    Expired,
+    /// Logged out remotely, by a password change or by deleting the user.
+    Revoked,
+}
+
+/// Records that the session has been used, unless it has ended in the meantime. Expired
+/// sessions are removed.
+#[tracing::instrument(name = "check session", skip(settings, pool))]
+pub async fn touch_session(
+    session_id: Uuid,
+    user_id: Uuid,
+    settings: &SessionSettings,
+    pool: &PgPool,
+) -> Result<SessionStatus, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT created_at, last_seen_at
+        FROM user_sessions
+        WHERE session_id = $1 AND user_id = $2
+        "#,
+        session_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to retrieve the session")?;
+    let Some(row) = row else {
+        return Ok(SessionStatus::Revoked);
+    };
+
+    let now = Utc::now();
+    if now - row.last_seen_at > settings.idle_timeout()
+        || now - row.created_at > settings.max_lifetime()
+    {
+        revoke_session(user_id, session_id, pool).await?;
+        return Ok(SessionStatus::Expired);
+    }
+    sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET last_seen_at = now()
+        WHERE session_id = $1
+        "#,
+        session_id
+    )
+    .execute(pool)
+    .await
+    .context("failed to update the session")?;
+    Ok(SessionStatus::Active)
+}
+
+/// The unexpired sessions of the user, the most recently used first.
+#[tracing::instrument(name = "list sessions", skip(settings, pool))]
+pub async fn list_sessions(
+    user_id: Uuid,
+    settings: &SessionSettings,
+    pool: &PgPool,
+) -> Result<Vec<ActiveSession>, anyhow::Error> {
+    let now = Utc::now();
+    let sessions = sqlx::query_as!(
+        ActiveSession,
+        r#"
+        SELECT session_id, created_at, last_seen_at, ip_address, user_agent
+        FROM user_sessions
+        WHERE
+            user_id = $1 AND
+            last_seen_at > $2 AND
+            created_at > $3
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id,
+        now - settings.idle_timeout(),
+        now - settings.max_lifetime()
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to list the sessions of the user")?;
+    Ok(sessions)
+}
+
+/// Removes the expired sessions of all users - expired sessions are only removed on their
+/// next use otherwise, which may never come.
+#[tracing::instrument(name = "prune expired sessions", skip(settings, pool))]
+pub async fn prune_expired_sessions(
+    settings: &SessionSettings,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE last_seen_at <= $1 OR created_at <= $2
+        "#,
+        now - settings.idle_timeout(),
+        now - settings.max_lifetime()
+    )
+    .execute(pool)
+    .await
+    .context("failed to prune the expired sessions")?;
+    Ok(())
+}
+
+/// Returns `false` if the user has no such session.
+#[tracing::instrument(name = "revoke session", skip(executor))]
+pub async fn revoke_session(
+    user_id: Uuid,
+    session_id: Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE session_id = $1 AND user_id = $2
+        "#,
+        session_id,
+        user_id
+    )
+    .execute(executor)
+    .await
+    .context("failed to revoke the session")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Forces the user to log in again everywhere, except for the session `keep` (if any).
+#[tracing::instrument(name = "revoke sessions", skip(executor))]
+pub async fn revoke_sessions(
+    user_id: Uuid,
+    keep: Option<Uuid>,
+    executor: impl PgExecutor<'_>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE user_id = $1 AND session_id IS DISTINCT FROM $2
+        "#,
+        user_id,
+        keep
+    )
+    .execute(executor)
+    .await
+    .context("failed to revoke the sessions of the user")?;
+    Ok(())
+}
@@ -0,0 +1,306 @@
+use std::{
+    future::{ready, Ready},
+    net::IpAddr,
+};
+
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditAction},
+    configuration::LoginThrottlingSettings,
+};
+
+/// The address of the client, resolved like for the login throttling - i.e. taken from
+/// `X-Forwarded-For` only if the proxy is trusted.
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl FromRequest for ClientIp {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<ClientIp, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let client_ip = match req.app_data::<web::Data<LoginThrottle>>() {
+            Some(login_throttle) => login_throttle.client_ip(req),
+            None => req.peer_addr().map(|addr| addr.ip()),
+        };
+        ready(Ok(ClientIp(client_ip)))
+    }
+}
+
+/// Guards the login against guessing passwords. The counters live in Postgres, so that all
+/// the API replicas share them.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    settings: LoginThrottlingSettings,
+}
+
+impl LoginThrottle {
+    pub fn new(settings: LoginThrottlingSettings) -> Self {
+        Self { settings }
+    }
+
+    pub fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        if self.settings.trust_forwarded_for {
+            // the proxy appends the address it has been connected from, anything before that
+            // has been sent by the client
+            let forwarded_for = request.headers().get("X-Forwarded-For")?.to_str().ok()?;
+            forwarded_for.rsplit(',').next()?.trim().parse().ok()
+        } else {
+            request.peer_addr().map(|addr| addr.ip())
+        }
+    }
+
+    /// Returns the end of the lockout if either the username or the IP is locked out.
+    #[tracing::instrument(name = "check login lockout", skip(self, pool))]
+    pub async fn locked_until(
+        &self,
+        username: &str,
+        client_ip: Option<IpAddr>,
+        pool: &PgPool,
+    ) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+        let keys = throttle_keys(username, client_ip);
+        let row = sqlx::query!(
+            r#"
+            SELECT MAX(locked_until) AS locked_until
+            FROM login_throttles
+            WHERE throttle_key = ANY($1) AND locked_until > now()
+            "#,
+            &keys[..]
+        )
+        .fetch_one(pool)
+        .await
+        .context("failed to check for a login lockout")?;
+        Ok(row.locked_until)
+    }
+
+    #[tracing::instrument(name = "record failed login", skip(self, pool))]
+    pub async fn record_failure(
+        &self,
+        username: &str,
+        client_ip: Option<IpAddr>,
+        pool: &PgPool,
+    ) -> Result<(), anyhow::Error> {
+        let username_key = username_key(username);
+        self.count_failure(&username_key, self.settings.max_failures_per_username, pool)
+            .await?;
+        if let Some(client_ip) = client_ip {
+            self.count_failure(&ip_key(client_ip), self.settings.max_failures_per_ip, pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Only the username is forgiven - one valid account must not reset the counter of an IP
+    /// that is guessing the passwords of others.
+    #[tracing::instrument(name = "record successful login", skip(self, pool))]
+    pub async fn record_success(&self, username: &str, pool: &PgPool) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM login_throttles
+            WHERE throttle_key = $1
+            "#,
+            username_key(username)
+        )
+        .execute(pool)
+        .await
+        .context("failed to reset the failed logins of the username")?;
+        Ok(())
+    }
+
+    /// Counts a request for a password reset email. Returns `false` if the username or the IP
+    /// has asked for too many of them within the window - no email should be sent then.
+    #[tracing::instrument(name = "count password reset request", skip(self, pool))]
+    pub async fn allow_password_reset(
+        &self,
+        username: &str,
+        client_ip: Option<IpAddr>,
+        pool: &PgPool,
+    ) -> Result<bool, anyhow::Error> {
+        // counted under keys of their own, so that they never lock anybody out of the login
+        let n_requests = self
+            .count_attempt(&password_reset_key(&username_key(username)), pool)
+            .await?;
+        let mut allowed = n_requests <= self.settings.max_password_resets_per_username;
+        if let Some(client_ip) = client_ip {
+            let n_requests = self
+                .count_attempt(&password_reset_key(&ip_key(client_ip)), pool)
+                .await?;
+            allowed &= n_requests <= self.settings.max_password_resets_per_ip;
+        }
+        Ok(allowed)
+    }
+
+    async fn count_failure(
+        &self,
+        throttle_key: &str,
+        max_failures: i32,
+        pool: &PgPool,
+    ) -> Result<(), anyhow::Error> {
+        let n_failures = self.count_attempt(throttle_key, pool).await?;
+        if n_failures < max_failures {
+            return Ok(());
+        }
+
+        let lockout_seconds = self.settings.lockout_seconds as f64;
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("failed to start a transaction")?;
+        let lockout = sqlx::query!(
+            r#"
+            UPDATE login_throttles
+            SET n_failures = 0,
+                window_started_at = now(),
+                locked_until = now() + make_interval(secs => $2)
+            WHERE throttle_key = $1
+            RETURNING locked_until AS "locked_until!"
+            "#,
+            throttle_key,
+            lockout_seconds
+        )
+        .fetch_one(&mut transaction)
+        .await
+        .context("failed to lock out the login")?;
+        sqlx::query!(
+            r#"
+            INSERT INTO login_lockouts (lockout_id, throttle_key, n_failures, locked_at, locked_until)
+            VALUES ($1, $2, $3, now(), $4)
+            "#,
+            Uuid::new_v4(),
+            throttle_key,
+            n_failures,
+            lockout.locked_until
+        )
+        .execute(&mut transaction)
+        .await
+        .context("failed to record the lockout")?;
+        record_audit_event(
+            None,
+            None,
+            AuditAction::LoginLockedOut,
+            serde_json::json!({
+                "throttle_key": throttle_key,
+                "n_failures": n_failures,
+                "locked_until": lockout.locked_until.to_rfc3339(),
+            }),
+            &mut transaction,
+        )
+        .await?;
+        transaction
+            .commit()
+            .await
+            .context("failed to commit the lockout")?;
+        tracing::warn!(
+            throttle_key,
+            n_failures,
+            locked_until = %lockout.locked_until,
+            "locked out logins after too many failures"
+        );
+        Ok(())
+    }
+
+    /// Counts an attempt within the current window of the key and returns the count.
+    async fn count_attempt(&self, throttle_key: &str, pool: &PgPool) -> Result<i32, anyhow::Error> {
+        let window_seconds = self.settings.failure_window_seconds as f64;
+        // every username tried adds a row - the ones which no longer count for anything are
+        // dropped, so that guessing usernames cannot grow the table without limit
+        sqlx::query!(
+            r#"
+            DELETE FROM login_throttles
+            WHERE
+                window_started_at < now() - make_interval(secs => $1) AND
+                (locked_until IS NULL OR locked_until <= now())
+            "#,
+            window_seconds
+        )
+        .execute(pool)
+        .await
+        .context("failed to prune the expired login throttles")?;
+        let row = sqlx::query!(
+            r#"
+            INSERT INTO login_throttles (throttle_key, n_failures, window_started_at)
+            VALUES ($1, 1, now())
+            ON CONFLICT (throttle_key) DO UPDATE SET
+                n_failures = CASE
+                    WHEN login_throttles.window_started_at < now() - make_interval(secs => $2)
+                    THEN 1
+                    ELSE login_throttles.n_failures + 1
+                END,
+                window_started_at = CASE
+                    WHEN login_throttles.window_started_at < now() - make_interval(secs => $2)
+                    THEN now()
+                    ELSE login_throttles.window_started_at
+                END
+            RETURNING n_failures
+            "#,
+            throttle_key,
+            window_seconds
+        )
+        .fetch_one(pool)
+        .await
+        .with_context(|| format!("failed to count an attempt for {throttle_key}"))?;
+        Ok(row.n_failures)
+    }
+}
+
+fn username_key(username: &str) -> String {
+    format!("username:{username}")
+}
+
+fn ip_key(client_ip: IpAddr) -> String {
+    format!("ip:{client_ip}")
+}
+
+fn password_reset_key(key: &str) -> String {
+    format!("password_reset:{key}")
+}
+
+fn throttle_keys(username: &str, client_ip: Option<IpAddr>) -> Vec<String> {
+    let mut keys = vec![username_key(username)];
+    keys.extend(client_ip.map(ip_key));
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::LoginThrottle;
+    use crate::configuration::LoginThrottlingSettings;
+
+    fn throttle(trust_forwarded_for: bool) -> LoginThrottle {
+        LoginThrottle::new(LoginThrottlingSettings {
+            max_failures_per_username: 5,
+            max_failures_per_ip: 20,
+            failure_window_seconds: 900,
+            lockout_seconds: 900,
+            max_password_resets_per_username: 3,
+            max_password_resets_per_ip: 10,
+            trust_forwarded_for,
+        })
+    }
+
+    #[test]
+    fn the_peer_address_is_used_by_default() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4321".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "1.2.3.4"))
+            .to_http_request();
+        let ip = throttle(false).client_ip(&request).unwrap();
+        assert_eq!(ip.to_string(), "10.0.0.1");
+    }
+
+    #[test]
+    fn only_the_entry_added_by_the_proxy_is_trusted() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4321".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "1.2.3.4, 5.6.7.8"))
+            .to_http_request();
+        let ip = throttle(true).client_ip(&request).unwrap();
+        assert_eq!(ip.to_string(), "5.6.7.8");
+    }
+}
@@ -0,0 +1,277 @@
+use anyhow::Context;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use totp_rs::{Algorithm, TOTP};
+use uuid::Uuid;
+
+const ISSUER: &str = "zero2prod";
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP_SECONDS: u64 = 30;
+const N_RECOVERY_CODES: usize = 10;
+
+/// A fresh base32 encoded secret for an authenticator app.
+pub fn generate_totp_secret() -> Secret<String> {
+    match totp_rs::Secret::generate_secret().to_encoded() {
+        totp_rs::Secret::Encoded(secret) => Secret::new(secret),
+        totp_rs::Secret::Raw(_) => unreachable!("the secret has just been encoded"),
+    }
+}
+
+/// The `otpauth://` url authenticator apps are set up with, usually by scanning it as a QR code.
+pub fn totp_setup_url(secret: &Secret<String>, username: &str) -> Result<String, anyhow::Error> {
+    Ok(build_totp(secret, username)?.get_url())
+}
+
+pub fn qr_code_svg(data: &str) -> Result<String, anyhow::Error> {
+    let code = qrcode::QrCode::new(data.as_bytes()).context("failed to encode the QR code")?;
+    Ok(code
+        .render::<qrcode::render::svg::Color>()
+        .min_dimensions(200, 200)
+        .build())
+}
+
+/// Returns the time step the code was generated for, allowing for one step of clock drift
+/// in either direction.
+pub fn verify_totp_code(secret: &Secret<String>, code: &str) -> Result<Option<u64>, anyhow::Error> {
+    let totp = build_totp(secret, "")?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .context("the system clock is set before the epoch")?
+        .as_secs();
+    let current_step = now / TOTP_STEP_SECONDS;
+    let code = code.trim();
+    Ok((current_step - 1..=current_step + 1)
+        .find(|step| totp.check(code, step * TOTP_STEP_SECONDS)))
+}
+
+fn build_totp(secret: &Secret<String>, username: &str) -> Result<TOTP, anyhow::Error> {
+    let secret = totp_rs::Secret::Encoded(secret.expose_secret().clone())
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("invalid totp secret: {e:?}"))?;
+    // the colon separates the issuer from the account name in the setup url
+    let account_name = username.replace(':', "_");
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        0,
+        TOTP_STEP_SECONDS,
+        secret,
+        Some(ISSUER.into()),
+        account_name,
+    )
+    .context("failed to set up totp")
+}
+
+#[tracing::instrument(name = "check if two-factor authentication is enabled", skip(pool))]
+pub async fn is_two_factor_enabled(user_id: Uuid, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT totp_secret
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to retrieve the totp secret")?;
+    Ok(row.is_some_and(|r| r.totp_secret.is_some()))
+}
+
+/// Stores the confirmed secret and returns a new set of recovery codes, which replace any
+/// previous ones. Only the hashes of the codes are stored, so they can only be shown now.
+/// The change is part of the caller's transaction.
+#[tracing::instrument(name = "enable two-factor authentication", skip(secret, transaction))]
+pub async fn enable_two_factor(
+    user_id: Uuid,
+    secret: &Secret<String>,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<String>, anyhow::Error> {
+    let recovery_codes: Vec<String> = (0..N_RECOVERY_CODES)
+        .map(|_| generate_recovery_code())
+        .collect();
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $1, totp_last_used_step = NULL
+        WHERE user_id = $2
+        "#,
+        secret.expose_secret(),
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("failed to store the totp secret")?;
+    sqlx::query!(
+        r#"
+        DELETE FROM totp_recovery_codes
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("failed to delete the old recovery codes")?;
+    for code in &recovery_codes {
+        sqlx::query!(
+            r#"
+            INSERT INTO totp_recovery_codes (user_id, code_hash)
+            VALUES ($1, $2)
+            "#,
+            user_id,
+            hash_recovery_code(code)
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("failed to store a recovery code")?;
+    }
+    Ok(recovery_codes)
+}
+
+/// Returns `false` if two-factor authentication was not enabled for the user.
+#[tracing::instrument(name = "disable two-factor authentication", skip(executor))]
+pub async fn disable_two_factor(
+    user_id: Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<bool, anyhow::Error> {
+    // the recovery codes are useless without a secret and get replaced when re-enabling
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_last_used_step = NULL
+        WHERE user_id = $1 AND totp_secret IS NOT NULL
+        "#,
+        user_id
+    )
+    .execute(executor)
+    .await
+    .context("failed to remove the totp secret")?
+    .rows_affected();
+    Ok(n_updated_rows == 1)
+}
+
+#[tracing::instrument(name = "count unused recovery codes", skip(pool))]
+pub async fn count_unused_recovery_codes(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<i64, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "n_unused!"
+        FROM totp_recovery_codes
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("failed to count the unused recovery codes")?;
+    Ok(row.n_unused)
+}
+
+/// Accepts a code from the authenticator app or an unused recovery code. Either can only be
+/// used once.
+#[tracing::instrument(name = "verify second factor", skip(code, pool))]
+pub async fn verify_second_factor(
+    user_id: Uuid,
+    code: &str,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT totp_secret
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to retrieve the totp secret")?;
+    let Some(secret) = row.and_then(|r| r.totp_secret).map(Secret::new) else {
+        return Ok(false);
+    };
+
+    if let Some(step) = verify_totp_code(&secret, code)? {
+        let n_updated_rows = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_last_used_step = $1
+            WHERE user_id = $2
+                AND (totp_last_used_step IS NULL OR totp_last_used_step < $1)
+            "#,
+            step as i64,
+            user_id
+        )
+        .execute(pool)
+        .await
+        .context("failed to record the use of the totp code")?
+        .rows_affected();
+        return Ok(n_updated_rows == 1);
+    }
+
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE totp_recovery_codes
+        SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code)
+    )
+    .execute(pool)
+    .await
+    .context("failed to use up the recovery code")?
+    .rows_affected();
+    Ok(n_updated_rows == 1)
+}
+
+fn generate_recovery_code() -> String {
+    let mut rng = thread_rng();
+    let code: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(|c| char::from(c).to_ascii_lowercase())
+        .take(10)
+        .collect();
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+/// Recovery codes are random enough that a fast hash is sufficient. The dash and the case are
+/// ignored to make them easier to type.
+fn hash_recovery_code(code: &str) -> String {
+    let normalised: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex::encode(Sha256::digest(normalised.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        generate_recovery_code, generate_totp_secret, hash_recovery_code, verify_totp_code,
+    };
+
+    #[test]
+    fn the_current_code_of_a_secret_is_accepted() {
+        let secret = generate_totp_secret();
+        let totp = super::build_totp(&secret, "admin").unwrap();
+        let code = totp.generate_current().unwrap();
+        assert!(verify_totp_code(&secret, &code).unwrap().is_some());
+    }
+
+    #[test]
+    fn recovery_codes_can_be_typed_without_dash_and_in_upper_case() {
+        let code = generate_recovery_code();
+        let sloppy = code.replace('-', "").to_uppercase();
+        assert_eq!(hash_recovery_code(&code), hash_recovery_code(&sloppy));
+    }
+
+    #[test]
+    fn usernames_with_colons_can_set_up_an_authenticator() {
+        let url = super::totp_setup_url(&generate_totp_secret(), "team:alice").unwrap();
+        assert!(url.starts_with("otpauth://totp/"));
+    }
+}
@@ -0,0 +1,232 @@
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    configuration::PasswordHashingSettings, domain::subscriber_email::SubscriberEmail,
+    telemetry::spawn_blocking_with_tracing,
+};
+
+use super::{password::compute_password_hash, roles::Role};
+
+pub struct User {
+    pub user_id: Uuid,
+    pub username: String,
+    pub role: Role,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum UserError {
+    #[error("a user called {0} already exists")]
+    UsernameTaken(String),
+    #[error("a user with the email {0} already exists")]
+    EmailTaken(String),
+    #[error("there is no user called {0}")]
+    UnknownUser(String),
+    #[error("{0} is the last owner - make another user an owner first")]
+    LastOwner(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+#[tracing::instrument(name = "create user", skip(password, hashing, pool))]
+pub async fn create_user(
+    username: &str,
+    email: Option<&SubscriberEmail>,
+    password: Secret<String>,
+    role: Role,
+    hashing: &PasswordHashingSettings,
+    pool: &PgPool,
+) -> Result<Uuid, UserError> {
+    let hashing = *hashing;
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &hashing))
+            .await
+            .context("failed to spawn blocking task")?
+            .context("failed to hash password")?;
+    let email = email.map(|e| e.as_ref());
+    if let Some(email) = email {
+        if email_is_taken(email, pool).await? {
+            return Err(UserError::EmailTaken(email.into()));
+        }
+    }
+    let user_id = Uuid::new_v4();
+    let inserted = store_user(pool, user_id, username, email, password_hash, role)
+        .await
+        .context("failed to store the new user")?;
+    if !inserted {
+        return Err(UserError::UsernameTaken(username.into()));
+    }
+    Ok(user_id)
+}
+
+/// Returns `false` if the username (or email) is already taken.
+pub(super) async fn store_user(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    username: &str,
+    email: Option<&str>,
+    password_hash: Secret<String>,
+    role: Role,
+) -> Result<bool, sqlx::Error> {
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, password_hash, role)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        username,
+        email,
+        password_hash.expose_secret(),
+        role.as_str()
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_inserted_rows == 1)
+}
+
+pub(super) async fn email_is_taken(
+    email: &str,
+    executor: impl PgExecutor<'_>,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM users
+        WHERE email = $1
+        "#,
+        email
+    )
+    .fetch_optional(executor)
+    .await
+    .context("failed to look up the email")?;
+    Ok(row.is_some())
+}
+
+#[tracing::instrument(name = "get user id", skip(executor))]
+pub async fn get_user_id(username: &str, executor: impl PgExecutor<'_>) -> Result<Uuid, UserError> {
+    sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM users
+        WHERE username = $1
+        "#,
+        username
+    )
+    .fetch_optional(executor)
+    .await
+    .context("failed to retrieve the user")?
+    .map(|r| r.user_id)
+    .ok_or_else(|| UserError::UnknownUser(username.into()))
+}
+
+/// Refuses to demote the last owner, nobody could manage the users anymore otherwise.
+/// The change is part of the caller's transaction, so that it can be committed together with
+/// its audit event.
+#[tracing::instrument(name = "set user role", skip(transaction))]
+pub async fn set_role(
+    username: &str,
+    role: Role,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), UserError> {
+    if role != Role::Owner {
+        ensure_another_owner_remains(username, transaction).await?;
+    }
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE users
+        SET role = $1
+        WHERE username = $2
+        "#,
+        role.as_str(),
+        username
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("failed to change the role of the user")?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Err(UserError::UnknownUser(username.into()));
+    }
+    Ok(())
+}
+
+/// Removes the user along with the responses saved for their idempotent requests, as part of
+/// the caller's transaction. Refuses to remove the last owner, like `set_role`.
+#[tracing::instrument(name = "delete user", skip(transaction))]
+pub async fn delete_user(
+    username: &str,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), UserError> {
+    ensure_another_owner_remains(username, transaction).await?;
+    let user_id = get_user_id(username, &mut *transaction).await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("failed to delete the saved responses of the user")?;
+    sqlx::query!(
+        r#"
+        DELETE FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("failed to delete the user")?;
+    Ok(())
+}
+
+async fn ensure_another_owner_remains(
+    username: &str,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), UserError> {
+    // locking the owners keeps two concurrent changes from removing the last two of them
+    let owners = sqlx::query!(
+        r#"
+        SELECT username
+        FROM users
+        WHERE role = 'owner'
+        FOR UPDATE
+        "#
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("failed to retrieve the owners")?;
+    if owners.len() == 1 && owners[0].username == username {
+        return Err(UserError::LastOwner(username.into()));
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "list users", skip(pool))]
+pub async fn list_users(pool: &PgPool) -> Result<Vec<User>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT user_id, username, role
+        FROM users
+        ORDER BY username
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to list the users")?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(User {
+                user_id: r.user_id,
+                username: r.username,
+                role: Role::try_from(r.role).map_err(anyhow::Error::msg)?,
+            })
+        })
+        .collect()
+}
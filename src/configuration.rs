@@ -1,7 +1,10 @@
 use std::time::Duration;
 
+use actix_web::cookie::SameSite;
 use secrecy::{ExposeSecret, Secret};
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde_aux::field_attributes::{
+    deserialize_number_from_string, deserialize_option_number_from_string,
+};
 use sqlx::{
     postgres::{PgConnectOptions, PgSslMode},
     ConnectOptions,
@@ -15,6 +18,122 @@ pub struct Settings {
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
     pub redis_uri: Secret<String>,
+    #[serde(default)]
+    pub delivery_rate_limit: DeliveryRateLimitSettings,
+    pub delivery_worker: DeliveryWorkerSettings,
+    pub login_throttling: LoginThrottlingSettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub password_policy: PasswordPolicySettings,
+    pub session: SessionSettings,
+}
+
+/// Admin sessions end after a period of inactivity and, regardless of activity, once they
+/// reach their maximum lifetime.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SessionSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub idle_timeout_minutes: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_lifetime_hours: i64,
+    /// Only send the session cookie over https - has to be off locally.
+    pub cookie_secure: bool,
+    pub cookie_same_site: CookieSameSite,
+}
+
+impl SessionSettings {
+    pub fn idle_timeout(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.idle_timeout_minutes)
+    }
+
+    pub fn max_lifetime(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.max_lifetime_hours)
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CookieSameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl From<CookieSameSite> for SameSite {
+    fn from(same_site: CookieSameSite) -> Self {
+        match same_site {
+            CookieSameSite::Strict => SameSite::Strict,
+            CookieSameSite::Lax => SameSite::Lax,
+            CookieSameSite::None => SameSite::None,
+        }
+    }
+}
+
+/// The rules for newly chosen passwords. Lengths count user-perceived characters.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct PasswordPolicySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_length: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_length: usize,
+    /// The minimal estimated strength - roughly the log2 of the guesses needed to find it.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_strength_bits: f64,
+}
+
+/// The Argon2id parameters for new password hashes. Hashes created with other parameters are
+/// upgraded the next time their user logs in.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct PasswordHashingSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub memory_cost_kib: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub time_cost: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub parallelism: u32,
+}
+
+/// Failed logins are counted per username and per client IP. Once either reaches its limit
+/// within the window, further logins are refused until the lockout is over.
+/// Password reset requests are counted the same way, but only stop further reset emails.
+#[derive(serde::Deserialize, Clone)]
+pub struct LoginThrottlingSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_username: i32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_ip: i32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub failure_window_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_password_resets_per_username: i32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_password_resets_per_ip: i32,
+    /// Take the client IP from the last `X-Forwarded-For` entry instead of the peer address.
+    /// Only enable this behind a proxy that appends to the header, clients could fake it otherwise.
+    pub trust_forwarded_for: bool,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct DeliveryWorkerSettings {
+    /// The number of worker loops running concurrently within the process.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub n_workers: usize,
+    /// The number of queued deliveries each worker claims per transaction.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: i64,
+}
+
+/// How many emails may be handed to the email provider within each window.
+/// Windows without a limit are not restricted.
+#[derive(serde::Deserialize, Clone, Default)]
+pub struct DeliveryRateLimitSettings {
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub per_second: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub per_hour: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub per_day: Option<u32>,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -53,6 +172,15 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// How long in-flight requests and deliveries get to finish once a shutdown is requested.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_timeout_seconds: u64,
+}
+
+impl ApplicationSettings {
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_seconds)
+    }
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -64,6 +192,8 @@ pub struct DataBaseSettings {
     pub host: String,
     pub database_name: String,
     pub require_ssl: bool,
+    /// Otherwise the migrations have to be applied with `zero_2_prod migrate up` before starting.
+    pub run_migrations_on_startup: bool,
 }
 
 impl DataBaseSettings {
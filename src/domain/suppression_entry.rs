@@ -0,0 +1,146 @@
+use std::fmt::Display;
+
+use super::subscriber_email::SubscriberEmail;
+
+/// An address or a whole domain which must never receive any mail from us.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SuppressionEntry {
+    Address(String),
+    Domain(String),
+}
+
+impl SuppressionEntry {
+    /// Entries containing an '@' are treated as addresses, everything else as a domain.
+    /// A leading '@' (e.g. "@example.com") is accepted as a way to spell out a domain.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim().to_lowercase();
+        if let Some(domain) = s.strip_prefix('@') {
+            return parse_domain(domain).map(Self::Domain);
+        }
+        if s.contains('@') {
+            let email = SubscriberEmail::parse(s)?;
+            Ok(Self::Address(email.as_ref().to_owned()))
+        } else {
+            parse_domain(&s).map(Self::Domain)
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SuppressionEntry::Address(_) => "address",
+            SuppressionEntry::Domain(_) => "domain",
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        match self {
+            SuppressionEntry::Address(v) | SuppressionEntry::Domain(v) => v,
+        }
+    }
+}
+
+impl Display for SuppressionEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.value().fmt(f)
+    }
+}
+
+fn parse_domain(s: &str) -> Result<String, String> {
+    let is_valid = !s.is_empty()
+        && s.len() <= 253
+        && s.contains('.')
+        && s.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if is_valid {
+        Ok(s.to_owned())
+    } else {
+        Err(format!(
+            "'{s}' is neither a valid email address nor a valid domain"
+        ))
+    }
+}
+
+/// Why an entry ended up on the suppression list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionReason {
+    Manual,
+    Bounce,
+    Legal,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::Manual => "manual",
+            SuppressionReason::Bounce => "bounce",
+            SuppressionReason::Legal => "legal",
+        }
+    }
+}
+
+impl TryFrom<String> for SuppressionReason {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "manual" => Ok(Self::Manual),
+            "bounce" => Ok(Self::Bounce),
+            "legal" => Ok(Self::Legal),
+            other => Err(format!(
+                "{other} is not a supported suppression reason. Use 'manual', 'bounce' or 'legal'"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok_eq};
+
+    use crate::domain::suppression_entry::SuppressionEntry;
+
+    #[test]
+    fn an_email_address_is_parsed_as_an_address() {
+        assert_ok_eq!(
+            SuppressionEntry::parse("Ursula@Example.com"),
+            SuppressionEntry::Address("ursula@example.com".into())
+        );
+    }
+
+    #[test]
+    fn a_bare_domain_is_parsed_as_a_domain() {
+        assert_ok_eq!(
+            SuppressionEntry::parse(" example.com "),
+            SuppressionEntry::Domain("example.com".into())
+        );
+    }
+
+    #[test]
+    fn a_domain_with_a_leading_at_is_parsed_as_a_domain() {
+        assert_ok_eq!(
+            SuppressionEntry::parse("@mail.example.com"),
+            SuppressionEntry::Domain("mail.example.com".into())
+        );
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(SuppressionEntry::parse(""));
+    }
+
+    #[test]
+    fn invalid_addresses_are_rejected() {
+        assert_err!(SuppressionEntry::parse("ursula@"));
+    }
+
+    #[test]
+    fn invalid_domains_are_rejected() {
+        for domain in ["localhost", "exa mple.com", "-example.com", "example..com"] {
+            assert_err!(SuppressionEntry::parse(domain));
+        }
+    }
+}
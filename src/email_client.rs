@@ -6,6 +6,8 @@ use secrecy::{ExposeSecret, Secret};
 use crate::domain::subscriber_email::SubscriberEmail;
 
 const POSTMARK_HEADER: &str = "X-Postmark-Server-Token";
+/// The most messages the provider accepts in a single batch request.
+pub const MAX_BATCH_SIZE: usize = 500;
 
 pub struct EmailClient {
     http_client: Client,
@@ -36,24 +38,120 @@ impl EmailClient {
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<DeliveryReceipt, reqwest::Error> {
         let url = self.base_url.join("email").expect("failed joining url");
         let request_body = SendEmailRequest {
             from: self.sender.as_ref(),
             to: recipient.as_ref(),
-            subject: subject,
+            subject,
             html_body: html_content,
             text_body: text_content,
         };
-        self.http_client
+        let response = self
+            .http_client
             .post(url)
             .header(POSTMARK_HEADER, self.authorization_token.expose_secret())
             .json(&request_body)
             .send()
             .await?
             .error_for_status()?;
-        Ok(())
+        // the email has been accepted at this point, so a body we cannot make sense of is not an error
+        let message_id = response
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .map(|r| r.message_id);
+        Ok(DeliveryReceipt { message_id })
     }
+
+    /// Sends up to [`MAX_BATCH_SIZE`] emails with a single request.
+    /// The provider accepts or rejects each email separately, so there is one result per email,
+    /// in the order of `emails`.
+    pub async fn send_batch(
+        &self,
+        emails: &[OutgoingEmail<'_>],
+    ) -> Result<Vec<Result<DeliveryReceipt, RejectedEmail>>, SendBatchError> {
+        if emails.len() > MAX_BATCH_SIZE {
+            return Err(SendBatchError::TooManyEmails(emails.len()));
+        }
+        let url = self
+            .base_url
+            .join("email/batch")
+            .expect("failed joining url");
+        let request_body: Vec<_> = emails
+            .iter()
+            .map(|email| SendEmailRequest {
+                from: self.sender.as_ref(),
+                to: email.recipient.as_ref(),
+                subject: email.subject,
+                html_body: email.html_content,
+                text_body: email.text_content,
+            })
+            .collect();
+        let response = self
+            .http_client
+            .post(url)
+            .header(POSTMARK_HEADER, self.authorization_token.expose_secret())
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+        // unlike for single emails, we cannot tell which emails were accepted without the body
+        let results = response.json::<Vec<SendBatchResponseEntry>>().await?;
+        if results.len() != emails.len() {
+            return Err(SendBatchError::UnexpectedResultCount {
+                n_emails: emails.len(),
+                n_results: results.len(),
+            });
+        }
+        Ok(results
+            .into_iter()
+            .map(|r| {
+                if r.error_code != 0 {
+                    Err(RejectedEmail {
+                        error_code: r.error_code,
+                        message: r.message,
+                    })
+                } else {
+                    Ok(DeliveryReceipt {
+                        message_id: r.message_id,
+                    })
+                }
+            })
+            .collect())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SendBatchError {
+    #[error("a batch holds at most {MAX_BATCH_SIZE} emails, got {0}")]
+    TooManyEmails(usize),
+    #[error("the provider returned {n_results} results for {n_emails} emails")]
+    UnexpectedResultCount { n_emails: usize, n_results: usize },
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+}
+
+/// An email to be sent as part of a batch.
+pub struct OutgoingEmail<'a> {
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+}
+
+/// What the email provider tells us about an email it has accepted.
+#[derive(Debug)]
+pub struct DeliveryReceipt {
+    pub message_id: Option<String>,
+}
+
+/// An email of a batch which the email provider refused to send.
+#[derive(Debug, thiserror::Error)]
+#[error("the email was rejected by the provider ({error_code}): {message}")]
+pub struct RejectedEmail {
+    pub error_code: i64,
+    pub message: String,
 }
 
 fn to_url(base_url: &str) -> Url {
@@ -76,11 +174,27 @@ struct SendEmailRequest<'a> {
     text_body: &'a str,
 }
 
+#[derive(serde::Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendBatchResponseEntry {
+    error_code: i64,
+    #[serde(default)]
+    message: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
 
-    use crate::email_client::EmailClient;
+    use crate::email_client::{EmailClient, OutgoingEmail};
     use claim::{assert_err, assert_ok};
     use fake::{
         faker::{
@@ -174,6 +288,34 @@ mod tests {
         assert_ok!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_email_returns_the_message_id_assigned_by_the_server() {
+        let mock_server = MockServer::start().await; // spins up mock server
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "To": "receiver@example.com",
+            "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+            "ErrorCode": 0,
+            "Message": "OK"
+        }));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let receipt = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            receipt.message_id.as_deref(),
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+        );
+    }
+
     #[tokio::test]
     async fn send_email_fails_if_the_server_returns_500() {
         let mock_server = MockServer::start().await; // spins up mock server
@@ -213,4 +355,132 @@ mod tests {
 
         // no explicit assert, since the 'expect' of the mock will do the job here
     }
+
+    #[tokio::test]
+    async fn send_batch_returns_a_result_per_email() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {
+                "ErrorCode": 0,
+                "Message": "OK",
+                "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+                "To": "first@example.com"
+            },
+            {
+                "ErrorCode": 406,
+                "Message": "You tried to send to a recipient that has been marked as inactive."
+            }
+        ]));
+        Mock::given(header_exists(POSTMARK_HEADER))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (first, second) = (email(), email());
+        let (subject, content) = (subject(), content());
+        let emails: Vec<_> = [&first, &second]
+            .into_iter()
+            .map(|recipient| OutgoingEmail {
+                recipient,
+                subject: &subject,
+                html_content: &content,
+                text_content: &content,
+            })
+            .collect();
+        let results = email_client.send_batch(&emails).await.unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[0].as_ref().unwrap().message_id.as_deref(),
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+        );
+        assert_eq!(results[1].as_ref().unwrap_err().error_code, 406);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 2);
+        assert_eq!(body[1]["To"], second.as_ref());
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_if_a_result_is_missing() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK", "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817"}
+        ]));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (first, second) = (email(), email());
+        let (subject, content) = (subject(), content());
+        let emails: Vec<_> = [&first, &second]
+            .into_iter()
+            .map(|recipient| OutgoingEmail {
+                recipient,
+                subject: &subject,
+                html_content: &content,
+                text_content: &content,
+            })
+            .collect();
+        let outcome = email_client.send_batch(&emails).await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_if_the_response_cannot_be_parsed() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (recipient, subject, content) = (email(), subject(), content());
+        let outcome = email_client
+            .send_batch(&[OutgoingEmail {
+                recipient: &recipient,
+                subject: &subject,
+                html_content: &content,
+                text_content: &content,
+            }])
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let (recipient, subject, content) = (email(), subject(), content());
+        let outcome = email_client
+            .send_batch(&[OutgoingEmail {
+                recipient: &recipient,
+                subject: &subject,
+                html_content: &content,
+                text_content: &content,
+            }])
+            .await;
+
+        assert_err!(outcome);
+    }
 }
@@ -5,6 +5,7 @@ use uuid::Uuid;
 
 use super::IdempotencyKey;
 
+#[allow(clippy::large_enum_variant)]
 pub enum NextAction {
     StartProcessing(Transaction<'static, Postgres>),
     ReturnSavedResponse(HttpResponse),
@@ -121,7 +122,7 @@ pub async fn get_saved_response(
     .fetch_optional(pool)
     .await?;
 
-    let Some(r) = saved_response else{
+    let Some(r) = saved_response else {
         return Ok(None);
     };
 
@@ -0,0 +1,331 @@
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    Pending,
+    Sent,
+    Failed,
+    Skipped,
+    Cancelled,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Skipped => "skipped",
+            DeliveryStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Whether the workers should keep fanning out an issue to its recipients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueDeliveryState {
+    Active,
+    Paused,
+    Cancelled,
+}
+
+impl IssueDeliveryState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueDeliveryState::Active => "active",
+            IssueDeliveryState::Paused => "paused",
+            IssueDeliveryState::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl TryFrom<String> for IssueDeliveryState {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "active" => Ok(Self::Active),
+            "paused" => Ok(Self::Paused),
+            "cancelled" => Ok(Self::Cancelled),
+            other => Err(format!("{other} is not a valid issue delivery state")),
+        }
+    }
+}
+
+/// Stops the workers from picking up further deliveries of the issue until it is resumed.
+/// Returns `false` if the delivery was not active.
+#[tracing::instrument(name = "pause issue delivery", skip(executor))]
+pub async fn pause_delivery(
+    executor: impl PgExecutor<'_>,
+    issue_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET delivery_state = 'paused'
+        WHERE
+            newsletter_issue_id = $1 AND
+            delivery_state = 'active'
+        "#,
+        issue_id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+/// Returns `false` if the delivery was not paused.
+#[tracing::instrument(name = "resume issue delivery", skip(executor))]
+pub async fn resume_delivery(
+    executor: impl PgExecutor<'_>,
+    issue_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET delivery_state = 'active'
+        WHERE
+            newsletter_issue_id = $1 AND
+            delivery_state = 'paused'
+        "#,
+        issue_id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+/// Drops all deliveries of the issue which have not been processed yet, as part of the
+/// caller's transaction. Returns `false` if the delivery had already been cancelled.
+#[tracing::instrument(name = "cancel issue delivery", skip(transaction))]
+pub async fn cancel_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET delivery_state = 'cancelled'
+        WHERE
+            newsletter_issue_id = $1 AND
+            delivery_state IN ('active', 'paused')
+        "#,
+        issue_id
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Ok(false);
+    }
+    // deliveries which a worker is in the middle of stay locked until its batch is done, so
+    // this waits for the batch and only removes what is left
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET
+            status = 'cancelled',
+            updated_at = now()
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'pending'
+        "#,
+        issue_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(true)
+}
+
+/// The state of the delivery of an issue to a single recipient after a worker has processed it.
+pub struct DeliveryRecord {
+    pub status: DeliveryStatus,
+    pub n_attempts: i16,
+    pub provider_message_id: Option<String>,
+    pub last_error: Option<String>,
+    pub tracking_token: Option<String>,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn record_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    email: &str,
+    record: DeliveryRecord,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries(
+            newsletter_issue_id,
+            subscriber_email,
+            status,
+            n_attempts,
+            provider_message_id,
+            last_error,
+            tracking_token,
+            created_at,
+            updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, now(), now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            status = EXCLUDED.status,
+            n_attempts = EXCLUDED.n_attempts,
+            provider_message_id = EXCLUDED.provider_message_id,
+            last_error = EXCLUDED.last_error,
+            tracking_token = EXCLUDED.tracking_token,
+            updated_at = EXCLUDED.updated_at
+        "#,
+        issue_id,
+        email,
+        record.status.as_str(),
+        record.n_attempts,
+        record.provider_message_id,
+        record.last_error,
+        record.tracking_token
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct DeliveryStats {
+    pub n_pending: i64,
+    pub n_sent: i64,
+    pub n_failed: i64,
+    pub n_skipped: i64,
+    pub n_cancelled: i64,
+}
+
+impl DeliveryStats {
+    pub fn n_total(&self) -> i64 {
+        self.n_pending + self.n_processed()
+    }
+
+    pub fn n_processed(&self) -> i64 {
+        self.n_sent + self.n_failed + self.n_skipped + self.n_cancelled
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.n_pending > 0
+    }
+
+    /// Share of the recipients which have been processed, in percent.
+    pub fn progress(&self) -> f64 {
+        if self.n_total() == 0 {
+            100.0
+        } else {
+            self.n_processed() as f64 * 100.0 / self.n_total() as f64
+        }
+    }
+}
+
+#[tracing::instrument(name = "get delivery stats", skip(pool))]
+pub async fn get_delivery_stats(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<DeliveryStats, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE status = 'pending') AS "n_pending!",
+            COUNT(*) FILTER (WHERE status = 'sent') AS "n_sent!",
+            COUNT(*) FILTER (WHERE status = 'failed') AS "n_failed!",
+            COUNT(*) FILTER (WHERE status = 'skipped') AS "n_skipped!",
+            COUNT(*) FILTER (WHERE status = 'cancelled') AS "n_cancelled!"
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(DeliveryStats {
+        n_pending: r.n_pending,
+        n_sent: r.n_sent,
+        n_failed: r.n_failed,
+        n_skipped: r.n_skipped,
+        n_cancelled: r.n_cancelled,
+    })
+}
+
+pub struct FailedDelivery {
+    pub subscriber_email: String,
+    pub n_attempts: i16,
+    pub last_error: Option<String>,
+}
+
+#[tracing::instrument(name = "list failed deliveries", skip(pool))]
+pub async fn list_failed_deliveries(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Vec<FailedDelivery>, sqlx::Error> {
+    sqlx::query_as!(
+        FailedDelivery,
+        r#"
+        SELECT subscriber_email, n_attempts, last_error
+        FROM issue_deliveries
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'failed'
+        ORDER BY updated_at DESC
+        LIMIT 100
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::issue_deliveries::DeliveryStats;
+
+    #[test]
+    fn progress_counts_every_processed_recipient() {
+        let stats = DeliveryStats {
+            n_pending: 5,
+            n_sent: 3,
+            n_failed: 1,
+            n_skipped: 1,
+            n_cancelled: 0,
+        };
+        assert_eq!(stats.n_total(), 10);
+        assert_eq!(stats.progress(), 50.0);
+        assert!(stats.is_running());
+    }
+
+    #[test]
+    fn cancelled_deliveries_count_as_processed() {
+        let stats = DeliveryStats {
+            n_pending: 0,
+            n_sent: 1,
+            n_failed: 0,
+            n_skipped: 1,
+            n_cancelled: 2,
+        };
+        assert_eq!(stats.n_processed(), 4);
+        assert_eq!(stats.progress(), 100.0);
+        assert!(!stats.is_running());
+    }
+
+    #[test]
+    fn an_issue_without_recipients_is_complete() {
+        let stats = DeliveryStats::default();
+        assert_eq!(stats.progress(), 100.0);
+        assert!(!stats.is_running());
+    }
+}
@@ -1,84 +1,460 @@
-use std::time::Duration;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
 
-use sqlx::{PgPool, Postgres, Transaction};
-use tracing::{field::display, Span};
+use sqlx::{postgres::PgListener, Acquire, PgPool, Postgres, Transaction};
+use tokio::{sync::watch, task::JoinSet};
 use uuid::Uuid;
 
 use crate::{
-    configuration::Settings, domain::subscriber_email::SubscriberEmail, email_client::EmailClient,
+    configuration::Settings,
+    domain::subscriber_email::SubscriberEmail,
+    email_client::{DeliveryReceipt, EmailClient, OutgoingEmail, MAX_BATCH_SIZE},
+    issue_deliveries::{record_delivery, DeliveryRecord, DeliveryStatus, IssueDeliveryState},
+    rate_limit::{DeliveryRateLimiter, RateLimitDecision},
+    shutdown::Shutdown,
     startup::get_connection_pool,
+    suppression::is_suppressed,
+    tracking::{add_tracking, generate_tracking_token},
 };
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+const MAX_DELIVERY_ATTEMPTS: i16 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(30);
+/// Notified whenever deliveries are added to the queue or a paused issue is resumed.
+const DELIVERY_CHANNEL: &str = "issue_delivery_queue";
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
 struct NewsletterIssue {
     title: String,
     content: String,
+    tracking_enabled: bool,
+    delivery_state: IssueDeliveryState,
 }
 
 pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
+    RateLimited { retry_after: Duration },
+}
+
+struct Task {
+    issue_id: Uuid,
+    email: String,
+    n_retries: i16,
 }
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+/// Runs the configured number of worker loops side by side until a shutdown is requested.
+/// Each worker finishes - and commits - the batch it is working on before it stops.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database).await;
-    let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    let email_client = Arc::new(configuration.email_client.client());
+    let rate_limiter = DeliveryRateLimiter::new(&configuration.delivery_rate_limit);
+    let settings = configuration.delivery_worker;
+
+    let (wake_up_sender, wake_up) = watch::channel(false);
+
+    let mut workers = JoinSet::new();
+    workers.spawn(listen_for_new_tasks(
+        connection_pool.clone(),
+        wake_up_sender,
+        shutdown.clone(),
+    ));
+    for _ in 0..settings.n_workers.max(1) {
+        workers.spawn(worker_loop(
+            connection_pool.clone(),
+            Arc::clone(&email_client),
+            rate_limiter.clone(),
+            configuration.application.base_url.clone(),
+            settings.batch_size,
+            wake_up.clone(),
+            shutdown.clone(),
+        ));
+    }
+    while let Some(outcome) = workers.join_next().await {
+        outcome??;
+    }
+    Ok(())
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&pool, &email_client).await {
-            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
-            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<EmailClient>,
+    rate_limiter: DeliveryRateLimiter,
+    base_url: String,
+    batch_size: i64,
+    mut wake_up: watch::Receiver<bool>,
+    mut shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_requested() {
+        // notifications arriving from here on end the wait after an empty dequeue
+        let is_listening = *wake_up.borrow_and_update();
+        match try_execute_task(&pool, &email_client, &rate_limiter, &base_url, batch_size).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                let timeout = if is_listening {
+                    time_until_next_task(&pool)
+                        .await
+                        .unwrap_or(Some(POLL_INTERVAL))
+                } else {
+                    Some(POLL_INTERVAL)
+                };
+                unless_shut_down(&mut shutdown, wait_for_wake_up(&mut wake_up, timeout)).await
+            }
+            Ok(ExecutionOutcome::RateLimited { retry_after }) => {
+                tracing::info!(
+                    "the delivery budget is exhausted - Waiting {}s",
+                    retry_after.as_secs_f64()
+                );
+                unless_shut_down(&mut shutdown, tokio::time::sleep(retry_after)).await
+            }
+            Err(_) => {
+                unless_shut_down(&mut shutdown, tokio::time::sleep(Duration::from_secs(1))).await
+            }
             Ok(ExecutionOutcome::TaskCompleted) => {}
         }
     }
+    Ok(())
 }
 
-#[tracing::instrument(
-    skip_all,
-    fields(
-        newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty
-    ),
-    err
-)]
+/// Waits for `future` to complete, but gives up as soon as a shutdown is requested.
+async fn unless_shut_down(shutdown: &mut Shutdown, future: impl Future<Output = ()>) {
+    tokio::select! {
+        _ = shutdown.requested() => {},
+        _ = future => {},
+    }
+}
+
+/// Keeps the workers informed about new deliveries. While the listener is down, the workers
+/// fall back to polling the queue.
+async fn listen_for_new_tasks(
+    pool: PgPool,
+    wake_up: watch::Sender<bool>,
+    mut shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_requested() {
+        let listen = async {
+            if let Err(e) = forward_notifications(&pool, &wake_up).await {
+                tracing::warn!(error.cause_chain = ?e, error.message = %e, "failed to listen for new deliveries - Falling back to polling");
+            }
+            wake_up.send_replace(false);
+            tokio::time::sleep(POLL_INTERVAL).await;
+        };
+        unless_shut_down(&mut shutdown, listen).await;
+    }
+    Ok(())
+}
+
+async fn forward_notifications(
+    pool: &PgPool,
+    wake_up: &watch::Sender<bool>,
+) -> Result<(), sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(DELIVERY_CHANNEL).await?;
+    // deliveries queued while nobody was listening are picked up right away
+    wake_up.send_replace(true);
+    loop {
+        // `None` means the connection was lost and notifications may have been missed -
+        // the listener reconnects on the next call
+        listener.try_recv().await?;
+        wake_up.send_replace(true);
+    }
+}
+
+async fn wait_for_wake_up(wake_up: &mut watch::Receiver<bool>, timeout: Option<Duration>) {
+    let woken_up = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, wake_up.changed())
+            .await
+            .unwrap_or(Ok(())),
+        None => wake_up.changed().await,
+    };
+    if woken_up.is_err() {
+        // the listener is gone for good
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// How long until the next queued delivery is due, `None` if there is nothing to deliver.
+/// Due deliveries are locked by other workers, so we check back on them a second later.
+#[tracing::instrument(skip_all)]
+async fn time_until_next_task(pool: &PgPool) -> Result<Option<Duration>, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            EXTRACT(EPOCH FROM MIN(q.execute_after) - now())::DOUBLE PRECISION AS seconds
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE i.delivery_state = 'active'
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(r.seconds
+        .map(|seconds| Duration::from_secs_f64(seconds.max(1.0))))
+}
+
+/// Dequeues up to `batch_size` tasks and executes them within a single transaction, which keeps
+/// them locked until each of them has either been removed from the queue or rescheduled.
+/// The outcome of each task is recorded in a savepoint of its own, so that failing to record
+/// one of them does not roll back the others. Tasks which have not been worked on are left
+/// untouched and are due again as soon as the transaction ends.
+/// All the tasks of a batch belong to the same issue, so that their emails can be handed to the
+/// email provider with as few requests as possible.
+#[tracing::instrument(skip_all, err)]
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    rate_limiter: &DeliveryRateLimiter,
+    base_url: &str,
+    batch_size: i64,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let Some((transaction, issue_id, email)) = dequeue_task(pool).await? else{
+    let (mut transaction, tasks) = dequeue_tasks(pool, batch_size).await?;
+    if tasks.is_empty() {
         return Ok(ExecutionOutcome::EmptyQueue);
-    };
-    Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
-    match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(&email, &issue.title, &issue.content, &issue.content)
-                .await
+    }
+    let mut outcome = ExecutionOutcome::TaskCompleted;
+    let mut issues = HashMap::new();
+    let mut batches: HashMap<Uuid, Vec<PreparedDelivery>> = HashMap::new();
+    for task in tasks {
+        // nothing has been sent yet - should the preparation fail, dropping the transaction
+        // puts the whole batch back into the queue
+        match prepare_delivery(
+            &mut transaction,
+            pool,
+            rate_limiter,
+            base_url,
+            &mut issues,
+            task,
+        )
+        .await?
+        {
+            Preparation::Ready(delivery) => batches
+                .entry(delivery.task.issue_id)
+                .or_default()
+                .push(delivery),
+            Preparation::Done => {}
+            Preparation::RateLimited { retry_after } => {
+                outcome = ExecutionOutcome::RateLimited { retry_after };
+                break;
+            }
+            // the other tasks of the batch belong to the same issue - they become due again
+            // once the issue is resumed
+            Preparation::Paused => break,
+        }
+    }
+    let mut failure = Ok(());
+    'sending: for (issue_id, deliveries) in &batches {
+        for chunk in deliveries.chunks(MAX_BATCH_SIZE) {
+            if let Err(e) =
+                send_batch(&mut transaction, email_client, &issues[issue_id], chunk).await
             {
-                tracing::error!(error.cause_chain = ?e, error.message = %e, "failed to deliver issue to a confirmend subscriber - Skipping");
+                // the outcomes which could be recorded are kept, the remaining chunks are not
+                // sent and stay in the queue
+                failure = Err(e);
+                break 'sending;
+            }
+        }
+    }
+    transaction.commit().await?;
+    failure.map(|_| outcome)
+}
+
+/// A task whose email is ready to be handed to the email provider.
+struct PreparedDelivery {
+    task: Task,
+    recipient: SubscriberEmail,
+    html_content: String,
+    tracking_token: Option<String>,
+}
+
+enum Preparation {
+    Ready(PreparedDelivery),
+    Done,
+    RateLimited { retry_after: Duration },
+    Paused,
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id=%task.issue_id,
+        subscriber_email=%task.email
+    )
+)]
+async fn prepare_delivery(
+    transaction: &mut PgTransaction,
+    pool: &PgPool,
+    rate_limiter: &DeliveryRateLimiter,
+    base_url: &str,
+    issues: &mut HashMap<Uuid, NewsletterIssue>,
+    task: Task,
+) -> Result<Preparation, anyhow::Error> {
+    let record = match SubscriberEmail::parse(task.email.clone()) {
+        Ok(email) if is_suppressed(pool, email.as_ref()).await? => {
+            tracing::info!(
+                "skipping a confirmed subscriber - Their email is on the suppression list"
+            );
+            DeliveryRecord {
+                status: DeliveryStatus::Skipped,
+                n_attempts: task.n_retries,
+                provider_message_id: None,
+                last_error: Some("the email is on the suppression list".into()),
+                tracking_token: None,
+            }
+        }
+        Ok(recipient) => {
+            if let RateLimitDecision::Exhausted { retry_after } =
+                rate_limiter.try_acquire(pool).await?
+            {
+                return Ok(Preparation::RateLimited { retry_after });
+            }
+            // the issue may have been stopped after its tasks have been dequeued
+            let issue = match issues.entry(task.issue_id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(get_issue(pool, task.issue_id).await?),
+            };
+            match issue.delivery_state {
+                IssueDeliveryState::Paused => return Ok(Preparation::Paused),
+                IssueDeliveryState::Cancelled => {
+                    tracing::info!(
+                        "skipping a confirmed subscriber - The delivery has been cancelled"
+                    );
+                    DeliveryRecord {
+                        status: DeliveryStatus::Cancelled,
+                        n_attempts: task.n_retries,
+                        provider_message_id: None,
+                        last_error: None,
+                        tracking_token: None,
+                    }
+                }
+                IssueDeliveryState::Active => {
+                    let tracking_token = if issue.tracking_enabled
+                        && subscriber_allows_tracking(pool, recipient.as_ref()).await?
+                    {
+                        Some(generate_tracking_token())
+                    } else {
+                        None
+                    };
+                    let html_content = match &tracking_token {
+                        Some(token) => add_tracking(&issue.content, base_url, token),
+                        None => issue.content.clone(),
+                    };
+                    return Ok(Preparation::Ready(PreparedDelivery {
+                        task,
+                        recipient,
+                        html_content,
+                        tracking_token,
+                    }));
+                }
             }
         }
         Err(e) => {
             tracing::error!(error.cause_chain = ?e, error.message = %e, "skipping a confirmed subscriber. - Their stored contact details are invalid");
+            DeliveryRecord {
+                status: DeliveryStatus::Failed,
+                n_attempts: task.n_retries,
+                provider_message_id: None,
+                last_error: Some(e),
+                tracking_token: None,
+            }
+        }
+    };
+    finish_task(transaction, task.issue_id, &task.email, record).await?;
+    Ok(Preparation::Done)
+}
+
+#[tracing::instrument(skip_all, fields(batch_size=deliveries.len()))]
+async fn send_batch(
+    transaction: &mut PgTransaction,
+    email_client: &EmailClient,
+    issue: &NewsletterIssue,
+    deliveries: &[PreparedDelivery],
+) -> Result<(), anyhow::Error> {
+    let emails: Vec<_> = deliveries
+        .iter()
+        .map(|d| OutgoingEmail {
+            recipient: &d.recipient,
+            subject: &issue.title,
+            html_content: &d.html_content,
+            text_content: &issue.content,
+        })
+        .collect();
+    let results: Vec<_> = match email_client.send_batch(&emails).await {
+        Ok(results) => results
+            .into_iter()
+            .map(|r| r.map_err(|e| e.to_string()))
+            .collect(),
+        Err(e) => {
+            tracing::warn!(error.cause_chain = ?e, error.message = %e, "failed to send a batch of issue deliveries");
+            deliveries.iter().map(|_| Err(e.to_string())).collect()
+        }
+    };
+    // failing to record one outcome must not get in the way of recording the others
+    let mut outcome = Ok(());
+    for (delivery, result) in deliveries.iter().zip(results) {
+        if let Err(e) = complete_delivery(transaction, delivery, result).await {
+            outcome = Err(e);
         }
     }
-    delete_task(transaction, issue_id, &email).await?;
-    Ok(ExecutionOutcome::TaskCompleted)
+    outcome
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id=%delivery.task.issue_id,
+        subscriber_email=%delivery.task.email
+    )
+)]
+async fn complete_delivery(
+    transaction: &mut PgTransaction,
+    delivery: &PreparedDelivery,
+    result: Result<DeliveryReceipt, String>,
+) -> Result<(), anyhow::Error> {
+    let Task {
+        issue_id,
+        ref email,
+        n_retries,
+    } = delivery.task;
+    let n_attempts = n_retries + 1;
+    let record = match result {
+        Ok(receipt) => DeliveryRecord {
+            status: DeliveryStatus::Sent,
+            n_attempts,
+            provider_message_id: receipt.message_id,
+            last_error: None,
+            tracking_token: delivery.tracking_token.clone(),
+        },
+        Err(e) if n_attempts < MAX_DELIVERY_ATTEMPTS => {
+            tracing::warn!(error.message = %e, "failed to deliver issue to a confirmend subscriber - Retrying later");
+            retry_task(transaction, issue_id, email, n_attempts, e).await?;
+            return Ok(());
+        }
+        Err(e) => {
+            tracing::error!(error.message = %e, "failed to deliver issue to a confirmend subscriber - Giving up");
+            DeliveryRecord {
+                status: DeliveryStatus::Failed,
+                n_attempts,
+                provider_message_id: None,
+                last_error: Some(e),
+                tracking_token: None,
+            }
+        }
+    };
+    finish_task(transaction, issue_id, email, record).await
 }
 
 #[tracing::instrument(skip_all)]
 async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
-    let issue = sqlx::query_as!(
-        NewsletterIssue,
+    let r = sqlx::query!(
         r#"
-        SELECT title, content
+        SELECT title, content, tracking_enabled, delivery_state
         FROM newsletter_issues
         WHERE
             newsletter_issue_id = $1
@@ -87,39 +463,92 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     )
     .fetch_one(pool)
     .await?;
-    Ok(issue)
+    Ok(NewsletterIssue {
+        title: r.title,
+        content: r.content,
+        tracking_enabled: r.tracking_enabled,
+        delivery_state: r.delivery_state.try_into().map_err(anyhow::Error::msg)?,
+    })
+}
+
+#[tracing::instrument(skip_all)]
+async fn subscriber_allows_tracking(pool: &PgPool, email: &str) -> Result<bool, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT tracking_enabled
+        FROM subscriptions
+        WHERE email = $1
+        "#,
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(r.is_some_and(|r| r.tracking_enabled))
 }
 
+/// Locks due tasks of a single issue for the returned transaction. The other workers skip them
+/// until the transaction ends.
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
+async fn dequeue_tasks(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+    batch_size: i64,
+) -> Result<(PgTransaction, Vec<Task>), anyhow::Error> {
     let mut transaction = pool.begin().await?;
-    let r = sqlx::query!(
+    let tasks = sqlx::query!(
         r#"
-        SELECT newsletter_issue_id, subscriber_email
-        FROM issue_delivery_queue
-        FOR UPDATE
+        WITH next_issue AS (
+            SELECT q.newsletter_issue_id
+            FROM issue_delivery_queue q
+            JOIN newsletter_issues i USING (newsletter_issue_id)
+            WHERE
+                q.execute_after <= now() AND
+                i.delivery_state = 'active'
+            FOR UPDATE OF q
+            SKIP LOCKED
+            LIMIT 1
+        )
+        SELECT q.newsletter_issue_id, q.subscriber_email, q.n_retries
+        FROM issue_delivery_queue q
+        JOIN next_issue USING (newsletter_issue_id)
+        WHERE q.execute_after <= now()
+        FOR UPDATE OF q
         SKIP LOCKED
-        LIMIT 1
-        "#
+        LIMIT $1
+        "#,
+        batch_size
     )
-    .fetch_optional(&mut transaction)
-    .await?;
-    if let Some(r) = r {
-        Ok(Some((
-            transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-        )))
-    } else {
-        Ok(None)
+    .fetch_all(&mut transaction)
+    .await?
+    .into_iter()
+    .map(|r| Task {
+        issue_id: r.newsletter_issue_id,
+        email: r.subscriber_email,
+        n_retries: r.n_retries,
+    })
+    .collect();
+    Ok((transaction, tasks))
+}
+
+/// Records the final outcome of a task and removes it from the queue.
+#[tracing::instrument(skip_all)]
+async fn finish_task(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+    record: DeliveryRecord,
+) -> Result<(), anyhow::Error> {
+    let mut savepoint = transaction.begin().await?;
+    let outcome = async {
+        record_delivery(&mut savepoint, issue_id, email, record).await?;
+        delete_task(&mut savepoint, issue_id, email).await
     }
+    .await;
+    release_savepoint(savepoint, outcome).await
 }
 
 #[tracing::instrument(skip_all)]
 async fn delete_task(
-    mut transaction: PgTransaction,
+    transaction: &mut Transaction<'_, Postgres>,
     issue_id: Uuid,
     email: &str,
 ) -> Result<(), anyhow::Error> {
@@ -133,8 +562,63 @@ async fn delete_task(
         issue_id,
         email
     )
-    .execute(&mut transaction)
+    .execute(&mut *transaction)
     .await?;
-    transaction.commit().await?;
     Ok(())
 }
+
+/// Keeps a failed task in the queue, postponing its next execution with an exponential backoff.
+#[tracing::instrument(skip_all)]
+async fn retry_task(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+    n_attempts: i16,
+    error: String,
+) -> Result<(), anyhow::Error> {
+    let backoff_seconds = RETRY_BASE_DELAY.as_secs_f64() * 2f64.powi(i32::from(n_attempts) - 1);
+    let mut savepoint = transaction.begin().await?;
+    let outcome = async {
+        sqlx::query!(
+            r#"
+            UPDATE issue_delivery_queue
+            SET
+                n_retries = $3,
+                execute_after = now() + make_interval(secs => $4)
+            WHERE
+                newsletter_issue_id = $1 AND
+                subscriber_email = $2
+            "#,
+            issue_id,
+            email,
+            n_attempts,
+            backoff_seconds
+        )
+        .execute(&mut savepoint)
+        .await?;
+        let record = DeliveryRecord {
+            status: DeliveryStatus::Pending,
+            n_attempts,
+            provider_message_id: None,
+            last_error: Some(error),
+            tracking_token: None,
+        };
+        record_delivery(&mut savepoint, issue_id, email, record).await?;
+        Ok(())
+    }
+    .await;
+    release_savepoint(savepoint, outcome).await
+}
+
+/// Keeps the changes of a savepoint if they have all been made, rolls them back otherwise -
+/// the rest of the transaction goes on either way.
+async fn release_savepoint(
+    savepoint: Transaction<'_, Postgres>,
+    outcome: Result<(), anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    match outcome {
+        Ok(()) => savepoint.commit().await?,
+        Err(_) => savepoint.rollback().await?,
+    }
+    outcome
+}
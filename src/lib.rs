@@ -1,12 +1,20 @@
 pub mod admin;
+pub mod api;
+pub mod audit;
 pub mod authentication;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
 pub mod idempotency;
+pub mod issue_deliveries;
 pub mod issue_delivery_worker;
+pub mod migrations;
+pub mod rate_limit;
 pub mod routes;
 pub mod session_state;
+pub mod shutdown;
 pub mod startup;
+pub mod suppression;
 pub mod telemetry;
+pub mod tracking;
 pub mod utils;
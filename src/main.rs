@@ -1,31 +1,325 @@
-use std::fmt::{Debug, Display};
+use std::{
+    fmt::{Debug, Display},
+    io::IsTerminal,
+};
 
-use tokio::task::JoinError;
+use clap::{Parser, Subcommand};
+use secrecy::Secret;
+use tokio::task::{JoinError, JoinHandle};
 use zero_2_prod::{
-    configuration::get_configuration,
+    audit::{record_audit_event, AuditAction},
+    authentication::{
+        change_password, create_user, delete_user, disable_two_factor, get_user_id, list_users,
+        set_role, PasswordPolicy, Role,
+    },
+    configuration::{get_configuration, Settings},
+    domain::subscriber_email::SubscriberEmail,
     issue_delivery_worker::run_worker_until_stopped,
-    startup::Application,
+    migrations::{migration_status, prepare_database, run_migrations},
+    shutdown::{shutdown_channel, termination_signal},
+    startup::{get_connection_pool, Application},
     telemetry::{get_subscriber, init_subscriber},
 };
 
+#[derive(Parser)]
+#[command(about = "A newsletter service")]
+struct Cli {
+    /// Defaults to `all`
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the API only
+    Serve,
+    /// Deliver newsletter issues only
+    Worker {
+        /// Number of concurrent delivery workers, overrides the configuration
+        #[arg(long)]
+        workers: Option<usize>,
+    },
+    /// Serve the API and deliver newsletter issues from the same process
+    All {
+        /// Number of concurrent delivery workers, overrides the configuration
+        #[arg(long)]
+        workers: Option<usize>,
+    },
+    /// Manage the admin users
+    Admin {
+        #[command(subcommand)]
+        command: AdminCommand,
+    },
+    /// Manage the database schema
+    Migrate {
+        #[command(subcommand)]
+        command: MigrateCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum MigrateCommand {
+    /// Apply all pending migrations
+    Up,
+    /// List the migrations and whether they have been applied
+    Status,
+}
+
+#[derive(Subcommand)]
+enum AdminCommand {
+    /// Create an admin user - the password is prompted for, or read from stdin when piped
+    CreateUser {
+        username: String,
+        /// Needed to reset a forgotten password
+        #[arg(long)]
+        email: Option<String>,
+        /// One of owner, editor or viewer
+        #[arg(long, default_value = "owner")]
+        role: Role,
+    },
+    /// Set a new password for an admin user
+    ResetPassword { username: String },
+    /// Change what an admin user is allowed to do
+    SetRole {
+        username: String,
+        /// One of owner, editor or viewer
+        role: Role,
+    },
+    /// Turn off two-factor authentication for an admin user who lost their authenticator
+    DisableTwoFactor { username: String },
+    /// Delete an admin user
+    DeleteUser { username: String },
+    /// List all admin users
+    ListUsers,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let subscriber = get_subscriber("zero2prod", "info", std::io::stdout);
-    init_subscriber(subscriber);
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::All { workers: None });
+    match command {
+        // the output of admin commands is meant for humans, so only problems are logged
+        Command::Admin { .. } | Command::Migrate { .. } => {
+            init_subscriber(get_subscriber("zero2prod", "warn", std::io::stderr))
+        }
+        _ => init_subscriber(get_subscriber("zero2prod", "info", std::io::stdout)),
+    }
 
-    let configuration = get_configuration().expect("failed to read configuration");
-    let application = Application::build(configuration.clone()).await?;
-    let application_task = tokio::task::spawn(application.run_until_stopped());
-    let worker_task = tokio::task::spawn(run_worker_until_stopped(configuration));
+    let mut configuration = get_configuration().expect("failed to read configuration");
+    let (run_api, run_worker, n_workers) = match command {
+        Command::Serve => (true, false, None),
+        Command::Worker { workers } => (false, true, workers),
+        Command::All { workers } => (true, true, workers),
+        Command::Admin { command } => return run_admin_command(command, configuration).await,
+        Command::Migrate { command } => return run_migrate_command(command, configuration).await,
+    };
+    let pool = get_connection_pool(&configuration.database).await;
+    prepare_database(&configuration.database, &pool).await?;
+    if let Some(n_workers) = n_workers {
+        configuration.delivery_worker.n_workers = n_workers;
+    }
+    let shutdown_timeout = configuration.application.shutdown_timeout();
+    let (shutdown_trigger, shutdown) = shutdown_channel();
+
+    let mut server_handle = None;
+    let mut application_task = None;
+    if run_api {
+        let application = Application::build(configuration.clone()).await?;
+        server_handle = Some(application.handle());
+        application_task = Some(tokio::task::spawn(application.run_until_stopped()));
+    }
+    let mut worker_task =
+        run_worker.then(|| tokio::task::spawn(run_worker_until_stopped(configuration, shutdown)));
 
     tokio::select! {
-        o = application_task => report_exit("API", o),
-        o = worker_task => report_exit("Background Worker", o)
+        o = join(&mut application_task) => {
+            report_exit("API", o);
+            return Ok(());
+        },
+        o = join(&mut worker_task) => {
+            report_exit("Background Worker", o);
+            return Ok(());
+        },
+        _ = termination_signal() => {}
+    }
+
+    tracing::info!("shutting down - Waiting for in-flight requests and deliveries to finish");
+    shutdown_trigger.trigger();
+    // stops accepting connections right away, running requests get the shutdown timeout
+    let stop_server = server_handle.map(|handle| handle.stop(true));
+    let graceful_shutdown = async {
+        if let Some(stop_server) = stop_server {
+            stop_server.await;
+        }
+        if let Some(task) = application_task {
+            report_exit("API", task.await);
+        }
+        if let Some(task) = worker_task {
+            report_exit("Background Worker", task.await);
+        }
+    };
+    if tokio::time::timeout(shutdown_timeout, graceful_shutdown)
+        .await
+        .is_err()
+    {
+        tracing::warn!("shutdown timed out - Exiting anyway");
     }
 
     Ok(())
 }
 
+async fn run_admin_command(command: AdminCommand, configuration: Settings) -> anyhow::Result<()> {
+    let pool = get_connection_pool(&configuration.database).await;
+    let password_policy = PasswordPolicy::new(configuration.password_policy);
+    match command {
+        AdminCommand::CreateUser {
+            username,
+            email,
+            role,
+        } => {
+            let email = email
+                .map(SubscriberEmail::parse)
+                .transpose()
+                .map_err(anyhow::Error::msg)?;
+            let password = read_new_password(&username, &password_policy)?;
+            create_user(
+                &username,
+                email.as_ref(),
+                password,
+                role,
+                &configuration.password_hashing,
+                &pool,
+            )
+            .await?;
+            println!("Created the user {username} with the role {role}.");
+        }
+        AdminCommand::ResetPassword { username } => {
+            let user_id = get_user_id(&username, &pool).await?;
+            let password = read_new_password(&username, &password_policy)?;
+            let mut transaction = pool.begin().await?;
+            change_password(
+                user_id,
+                password,
+                None,
+                &configuration.password_hashing,
+                &mut transaction,
+            )
+            .await?;
+            record_audit_event(
+                Some(user_id),
+                None,
+                AuditAction::PasswordChanged,
+                serde_json::json!({ "via": "command line" }),
+                &mut transaction,
+            )
+            .await?;
+            transaction.commit().await?;
+            println!("Reset the password of {username}.");
+        }
+        AdminCommand::SetRole { username, role } => {
+            let mut transaction = pool.begin().await?;
+            set_role(&username, role, &mut transaction).await?;
+            record_audit_event(
+                None,
+                None,
+                AuditAction::UserRoleChanged,
+                serde_json::json!({ "username": username, "role": role.as_str(), "via": "command line" }),
+                &mut transaction,
+            )
+            .await?;
+            transaction.commit().await?;
+            println!("Changed the role of {username} to {role}.");
+        }
+        AdminCommand::DisableTwoFactor { username } => {
+            let mut transaction = pool.begin().await?;
+            let user_id = get_user_id(&username, &mut transaction).await?;
+            if disable_two_factor(user_id, &mut transaction).await? {
+                record_audit_event(
+                    None,
+                    None,
+                    AuditAction::TwoFactorDisabled,
+                    serde_json::json!({ "username": username, "via": "command line" }),
+                    &mut transaction,
+                )
+                .await?;
+                transaction.commit().await?;
+                println!("Disabled two-factor authentication for {username}.");
+            } else {
+                println!("{username} has not enabled two-factor authentication.");
+            }
+        }
+        AdminCommand::DeleteUser { username } => {
+            let mut transaction = pool.begin().await?;
+            delete_user(&username, &mut transaction).await?;
+            record_audit_event(
+                None,
+                None,
+                AuditAction::UserDeleted,
+                serde_json::json!({ "username": username, "via": "command line" }),
+                &mut transaction,
+            )
+            .await?;
+            transaction.commit().await?;
+            println!("Deleted the user {username}.");
+        }
+        AdminCommand::ListUsers => {
+            for user in list_users(&pool).await? {
+                println!("{}\t{}\t{}", user.user_id, user.username, user.role);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_migrate_command(
+    command: MigrateCommand,
+    configuration: Settings,
+) -> anyhow::Result<()> {
+    let pool = get_connection_pool(&configuration.database).await;
+    match command {
+        MigrateCommand::Up => {
+            run_migrations(&pool).await?;
+            println!("The database schema is up to date.");
+        }
+        MigrateCommand::Status => {
+            for migration in migration_status(&pool).await? {
+                let status = if migration.applied {
+                    "applied"
+                } else {
+                    "pending"
+                };
+                println!("{}\t{status}\t{}", migration.version, migration.description);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_new_password(username: &str, policy: &PasswordPolicy) -> anyhow::Result<Secret<String>> {
+    let password = if std::io::stdin().is_terminal() {
+        let password = rpassword::prompt_password("New password: ")?;
+        let password_check = rpassword::prompt_password("Repeat the new password: ")?;
+        anyhow::ensure!(password == password_check, "the passwords do not match");
+        password
+    } else {
+        // scripted use, e.g. `echo "$PASSWORD" | zero_2_prod admin create-user alice`
+        let mut password = String::new();
+        std::io::stdin().read_line(&mut password)?;
+        password.trim_end_matches(['\r', '\n']).to_owned()
+    };
+    let password = Secret::new(password);
+    policy.check(&password, username)?;
+    Ok(password)
+}
+
+/// Waits for the task to finish - forever if the task has not been started.
+async fn join<T>(task: &mut Option<JoinHandle<T>>) -> Result<T, JoinError> {
+    match task {
+        Some(task) => task.await,
+        None => std::future::pending().await,
+    }
+}
+
 fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
     match outcome {
         Ok(Ok(())) => {
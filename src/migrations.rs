@@ -0,0 +1,78 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+use sqlx::{
+    migrate::{Migrate, MigrateError, Migrator},
+    PgPool,
+};
+
+use crate::configuration::DataBaseSettings;
+
+/// The migrations are embedded into the binary, so it always knows which schema it expects.
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+#[tracing::instrument(name = "run migrations", skip(pool))]
+pub async fn run_migrations(pool: &PgPool) -> Result<(), MigrateError> {
+    MIGRATOR.run(pool).await
+}
+
+/// All the migrations known to the binary, in the order they are applied.
+/// Migrations which failed half-way are reported as not applied.
+#[tracing::instrument(name = "get migration status", skip(pool))]
+pub async fn migration_status(pool: &PgPool) -> Result<Vec<MigrationStatus>, MigrateError> {
+    let mut connection = pool.acquire().await?;
+    connection.ensure_migrations_table().await?;
+    let dirty_version = connection.dirty_version().await?;
+    let applied: HashSet<_> = connection
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| m.version)
+        .filter(|version| Some(*version) != dirty_version)
+        .collect();
+    Ok(MIGRATOR
+        .iter()
+        .map(|m| MigrationStatus {
+            version: m.version,
+            description: m.description.to_string(),
+            applied: applied.contains(&m.version),
+        })
+        .collect())
+}
+
+/// Fails if the database lacks migrations which the code relies on.
+pub async fn ensure_schema_is_up_to_date(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let pending: Vec<_> = migration_status(pool)
+        .await
+        .context("failed to retrieve the migration status")?
+        .into_iter()
+        .filter(|m| !m.applied)
+        .map(|m| m.version.to_string())
+        .collect();
+    anyhow::ensure!(
+        pending.is_empty(),
+        "the database schema is behind the code (pending migrations: {}) - \
+        run `zero_2_prod migrate up` or enable `run_migrations_on_startup`",
+        pending.join(", ")
+    );
+    Ok(())
+}
+
+/// Brings the schema up to date if configured to, and refuses to go on with an outdated one.
+pub async fn prepare_database(
+    configuration: &DataBaseSettings,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    if configuration.run_migrations_on_startup {
+        run_migrations(pool)
+            .await
+            .context("failed to run the database migrations")?;
+    }
+    ensure_schema_is_up_to_date(pool).await
+}
@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use crate::configuration::DeliveryRateLimitSettings;
+
+/// A token bucket which holds up to `capacity` tokens and is refilled completely once per `window`.
+#[derive(Debug, Clone)]
+struct RateLimit {
+    name: &'static str,
+    capacity: f64,
+    window: Duration,
+}
+
+impl RateLimit {
+    fn refill_rate(&self) -> f64 {
+        self.capacity / self.window.as_secs_f64()
+    }
+
+    fn available_tokens(&self, tokens: f64, elapsed_seconds: f64) -> f64 {
+        (tokens + elapsed_seconds.max(0.0) * self.refill_rate()).min(self.capacity)
+    }
+
+    fn time_until_next_token(&self, available_tokens: f64) -> Duration {
+        // a capacity of 0 stops the sending - we check again once per window
+        if self.capacity <= 0.0 {
+            return self.window;
+        }
+        Duration::from_secs_f64(((1.0 - available_tokens) / self.refill_rate()).max(0.0))
+    }
+}
+
+pub enum RateLimitDecision {
+    Granted,
+    Exhausted { retry_after: Duration },
+}
+
+/// Limits how many emails are handed to the email provider.
+/// The buckets are stored in Postgres, so the budget is shared by all the worker instances.
+#[derive(Debug, Clone)]
+pub struct DeliveryRateLimiter {
+    limits: Vec<RateLimit>,
+}
+
+impl DeliveryRateLimiter {
+    pub fn new(settings: &DeliveryRateLimitSettings) -> Self {
+        let limits = [
+            ("per_second", settings.per_second, Duration::from_secs(1)),
+            ("per_hour", settings.per_hour, Duration::from_secs(60 * 60)),
+            (
+                "per_day",
+                settings.per_day,
+                Duration::from_secs(24 * 60 * 60),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, capacity, window)| {
+            capacity.map(|capacity| RateLimit {
+                name,
+                capacity: f64::from(capacity),
+                window,
+            })
+        })
+        .collect();
+        Self { limits }
+    }
+
+    /// Takes a token from every configured bucket - or none at all if one of them is empty.
+    #[tracing::instrument(name = "acquire a delivery token", skip_all)]
+    pub async fn try_acquire(&self, pool: &PgPool) -> Result<RateLimitDecision, sqlx::Error> {
+        if self.limits.is_empty() {
+            return Ok(RateLimitDecision::Granted);
+        }
+        let mut transaction = pool.begin().await?;
+        let mut available = Vec::with_capacity(self.limits.len());
+        // the buckets are always locked in the same order, so workers cannot deadlock
+        for limit in &self.limits {
+            sqlx::query!(
+                r#"
+                INSERT INTO delivery_rate_limits(name, tokens, refilled_at)
+                VALUES ($1, $2, now())
+                ON CONFLICT DO NOTHING
+                "#,
+                limit.name,
+                limit.capacity
+            )
+            .execute(&mut transaction)
+            .await?;
+            let r = sqlx::query!(
+                r#"
+                SELECT
+                    tokens,
+                    EXTRACT(EPOCH FROM now() - refilled_at)::DOUBLE PRECISION AS "elapsed_seconds!"
+                FROM delivery_rate_limits
+                WHERE name = $1
+                FOR UPDATE
+                "#,
+                limit.name
+            )
+            .fetch_one(&mut transaction)
+            .await?;
+            available.push(limit.available_tokens(r.tokens, r.elapsed_seconds));
+        }
+
+        let retry_after = self
+            .limits
+            .iter()
+            .zip(&available)
+            .filter(|(_, &tokens)| tokens < 1.0)
+            .map(|(limit, &tokens)| limit.time_until_next_token(tokens))
+            .max();
+        if let Some(retry_after) = retry_after {
+            return Ok(RateLimitDecision::Exhausted { retry_after });
+        }
+
+        for (limit, tokens) in self.limits.iter().zip(available) {
+            sqlx::query!(
+                r#"
+                UPDATE delivery_rate_limits
+                SET
+                    tokens = $2,
+                    refilled_at = now()
+                WHERE name = $1
+                "#,
+                limit.name,
+                tokens - 1.0
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+        transaction.commit().await?;
+        Ok(RateLimitDecision::Granted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RateLimit;
+
+    fn per_minute(capacity: f64) -> RateLimit {
+        RateLimit {
+            name: "test",
+            capacity,
+            window: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn buckets_are_refilled_proportionally_to_the_elapsed_time() {
+        let limit = per_minute(60.0);
+        assert_eq!(limit.available_tokens(0.0, 10.0), 10.0);
+    }
+
+    #[test]
+    fn buckets_never_hold_more_than_their_capacity() {
+        let limit = per_minute(60.0);
+        assert_eq!(limit.available_tokens(50.0, 3600.0), 60.0);
+    }
+
+    #[test]
+    fn the_wait_for_an_empty_bucket_is_the_time_to_refill_one_token() {
+        let limit = per_minute(6.0);
+        assert_eq!(limit.time_until_next_token(0.5), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn a_bucket_without_capacity_waits_for_one_window() {
+        let limit = per_minute(0.0);
+        assert_eq!(limit.available_tokens(0.0, 3600.0), 0.0);
+        assert_eq!(limit.time_until_next_token(0.0), Duration::from_secs(60));
+    }
+}
@@ -1,10 +1,16 @@
 pub mod health_check;
 mod home;
+mod invites;
 mod login;
 mod logout;
+mod password_reset;
 pub mod subscriptions;
 pub mod subscriptions_confirm;
+mod tracking;
 
 pub use home::*;
+pub use invites::*;
 pub use login::*;
 pub use logout::*;
+pub use password_reset::*;
+pub use tracking::*;
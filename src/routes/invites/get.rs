@@ -0,0 +1,69 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{authentication::get_pending_invite, utils::e500};
+
+#[derive(serde::Deserialize)]
+pub struct InviteParameters {
+    invite_token: String,
+}
+
+#[tracing::instrument(name = "show the invite acceptance form", skip_all)]
+pub async fn accept_invite_form(
+    parameters: web::Query<InviteParameters>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(invite) = get_pending_invite(&parameters.invite_token, &pool)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound()
+            .content_type(ContentType::html())
+            .body("<p>The invite is invalid or has expired.</p>"));
+    };
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Accept Invite</title>
+    </head>
+
+    <body>
+        {msg_html}
+        <p>You have been invited as {role}. Choose how you want to log in:</p>
+        <form action="/invites/accept" method="post">
+            <input hidden type="text" name="invite_token" value="{invite_token}">
+            <label>Username
+                <input type="text" placeholder="Enter Username" name="username">
+            </label>
+            <br>
+            <label>Password
+                <input type="password" placeholder="Enter Password" name="password">
+            </label>
+            <br>
+            <label>Confirm password
+                <input type="password" placeholder="Type the password again" name="password_check">
+            </label>
+            <br>
+            <button type="submit">Accept invite</button>
+        </form>
+    </body>
+
+    </html>"#,
+            role = invite.role,
+            invite_token = htmlescape::encode_attribute(&parameters.invite_token),
+        )))
+}
@@ -0,0 +1,63 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::Secret;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{accept_invite, check_new_password, InviteError, PasswordPolicy},
+    configuration::PasswordHashingSettings,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct AcceptInviteFormData {
+    invite_token: String,
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[tracing::instrument(name = "accept an invite", skip(form, pool, hashing, password_policy), fields(username = %form.username))]
+pub async fn accept_invite_submission(
+    form: web::Form<AcceptInviteFormData>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashingSettings>,
+    password_policy: web::Data<PasswordPolicy>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let AcceptInviteFormData {
+        invite_token,
+        username,
+        password,
+        password_check,
+    } = form.into_inner();
+    let form_location = format!(
+        "/invites/accept?invite_token={}",
+        urlencoding::encode(&invite_token)
+    );
+
+    let username = username.trim();
+    if username.is_empty() {
+        FlashMessage::error("The username must not be empty.").send();
+        return Ok(see_other(&form_location));
+    }
+    if let Err(e) = check_new_password(&password, &password_check, username, &password_policy) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&form_location));
+    }
+
+    match accept_invite(&invite_token, username, password, &hashing, &pool).await {
+        Ok(_) => {
+            FlashMessage::info("Your account has been created - you can log in now.").send();
+            Ok(see_other("/login"))
+        }
+        Err(InviteError::UsernameTaken(_)) => {
+            FlashMessage::error("The username is already taken.").send();
+            Ok(see_other(&form_location))
+        }
+        Err(e @ (InviteError::InvalidInvite | InviteError::EmailTaken(_))) => {
+            FlashMessage::error(htmlescape::encode_minimal(&format!("Sorry, {e}."))).send();
+            Ok(see_other("/login"))
+        }
+        Err(e) => Err(e500(e)),
+    }
+}
@@ -2,13 +2,19 @@ use actix_web::{http::header::ContentType, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+use crate::{authentication::csrf_field, session_state::TypedSession, utils::e500};
+
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session).map_err(e500)?;
     let mut error_html = String::new();
     for m in flash_messages.iter() {
         writeln!(error_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap()
     }
 
-    HttpResponse::Ok()
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
             r#"
@@ -23,6 +29,7 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
 
 <body>
     <form action="/login" method="post">
+        {csrf_field}
         <label>
             Username
             <input type="text" placeholder="Enter Username" name="username">
@@ -34,10 +41,48 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
         </label>
         <button type="submit">Login</button>
     </form>
+    <p><a href="/password-reset">Forgot your password?</a></p>
 </body>
 
 </html>
             "#,
             error_html
-        ))
+        )))
+}
+
+pub async fn login_second_factor_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_field(&session).map_err(e500)?;
+    let mut error_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(error_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap()
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Login</title>
+</head>
+
+<body>
+    {error_html}
+    <form action="/login/two-factor" method="post">
+        {csrf_field}
+        <label>
+            Code from your authenticator app, or one of your recovery codes
+            <input type="text" autocomplete="one-time-code" name="code">
+        </label>
+        <button type="submit">Verify</button>
+    </form>
+</body>
+
+</html>"#
+        )))
 }
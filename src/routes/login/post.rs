@@ -1,13 +1,22 @@
-use actix_web::{error::InternalError, web, HttpResponse};
+use actix_web::{error::InternalError, web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
-use reqwest::header::LOCATION;
+use anyhow::Context;
+use chrono::Utc;
+use reqwest::header::{LOCATION, USER_AGENT};
 use secrecy::Secret;
 use sqlx::PgPool;
+use uuid::Uuid;
 
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials},
+    admin::get_username,
+    audit::{record_audit_event, AuditAction},
+    authentication::{
+        is_two_factor_enabled, prune_expired_sessions, register_session, validate_credentials,
+        verify_second_factor, AuthError, Credentials, LoginThrottle,
+    },
+    configuration::{PasswordHashingSettings, SessionSettings},
     routes::subscriptions::error_chain_fmt,
-    session_state::TypedSession,
+    session_state::{PendingLogin, TypedSession},
 };
 
 #[derive(serde::Deserialize)]
@@ -20,6 +29,8 @@ pub struct FormData {
 pub enum LoginError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("Too many failed login attempts - please try again in {0} minutes.")]
+    LockedOut(i64),
     #[error("something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -31,37 +42,94 @@ impl std::fmt::Debug for LoginError {
 }
 
 #[tracing::instrument(
-    skip(form, pool, session),
+    skip(form, pool, session, login_throttle, hashing, session_settings, request),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn login(
+    request: HttpRequest,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    login_throttle: web::Data<LoginThrottle>,
+    hashing: web::Data<PasswordHashingSettings>,
+    session_settings: web::Data<SessionSettings>,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
-    let credentials = Credentials {
-        username: form.0.username,
-        password: form.0.password,
-    };
+    let FormData { username, password } = form.into_inner();
+    tracing::Span::current().record("username", tracing::field::display(&username));
 
-    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    let client_ip = login_throttle.client_ip(&request);
+    let locked_until = login_throttle
+        .locked_until(&username, client_ip, &pool)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    if let Some(locked_until) = locked_until {
+        let minutes_left = ((locked_until - Utc::now()).num_seconds() + 59) / 60;
+        return Err(login_redirect(LoginError::LockedOut(minutes_left.max(1))));
+    }
 
-    match validate_credentials(credentials, &pool).await {
+    let credentials = Credentials {
+        username: username.clone(),
+        password,
+    };
+    match validate_credentials(credentials, &hashing, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
-            let response = HttpResponse::SeeOther()
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+            let two_factor_enabled = is_two_factor_enabled(user_id, &pool)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            if two_factor_enabled {
+                session.renew();
+                let pending_login = PendingLogin {
+                    user_id,
+                    started_at: Utc::now().timestamp_millis(),
+                    n_failed_attempts: 0,
+                };
+                session
+                    .insert_pending_login(&pending_login)
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                return Ok(HttpResponse::SeeOther()
+                    .insert_header((LOCATION, "/login/two-factor"))
+                    .finish());
+            }
+
+            login_throttle
+                .record_success(&username, &pool)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            start_session(
+                &session,
+                user_id,
+                &username,
+                &request,
+                &login_throttle,
+                &session_settings,
+                &pool,
+            )
+            .await
+            .map_err(login_redirect)?;
+            Ok(HttpResponse::SeeOther()
                 .insert_header((LOCATION, "/admin/dashboard"))
-                .finish();
-            session.renew(); // to have a rotating token
-            session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
-            Ok(response)
+                .finish())
         }
 
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::InvalidCredentials(_) => {
+                    login_throttle
+                        .record_failure(&username, client_ip, &pool)
+                        .await
+                        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+                    record_audit_event(
+                        None,
+                        client_ip,
+                        AuditAction::LoginFailed,
+                        serde_json::json!({ "username": username, "reason": "invalid credentials" }),
+                        pool.as_ref(),
+                    )
+                    .await
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+                    LoginError::AuthError(e.into())
+                }
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
             FlashMessage::error(e.to_string()).send();
@@ -73,6 +141,150 @@ pub async fn login(
     }
 }
 
+/// How long the second factor can be entered for after the password has been checked.
+const PENDING_LOGIN_VALIDITY_MILLIS: i64 = 5 * 60 * 1000;
+/// After this many wrong codes the password has to be entered again.
+const MAX_SECOND_FACTOR_ATTEMPTS: u32 = 5;
+
+#[derive(serde::Deserialize)]
+pub struct SecondFactorFormData {
+    code: String,
+}
+
+#[tracing::instrument(
+    skip(form, pool, session, login_throttle, session_settings, request),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn login_second_factor(
+    request: HttpRequest,
+    form: web::Form<SecondFactorFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    login_throttle: web::Data<LoginThrottle>,
+    session_settings: web::Data<SessionSettings>,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+    let pending_login = session
+        .get_pending_login()
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?
+        .filter(|p| Utc::now().timestamp_millis() - p.started_at < PENDING_LOGIN_VALIDITY_MILLIS);
+    let Some(mut pending_login) = pending_login else {
+        session.remove_pending_login();
+        return Err(login_redirect(LoginError::AuthError(anyhow::anyhow!(
+            "there is no pending login"
+        ))));
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&pending_login.user_id));
+
+    let is_valid = verify_second_factor(pending_login.user_id, &form.code, &pool)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    let username = get_username(pending_login.user_id, &pool)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    if !is_valid {
+        record_audit_event(
+            Some(pending_login.user_id),
+            login_throttle.client_ip(&request),
+            AuditAction::LoginFailed,
+            serde_json::json!({ "username": username, "reason": "wrong second factor" }),
+            pool.as_ref(),
+        )
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+        pending_login.n_failed_attempts += 1;
+        if pending_login.n_failed_attempts >= MAX_SECOND_FACTOR_ATTEMPTS {
+            session.remove_pending_login();
+            // otherwise the codes could be guessed by entering the known password again and again
+            login_throttle
+                .record_failure(&username, login_throttle.client_ip(&request), &pool)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            return Err(login_redirect(LoginError::AuthError(anyhow::anyhow!(
+                "too many wrong codes"
+            ))));
+        }
+        session
+            .insert_pending_login(&pending_login)
+            .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+        FlashMessage::error("The code is incorrect.").send();
+        let response = HttpResponse::SeeOther()
+            .insert_header((LOCATION, "/login/two-factor"))
+            .finish();
+        return Err(InternalError::from_response(
+            LoginError::AuthError(anyhow::anyhow!("wrong second factor")),
+            response,
+        ));
+    }
+
+    session.remove_pending_login();
+    login_throttle
+        .record_success(&username, &pool)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    start_session(
+        &session,
+        pending_login.user_id,
+        &username,
+        &request,
+        &login_throttle,
+        &session_settings,
+        &pool,
+    )
+    .await
+    .map_err(login_redirect)?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((LOCATION, "/admin/dashboard"))
+        .finish())
+}
+
+/// Registers the session, so that it shows up in the list of sessions and can be revoked.
+async fn start_session(
+    session: &TypedSession,
+    user_id: Uuid,
+    username: &str,
+    request: &HttpRequest,
+    login_throttle: &LoginThrottle,
+    session_settings: &SessionSettings,
+    pool: &PgPool,
+) -> Result<(), LoginError> {
+    prune_expired_sessions(session_settings, pool)
+        .await
+        .map_err(LoginError::UnexpectedError)?;
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|h| h.to_str().ok());
+    let client_ip = login_throttle.client_ip(request);
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")?;
+    let session_id = register_session(user_id, client_ip, user_agent, &mut transaction)
+        .await
+        .map_err(LoginError::UnexpectedError)?;
+    record_audit_event(
+        Some(user_id),
+        client_ip,
+        AuditAction::Login,
+        serde_json::json!({ "username": username }),
+        &mut transaction,
+    )
+    .await
+    .map_err(LoginError::UnexpectedError)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit the new session")?;
+    session.renew(); // to have a rotating token
+    session
+        .insert_user_id(user_id)
+        .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+    session
+        .insert_session_id(session_id)
+        .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+    Ok(())
+}
+
 fn login_redirect(e: LoginError) -> InternalError<LoginError> {
     FlashMessage::error(e.to_string()).send();
     let response = HttpResponse::SeeOther()
@@ -1,17 +1,48 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
 
 use crate::{
+    audit::{record_audit_event, AuditAction},
+    authentication::{revoke_session, ClientIp},
     session_state::TypedSession,
     utils::{e500, see_other},
 };
 
-pub async fn log_out(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
-    if session.get_user_id().map_err(e500)?.is_none() {
-        Ok(see_other("/login"))
-    } else {
-        session.log_out();
-        FlashMessage::info("You have successfully logged out.").send();
-        Ok(see_other("/login"))
+pub async fn log_out(
+    session: TypedSession,
+    client_ip: ClientIp,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(user_id) = session.get_user_id().map_err(e500)? else {
+        return Ok(see_other("/login"));
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    if let Some(session_id) = session.get_session_id().map_err(e500)? {
+        revoke_session(user_id, session_id, &mut transaction)
+            .await
+            .map_err(e500)?;
     }
+    record_audit_event(
+        Some(user_id),
+        client_ip.0,
+        AuditAction::Logout,
+        serde_json::json!({}),
+        &mut transaction,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit the logout")
+        .map_err(e500)?;
+    session.log_out();
+    FlashMessage::info("You have successfully logged out.").send();
+    Ok(see_other("/login"))
 }
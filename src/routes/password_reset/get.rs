@@ -0,0 +1,97 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{authentication::verify_password_reset_token, startup::HmacSecret, utils::e500};
+
+pub async fn password_reset_request_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap();
+    }
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Forgot Password</title>
+    </head>
+
+    <body>
+        {msg_html}
+        <p>Enter your username and we will email you a link to choose a new password.</p>
+        <form action="/password-reset" method="post">
+            <label>Username
+                <input type="text" placeholder="Enter Username" name="username">
+            </label>
+            <button type="submit">Send reset link</button>
+        </form>
+        <p><a href="/login">&lt;- Back</a></p>
+    </body>
+
+    </html>"#
+        ))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PasswordResetParameters {
+    token: String,
+}
+
+#[tracing::instrument(name = "show the password reset form", skip_all)]
+pub async fn password_reset_form(
+    parameters: web::Query<PasswordResetParameters>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = verify_password_reset_token(&parameters.token, &hmac_secret.0, &pool)
+        .await
+        .map_err(e500)?;
+    if user_id.is_none() {
+        return Ok(HttpResponse::NotFound()
+            .content_type(ContentType::html())
+            .body("<p>The reset link is invalid, has expired or has already been used.</p>"));
+    }
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{msg}</i></p>", msg = m.content()).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+    <html lang="en">
+
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Reset Password</title>
+    </head>
+
+    <body>
+        {msg_html}
+        <form action="/password-reset/confirm" method="post">
+            <input hidden type="text" name="token" value="{token}">
+            <label>New password
+                <input type="password" placeholder="Enter new password" name="new_password">
+            </label>
+            <br>
+            <label>Confirm new password
+                <input type="password" placeholder="Type the new password again" name="new_password_check">
+            </label>
+            <br>
+            <button type="submit">Reset password</button>
+        </form>
+    </body>
+
+    </html>"#,
+            token = htmlescape::encode_attribute(&parameters.token),
+        )))
+}
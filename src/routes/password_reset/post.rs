@@ -0,0 +1,186 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
+
+use crate::{
+    admin::get_username,
+    audit::{record_audit_event, AuditAction},
+    authentication::{
+        change_password, check_new_password, generate_password_reset_token,
+        get_password_reset_recipient, verify_password_reset_token, ClientIp, LoginThrottle,
+        PasswordPolicy,
+    },
+    configuration::PasswordHashingSettings,
+    domain::subscriber_email::SubscriberEmail,
+    email_client::EmailClient,
+    startup::{ApplicationBaseUrl, HmacSecret},
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct ResetRequestFormData {
+    username: String,
+}
+
+#[tracing::instrument(
+    name = "request a password reset",
+    skip(form, pool, email_client, base_url, hmac_secret, login_throttle, client_ip),
+    fields(username = %form.username)
+)]
+pub async fn request_password_reset(
+    form: web::Form<ResetRequestFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    login_throttle: web::Data<LoginThrottle>,
+    client_ip: ClientIp,
+) -> HttpResponse {
+    // the response must not tell whether the username exists or has asked for too many resets -
+    // neither by its content nor by how long it takes - so the link is sent in the background
+    tokio::spawn(send_password_reset_link(
+        form.into_inner().username,
+        client_ip,
+        login_throttle,
+        pool,
+        email_client,
+        base_url,
+        hmac_secret,
+    ));
+    FlashMessage::info(
+        "If there is an account with an email address for the username, \
+        a link to reset its password has been sent to it.",
+    )
+    .send();
+    see_other("/login")
+}
+
+#[tracing::instrument(name = "send a password reset link", skip_all)]
+async fn send_password_reset_link(
+    username: String,
+    client_ip: ClientIp,
+    login_throttle: web::Data<LoginThrottle>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+) {
+    let outcome = async {
+        if !login_throttle
+            .allow_password_reset(&username, client_ip.0, &pool)
+            .await?
+        {
+            tracing::warn!("too many password reset requests - No link is sent");
+            return Ok(());
+        }
+        let Some(recipient) = get_password_reset_recipient(&username, &pool).await? else {
+            return Ok(());
+        };
+        let token = generate_password_reset_token(recipient.user_id, &hmac_secret.0, &pool).await?;
+        let email = SubscriberEmail::parse(recipient.email).map_err(anyhow::Error::msg)?;
+        send_password_reset_email(&email_client, &email, &base_url.0, &token)
+            .await
+            .context("failed to send the password reset email")
+    };
+    if let Err(e) = outcome.await {
+        tracing::error!(error.cause_chain = ?e, error.message = %e, "failed to send a password reset link");
+    }
+}
+
+async fn send_password_reset_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> Result<(), reqwest::Error> {
+    let reset_link = format!(
+        "{base_url}/password-reset/confirm?token={token}",
+        token = urlencoding::encode(token)
+    );
+    let html_body = format!(
+        "Somebody asked to reset your password. \
+        <a href=\"{reset_link}\">Choose a new password</a> if it was you, otherwise ignore this email."
+    );
+    let plain_body = format!(
+        "Somebody asked to reset your password. \
+        Choose a new password if it was you, otherwise ignore this email: {reset_link}"
+    );
+    email_client
+        .send_email(email, "Reset your password", &html_body, &plain_body)
+        .await
+        .map(|_| ())
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetFormData {
+    token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[tracing::instrument(name = "reset a password", skip_all)]
+pub async fn reset_password(
+    form: web::Form<ResetFormData>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+    hashing: web::Data<PasswordHashingSettings>,
+    password_policy: web::Data<PasswordPolicy>,
+    client_ip: ClientIp,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ResetFormData {
+        token,
+        new_password,
+        new_password_check,
+    } = form.into_inner();
+    let Some(user_id) = verify_password_reset_token(&token, &hmac_secret.0, &pool)
+        .await
+        .map_err(e500)?
+    else {
+        FlashMessage::error("The reset link is invalid, has expired or has already been used.")
+            .send();
+        return Ok(see_other("/login"));
+    };
+
+    let username = get_username(user_id, &pool).await.map_err(e500)?;
+    if let Err(e) = check_new_password(
+        &new_password,
+        &new_password_check,
+        &username,
+        &password_policy,
+    ) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&format!(
+            "/password-reset/confirm?token={}",
+            urlencoding::encode(&token)
+        )));
+    }
+
+    // whoever triggered the reset might have done so because somebody else got hold of the
+    // old password, so none of the sessions is kept
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to start a transaction")
+        .map_err(e500)?;
+    change_password(user_id, new_password, None, &hashing, &mut transaction)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        Some(user_id),
+        client_ip.0,
+        AuditAction::PasswordChanged,
+        serde_json::json!({ "via": "password reset" }),
+        &mut transaction,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit the password reset")
+        .map_err(e500)?;
+    FlashMessage::info("Your password has been reset - you can log in now.").send();
+    Ok(see_other("/login"))
+}
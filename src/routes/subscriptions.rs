@@ -4,7 +4,6 @@ use chrono::Utc;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use reqwest::StatusCode;
 use sqlx::{PgPool, Postgres, Transaction};
-use tracing::error;
 use uuid::Uuid;
 
 use crate::{
@@ -14,6 +13,7 @@ use crate::{
     },
     email_client::EmailClient,
     startup::ApplicationBaseUrl,
+    suppression::is_suppressed,
 };
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -46,7 +46,16 @@ pub async fn subscribe(
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let new_subscriber: NewSubscriber =
+        form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    // we answer as if everything went fine so that the suppression list cannot be probed
+    if is_suppressed(&pool, new_subscriber.email.as_ref())
+        .await
+        .context("failed to check the suppression list")?
+    {
+        tracing::info!("the subscriber email is suppressed - not sending a confirmation");
+        return Ok(HttpResponse::Ok().finish());
+    }
     let mut transaction = pool
         .begin()
         .await
@@ -151,7 +160,7 @@ async fn store_token(
     )
     .execute(transaction)
     .await
-    .map_err(|e| StoreTokenError(e))?;
+    .map_err(StoreTokenError)?;
     Ok(())
 }
 
@@ -173,6 +182,7 @@ async fn send_confirmation_email(
     email_client
         .send_email(&new_subscriber.email, "Welcome", plain_body, html_body)
         .await
+        .map(|_| ())
 }
 
 fn generate_subscription_token() -> String {
@@ -0,0 +1,68 @@
+use actix_web::{
+    http::header::{CacheControl, CacheDirective, LOCATION},
+    web, HttpResponse,
+};
+
+use crate::{
+    tracking::{extract_links, get_tracked_delivery, record_tracking_event, TrackingEvent},
+    utils::e500,
+};
+
+/// The smallest transparent gif there is.
+const TRACKING_PIXEL: &[u8] = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff!\xf9\x04\x01\x00\x00\x00\x00,\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02D\x01\x00;";
+
+#[tracing::instrument(name = "track an email open", skip(tracking_token, pool))]
+pub async fn track_open(
+    tracking_token: web::Path<String>,
+    pool: web::Data<sqlx::PgPool>,
+) -> HttpResponse {
+    // the pixel is served no matter what - mail clients should not show broken images
+    if let Err(e) = record_open(&pool, &tracking_token).await {
+        tracing::error!(error.cause_chain = ?e, error.message = %e, "failed to record an email open");
+    }
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(TRACKING_PIXEL)
+}
+
+async fn record_open(pool: &sqlx::PgPool, tracking_token: &str) -> Result<(), sqlx::Error> {
+    if let Some(delivery) = get_tracked_delivery(pool, tracking_token).await? {
+        record_tracking_event(pool, &delivery, TrackingEvent::Open, None).await?;
+    }
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+pub struct ClickParameters {
+    url: String,
+}
+
+#[tracing::instrument(name = "track a link click", skip(tracking_token, parameters, pool))]
+pub async fn track_click(
+    tracking_token: web::Path<String>,
+    parameters: web::Query<ClickParameters>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(delivery) = get_tracked_delivery(&pool, &tracking_token)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    // only redirect to urls which are part of the issue, otherwise we are an open redirect
+    if !extract_links(&delivery.issue_content).contains(&parameters.url) {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    record_tracking_event(
+        &pool,
+        &delivery,
+        TrackingEvent::Click,
+        Some(&parameters.url),
+    )
+    .await
+    .map_err(e500)?;
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, parameters.url.as_str()))
+        .finish())
+}
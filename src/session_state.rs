@@ -2,12 +2,17 @@ use std::future::{ready, Ready};
 
 use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
 use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use secrecy::{ExposeSecret, Secret};
 use uuid::Uuid;
 
 pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &str = "user_id";
+    const SESSION_ID_KEY: &str = "session_id";
+    const CSRF_TOKEN_KEY: &str = "csrf_token";
+    const PENDING_LOGIN_KEY: &str = "pending_two_factor_login";
+    const PENDING_TOTP_SECRET_KEY: &str = "pending_totp_secret";
 
     pub fn log_out(&self) {
         self.0.purge()
@@ -24,6 +29,67 @@ impl TypedSession {
     pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
         self.0.get(Self::USER_ID_KEY)
     }
+
+    /// The id under which the login has been registered in the database.
+    pub fn insert_session_id(&self, session_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    pub fn insert_pending_login(
+        &self,
+        pending_login: &PendingLogin,
+    ) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_LOGIN_KEY, pending_login)
+    }
+
+    pub fn get_pending_login(&self) -> Result<Option<PendingLogin>, SessionGetError> {
+        self.0.get(Self::PENDING_LOGIN_KEY)
+    }
+
+    pub fn remove_pending_login(&self) {
+        self.0.remove(Self::PENDING_LOGIN_KEY);
+    }
+
+    pub fn insert_csrf_token(&self, token: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::CSRF_TOKEN_KEY, token)
+    }
+
+    pub fn get_csrf_token(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
+
+    /// The secret of an authenticator app that has not been confirmed yet.
+    pub fn insert_pending_totp_secret(
+        &self,
+        secret: &Secret<String>,
+    ) -> Result<(), SessionInsertError> {
+        self.0
+            .insert(Self::PENDING_TOTP_SECRET_KEY, secret.expose_secret())
+    }
+
+    pub fn get_pending_totp_secret(&self) -> Result<Option<Secret<String>>, SessionGetError> {
+        Ok(self
+            .0
+            .get::<String>(Self::PENDING_TOTP_SECRET_KEY)?
+            .map(Secret::new))
+    }
+
+    pub fn remove_pending_totp_secret(&self) {
+        self.0.remove(Self::PENDING_TOTP_SECRET_KEY);
+    }
+}
+
+/// A login that has passed the password check but still waits for the second factor.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PendingLogin {
+    pub user_id: Uuid,
+    /// Milliseconds since the epoch
+    pub started_at: i64,
+    pub n_failed_attempts: u32,
 }
 
 impl FromRequest for TypedSession {
@@ -0,0 +1,90 @@
+use tokio::sync::watch;
+
+/// Lets long-running tasks know that the process is shutting down.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+/// Asks every [`Shutdown`] created alongside it to stop.
+pub struct ShutdownTrigger(watch::Sender<bool>);
+
+pub fn shutdown_channel() -> (ShutdownTrigger, Shutdown) {
+    let (sender, receiver) = watch::channel(false);
+    (ShutdownTrigger(sender), Shutdown(receiver))
+}
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+impl Shutdown {
+    /// Whether the shutdown has been triggered - or the trigger is gone.
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow() || self.0.has_changed().is_err()
+    }
+
+    /// Resolves once the shutdown has been triggered - or the trigger is gone.
+    pub async fn requested(&mut self) {
+        let _ = self.0.wait_for(|requested| *requested).await;
+    }
+}
+
+/// Resolves when the process receives SIGTERM or Ctrl+C.
+pub async fn termination_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for Ctrl+C");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::shutdown::shutdown_channel;
+
+    #[tokio::test]
+    async fn shutdown_is_not_requested_until_triggered() {
+        let (_trigger, mut shutdown) = shutdown_channel();
+        assert!(!shutdown.is_requested());
+        let outcome = tokio::time::timeout(Duration::from_millis(50), shutdown.requested()).await;
+        assert!(outcome.is_err());
+    }
+
+    #[tokio::test]
+    async fn every_copy_sees_the_triggered_shutdown() {
+        let (trigger, shutdown) = shutdown_channel();
+        let mut copy = shutdown.clone();
+        trigger.trigger();
+        assert!(shutdown.is_requested());
+        tokio::time::timeout(Duration::from_millis(50), copy.requested())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn dropping_the_trigger_counts_as_a_shutdown() {
+        let (trigger, mut shutdown) = shutdown_channel();
+        drop(trigger);
+        assert!(shutdown.is_requested());
+        tokio::time::timeout(Duration::from_millis(50), shutdown.requested())
+            .await
+            .unwrap();
+    }
+}
@@ -1,9 +1,9 @@
 use std::{net::TcpListener, time::Duration};
 
-use actix_session::{storage::RedisSessionStore, SessionMiddleware};
+use actix_session::{config::BrowserSession, storage::RedisSessionStore, SessionMiddleware};
 use actix_web::{
-    cookie::Key,
-    dev::Server,
+    cookie::{time::Duration as CookieDuration, Key},
+    dev::{Server, ServerHandle},
     web::{self, Data},
     App, HttpServer,
 };
@@ -16,15 +16,31 @@ use tracing_actix_web::TracingLogger;
 
 use crate::{
     admin::{
-        admin_dashboard, get::change_password_form, newsletter_form, post::change_password,
-        publish_newsletter,
+        add_api_token, add_suppression_entry, admin_dashboard, api_tokens_page, audit_page,
+        cancel_issue_delivery, change_subscriber_tracking, change_user_role,
+        disable_two_factor_authentication, drafts_page, enable_two_factor_authentication,
+        get::change_password_form, import_suppression_entries, invite_user, issue_status_page,
+        issues_page, newsletter_form, pause_issue_delivery, post::change_password, publish_draft,
+        publish_newsletter, remove_api_token, remove_suppression_entry, remove_user,
+        resume_issue_delivery, revoke_other_sessions, revoke_user_session, save_draft,
+        sessions_page, subscribers_page, suppressions_page, two_factor_page, users_page,
     },
-    authentication::reject_anonymous_users,
-    configuration::{DataBaseSettings, Settings},
+    api::{
+        create_draft_api, get_issue_api, json_error_handler, list_issues_api, publish_issue_api,
+        reject_api_non_owners, reject_api_viewers, require_api_tokens, update_draft_api,
+    },
+    authentication::{
+        authenticate_api_tokens, reject_anonymous_users, reject_api_tokens,
+        reject_invalid_csrf_tokens, reject_non_owners, reject_viewers, LoginThrottle,
+        PasswordPolicy,
+    },
+    configuration::{DataBaseSettings, PasswordHashingSettings, SessionSettings, Settings},
     email_client::EmailClient,
     routes::{
-        health_check::health_check, home, log_out, login, login_form, subscriptions::subscribe,
-        subscriptions_confirm::confirm,
+        accept_invite_form, accept_invite_submission, health_check::health_check, home, log_out,
+        login, login_form, login_second_factor, login_second_factor_form, password_reset_form,
+        password_reset_request_form, request_password_reset, reset_password,
+        subscriptions::subscribe, subscriptions_confirm::confirm, track_click, track_open,
     },
 };
 
@@ -52,6 +68,7 @@ impl Application {
             port = configuration.application.port
         );
         let port = listener.local_addr().unwrap().port();
+        let shutdown_timeout = configuration.application.shutdown_timeout();
         let server = run(
             listener,
             connection,
@@ -59,6 +76,11 @@ impl Application {
             configuration.application.base_url,
             configuration.application.hmac_secret,
             configuration.redis_uri,
+            shutdown_timeout,
+            LoginThrottle::new(configuration.login_throttling),
+            configuration.password_hashing,
+            PasswordPolicy::new(configuration.password_policy),
+            configuration.session,
         )
         .await?;
         let application = Self { server, port };
@@ -69,6 +91,11 @@ impl Application {
         self.port
     }
 
+    /// Used to stop the server from the outside - the server does not react to signals itself.
+    pub fn handle(&self) -> ServerHandle {
+        self.server.handle()
+    }
+
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
         self.server.await
     }
@@ -83,6 +110,7 @@ pub async fn get_connection_pool(configuration: &DataBaseSettings) -> PgPool {
 // We need this one to provide the url as app context
 pub struct ApplicationBaseUrl(pub String);
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
@@ -90,10 +118,20 @@ pub async fn run(
     base_url: String,
     hmac_secret: Secret<String>,
     redis_uri: Secret<String>,
+    shutdown_timeout: Duration,
+    login_throttle: LoginThrottle,
+    password_hashing: PasswordHashingSettings,
+    password_policy: PasswordPolicy,
+    session_settings: SessionSettings,
 ) -> Result<Server, anyhow::Error> {
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
     let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let login_throttle = Data::new(login_throttle);
+    let password_hashing = Data::new(password_hashing);
+    let password_policy = Data::new(password_policy);
+    let session_ttl = CookieDuration::seconds(session_settings.max_lifetime().num_seconds());
+    let session_settings = Data::new(session_settings);
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
@@ -101,33 +139,249 @@ pub async fn run(
     let server = HttpServer::new(move || {
         App::new()
             .wrap(message_framework.clone())
-            .wrap(SessionMiddleware::new(
-                redis_store.clone(),
-                secret_key.clone(),
-            ))
+            .wrap(
+                SessionMiddleware::builder(redis_store.clone(), secret_key.clone())
+                    .cookie_secure(session_settings.cookie_secure)
+                    .cookie_same_site(session_settings.cookie_same_site.into())
+                    .cookie_http_only(true)
+                    // the server side checks the limits, the state just must not outlive them
+                    .session_lifecycle(BrowserSession::default().state_ttl(session_ttl))
+                    .build(),
+            )
             .wrap(TracingLogger::default())
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
             .route("/", web::get().to(home))
             .route("/login", web::get().to(login_form))
-            .route("/login", web::post().to(login))
+            .route(
+                "/login",
+                web::post()
+                    .to(login)
+                    .wrap(from_fn(reject_invalid_csrf_tokens)),
+            )
+            .route("/login/two-factor", web::get().to(login_second_factor_form))
+            .route(
+                "/login/two-factor",
+                web::post()
+                    .to(login_second_factor)
+                    .wrap(from_fn(reject_invalid_csrf_tokens)),
+            )
+            .route(
+                "/password-reset",
+                web::get().to(password_reset_request_form),
+            )
+            .route("/password-reset", web::post().to(request_password_reset))
+            .route(
+                "/password-reset/confirm",
+                web::get().to(password_reset_form),
+            )
+            .route("/password-reset/confirm", web::post().to(reset_password))
+            .route("/invites/accept", web::get().to(accept_invite_form))
+            .route("/invites/accept", web::post().to(accept_invite_submission))
+            .route("/t/open/{tracking_token}", web::get().to(track_open))
+            .route("/t/click/{tracking_token}", web::get().to(track_click))
             .service(
                 web::scope("/admin")
+                    // the token is only checked for users which are logged in
+                    .wrap(from_fn(reject_invalid_csrf_tokens))
                     .wrap(from_fn(reject_anonymous_users))
-                    .route("/password", web::post().to(change_password))
-                    .route("/password", web::get().to(change_password_form))
+                    .wrap(from_fn(authenticate_api_tokens))
+                    .route(
+                        "/password",
+                        web::post()
+                            .to(change_password)
+                            .wrap(from_fn(reject_api_tokens)),
+                    )
+                    .route(
+                        "/password",
+                        web::get()
+                            .to(change_password_form)
+                            .wrap(from_fn(reject_api_tokens)),
+                    )
                     .route("/dashboard", web::get().to(admin_dashboard))
+                    .route(
+                        "/two-factor",
+                        web::get()
+                            .to(two_factor_page)
+                            .wrap(from_fn(reject_api_tokens)),
+                    )
+                    .route(
+                        "/two-factor/enable",
+                        web::post()
+                            .to(enable_two_factor_authentication)
+                            .wrap(from_fn(reject_api_tokens)),
+                    )
+                    .route(
+                        "/two-factor/disable",
+                        web::post()
+                            .to(disable_two_factor_authentication)
+                            .wrap(from_fn(reject_api_tokens)),
+                    )
+                    .route(
+                        "/sessions",
+                        web::get()
+                            .to(sessions_page)
+                            .wrap(from_fn(reject_api_tokens)),
+                    )
+                    .route(
+                        "/sessions/revoke",
+                        web::post()
+                            .to(revoke_user_session)
+                            .wrap(from_fn(reject_api_tokens)),
+                    )
+                    .route(
+                        "/sessions/revoke-others",
+                        web::post()
+                            .to(revoke_other_sessions)
+                            .wrap(from_fn(reject_api_tokens)),
+                    )
+                    .route(
+                        "/api-tokens",
+                        web::get()
+                            .to(api_tokens_page)
+                            .wrap(from_fn(reject_api_tokens)),
+                    )
+                    .route(
+                        "/api-tokens",
+                        web::post()
+                            .to(add_api_token)
+                            .wrap(from_fn(reject_api_tokens)),
+                    )
+                    .route(
+                        "/api-tokens/revoke",
+                        web::post()
+                            .to(remove_api_token)
+                            .wrap(from_fn(reject_api_tokens)),
+                    )
                     .route("/logout", web::post().to(log_out))
-                    .route("/newsletters", web::get().to(newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter)),
+                    .route(
+                        "/newsletters",
+                        web::get().to(newsletter_form).wrap(from_fn(reject_viewers)),
+                    )
+                    .route(
+                        "/newsletters",
+                        web::post()
+                            .to(publish_newsletter)
+                            .wrap(from_fn(reject_non_owners)),
+                    )
+                    .route("/drafts", web::get().to(drafts_page))
+                    .route(
+                        "/drafts",
+                        web::post().to(save_draft).wrap(from_fn(reject_viewers)),
+                    )
+                    .route(
+                        "/drafts/{draft_id}/publish",
+                        web::post()
+                            .to(publish_draft)
+                            .wrap(from_fn(reject_non_owners)),
+                    )
+                    .route("/issues", web::get().to(issues_page))
+                    .route("/issues/{issue_id}", web::get().to(issue_status_page))
+                    .route(
+                        "/issues/{issue_id}/pause",
+                        web::post()
+                            .to(pause_issue_delivery)
+                            .wrap(from_fn(reject_non_owners)),
+                    )
+                    .route(
+                        "/issues/{issue_id}/resume",
+                        web::post()
+                            .to(resume_issue_delivery)
+                            .wrap(from_fn(reject_non_owners)),
+                    )
+                    .route(
+                        "/issues/{issue_id}/cancel",
+                        web::post()
+                            .to(cancel_issue_delivery)
+                            .wrap(from_fn(reject_non_owners)),
+                    )
+                    .route("/subscribers", web::get().to(subscribers_page))
+                    .route(
+                        "/subscribers/tracking",
+                        web::post()
+                            .to(change_subscriber_tracking)
+                            .wrap(from_fn(reject_non_owners)),
+                    )
+                    .route("/suppressions", web::get().to(suppressions_page))
+                    .route(
+                        "/suppressions",
+                        web::post()
+                            .to(add_suppression_entry)
+                            .wrap(from_fn(reject_non_owners)),
+                    )
+                    .route(
+                        "/suppressions/import",
+                        web::post()
+                            .to(import_suppression_entries)
+                            .wrap(from_fn(reject_non_owners)),
+                    )
+                    .route(
+                        "/suppressions/remove",
+                        web::post()
+                            .to(remove_suppression_entry)
+                            .wrap(from_fn(reject_non_owners)),
+                    )
+                    .route(
+                        "/users",
+                        web::get().to(users_page).wrap(from_fn(reject_non_owners)),
+                    )
+                    .route(
+                        "/users/role",
+                        web::post()
+                            .to(change_user_role)
+                            .wrap(from_fn(reject_non_owners)),
+                    )
+                    .route(
+                        "/users/invite",
+                        web::post().to(invite_user).wrap(from_fn(reject_non_owners)),
+                    )
+                    .route(
+                        "/users/delete",
+                        web::post().to(remove_user).wrap(from_fn(reject_non_owners)),
+                    )
+                    .route(
+                        "/audit",
+                        web::get().to(audit_page).wrap(from_fn(reject_non_owners)),
+                    ),
+            )
+            .service(
+                web::scope("/api/v1")
+                    .wrap(from_fn(require_api_tokens))
+                    .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+                    .route("/issues", web::get().to(list_issues_api))
+                    .route(
+                        "/issues",
+                        web::post()
+                            .to(create_draft_api)
+                            .wrap(from_fn(reject_api_viewers)),
+                    )
+                    .route("/issues/{issue_id}", web::get().to(get_issue_api))
+                    .route(
+                        "/issues/{issue_id}",
+                        web::put()
+                            .to(update_draft_api)
+                            .wrap(from_fn(reject_api_viewers)),
+                    )
+                    .route(
+                        "/issues/{issue_id}/publish",
+                        web::post()
+                            .to(publish_issue_api)
+                            .wrap(from_fn(reject_api_non_owners)),
+                    ),
             )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(login_throttle.clone())
+            .app_data(password_hashing.clone())
+            .app_data(password_policy.clone())
+            .app_data(session_settings.clone())
             .app_data(Data::new(HmacSecret(hmac_secret.clone())))
     })
     .listen(listener)?
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout.as_secs())
     .run();
     Ok(server)
 }
@@ -0,0 +1,120 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::domain::suppression_entry::{SuppressionEntry, SuppressionReason};
+
+pub struct Suppression {
+    pub suppression_id: Uuid,
+    pub kind: String,
+    pub value: String,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Checks whether the given address - or the domain it belongs to - is on the suppression list.
+/// A suppressed domain covers its subdomains as well.
+#[tracing::instrument(name = "check suppression list", skip(pool))]
+pub async fn is_suppressed(pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    let email = email.to_lowercase();
+    let domain = email.rsplit_once('@').map(|(_, d)| d).unwrap_or_default();
+    let r = sqlx::query!(
+        r#"
+        SELECT EXISTS(
+            SELECT 1
+            FROM suppressions
+            WHERE
+                (kind = 'address' AND value = $1) OR
+                (kind = 'domain' AND value = ANY($2))
+        ) AS "suppressed!"
+        "#,
+        email,
+        &parent_domains(domain) as &[&str]
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(r.suppressed)
+}
+
+/// The domain itself followed by every domain it is a subdomain of,
+/// e.g. `mail.example.com`, `example.com` and `com`.
+fn parent_domains(domain: &str) -> Vec<&str> {
+    let mut domains = vec![domain];
+    let mut rest = domain;
+    while let Some((_, parent)) = rest.split_once('.') {
+        domains.push(parent);
+        rest = parent;
+    }
+    domains
+}
+
+/// Adds an entry to the suppression list. Returns `false` if the entry was already present.
+#[tracing::instrument(name = "add suppression entry", skip(executor))]
+pub async fn add_suppression(
+    executor: impl PgExecutor<'_>,
+    entry: &SuppressionEntry,
+    reason: SuppressionReason,
+) -> Result<bool, sqlx::Error> {
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO suppressions (suppression_id, kind, value, reason, created_at)
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        entry.kind(),
+        entry.value(),
+        reason.as_str()
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_inserted_rows > 0)
+}
+
+/// Returns the removed address or domain, `None` if there was no such entry.
+#[tracing::instrument(name = "remove suppression entry", skip(executor))]
+pub async fn remove_suppression(
+    executor: impl PgExecutor<'_>,
+    suppression_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"DELETE FROM suppressions WHERE suppression_id = $1 RETURNING value"#,
+        suppression_id
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(r.map(|r| r.value))
+}
+
+#[tracing::instrument(name = "list suppression entries", skip(pool))]
+pub async fn list_suppressions(pool: &PgPool) -> Result<Vec<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT suppression_id, kind, value, reason, created_at
+        FROM suppressions
+        ORDER BY created_at DESC, value
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parent_domains;
+
+    #[test]
+    fn a_domain_is_followed_by_its_parents() {
+        assert_eq!(
+            parent_domains("mail.example.com"),
+            vec!["mail.example.com", "example.com", "com"]
+        );
+    }
+
+    #[test]
+    fn a_missing_domain_has_no_parents() {
+        assert_eq!(parent_domains(""), vec![""]);
+    }
+}
@@ -0,0 +1,5 @@
+mod persistence;
+mod rewrite;
+
+pub use persistence::*;
+pub use rewrite::*;
@@ -0,0 +1,155 @@
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy)]
+pub enum TrackingEvent {
+    Open,
+    Click,
+}
+
+impl TrackingEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrackingEvent::Open => "open",
+            TrackingEvent::Click => "click",
+        }
+    }
+}
+
+/// A delivery which has been sent with tracking enabled.
+pub struct TrackedDelivery {
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_email: String,
+    pub issue_content: String,
+}
+
+pub fn generate_tracking_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(25)
+        .collect()
+}
+
+#[tracing::instrument(name = "get tracked delivery", skip(pool, tracking_token))]
+pub async fn get_tracked_delivery(
+    pool: &PgPool,
+    tracking_token: &str,
+) -> Result<Option<TrackedDelivery>, sqlx::Error> {
+    sqlx::query_as!(
+        TrackedDelivery,
+        r#"
+        SELECT
+            d.newsletter_issue_id,
+            d.subscriber_email,
+            i.content AS issue_content
+        FROM issue_deliveries d
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE d.tracking_token = $1
+        "#,
+        tracking_token
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(name = "record tracking event", skip(pool, delivery))]
+pub async fn record_tracking_event(
+    pool: &PgPool,
+    delivery: &TrackedDelivery,
+    event: TrackingEvent,
+    url: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO delivery_events(
+            event_id,
+            newsletter_issue_id,
+            subscriber_email,
+            kind,
+            url,
+            occurred_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        delivery.newsletter_issue_id,
+        delivery.subscriber_email,
+        event.as_str(),
+        url
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct EngagementStats {
+    /// Recipients the issue has been sent to with tracking enabled.
+    pub n_tracked: i64,
+    pub n_opened: i64,
+    pub n_clicked: i64,
+}
+
+impl EngagementStats {
+    pub fn open_rate(&self) -> f64 {
+        rate(self.n_opened, self.n_tracked)
+    }
+
+    pub fn click_rate(&self) -> f64 {
+        rate(self.n_clicked, self.n_tracked)
+    }
+}
+
+fn rate(n: i64, n_total: i64) -> f64 {
+    if n_total == 0 {
+        0.0
+    } else {
+        n as f64 * 100.0 / n_total as f64
+    }
+}
+
+#[tracing::instrument(name = "get engagement stats", skip(pool))]
+pub async fn get_engagement_stats(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<EngagementStats, sqlx::Error> {
+    // a click implies that the email has been opened, even if the pixel was blocked
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) AS "n_tracked!",
+            COUNT(*) FILTER (
+                WHERE EXISTS(
+                    SELECT 1 FROM delivery_events e
+                    WHERE
+                        e.newsletter_issue_id = d.newsletter_issue_id AND
+                        e.subscriber_email = d.subscriber_email
+                )
+            ) AS "n_opened!",
+            COUNT(*) FILTER (
+                WHERE EXISTS(
+                    SELECT 1 FROM delivery_events e
+                    WHERE
+                        e.newsletter_issue_id = d.newsletter_issue_id AND
+                        e.subscriber_email = d.subscriber_email AND
+                        e.kind = 'click'
+                )
+            ) AS "n_clicked!"
+        FROM issue_deliveries d
+        WHERE
+            d.newsletter_issue_id = $1 AND
+            d.status = 'sent' AND
+            d.tracking_token IS NOT NULL
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(EngagementStats {
+        n_tracked: r.n_tracked,
+        n_opened: r.n_opened,
+        n_clicked: r.n_clicked,
+    })
+}
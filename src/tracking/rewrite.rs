@@ -0,0 +1,140 @@
+/// Rewrites the links of an html email body to go through our click redirector and appends a
+/// tracking pixel, so that we learn when the email is opened.
+pub fn add_tracking(html: &str, base_url: &str, token: &str) -> String {
+    let mut tracked = String::with_capacity(html.len());
+    let mut last_end = 0;
+    for link in find_links(html) {
+        if !is_trackable(link.url) {
+            continue;
+        }
+        tracked.push_str(&html[last_end..link.start]);
+        tracked.push_str(&format!(
+            "{base_url}/t/click/{token}?url={url}",
+            url = urlencoding::encode(&decode_url(link.url))
+        ));
+        last_end = link.start + link.url.len();
+    }
+    tracked.push_str(&html[last_end..]);
+
+    let pixel = format!(r#"<img src="{base_url}/t/open/{token}" width="1" height="1" alt="">"#);
+    match tracked.to_ascii_lowercase().rfind("</body>") {
+        Some(i) => tracked.insert_str(i, &pixel),
+        None => tracked.push_str(&pixel),
+    }
+    tracked
+}
+
+/// All urls linked from the given html - used to make sure the click redirector only
+/// redirects to urls which are part of an issue. The urls are returned as the browser would
+/// follow them, i.e., with html entities decoded.
+pub fn extract_links(html: &str) -> Vec<String> {
+    find_links(html)
+        .into_iter()
+        .map(|l| decode_url(l.url))
+        .collect()
+}
+
+struct Link<'a> {
+    start: usize,
+    url: &'a str,
+}
+
+fn find_links(html: &str) -> Vec<Link<'_>> {
+    // lowercasing ascii characters does not move any byte offsets
+    let lowercase = html.to_ascii_lowercase();
+    let mut links = Vec::new();
+    let mut offset = 0;
+    while let Some(i) = lowercase[offset..].find("href=") {
+        let value_start = offset + i + "href=".len();
+        offset = value_start;
+        let Some(quote) = html[value_start..].chars().next() else {
+            break;
+        };
+        if quote != '"' && quote != '\'' {
+            continue;
+        }
+        let url_start = value_start + 1;
+        let Some(url_len) = html[url_start..].find(quote) else {
+            break;
+        };
+        links.push(Link {
+            start: url_start,
+            url: &html[url_start..url_start + url_len],
+        });
+        offset = url_start + url_len;
+    }
+    links
+}
+
+/// Decodes the html entities of an attribute value, e.g., `&amp;` separating query parameters.
+/// Values which are not valid html are kept as they are, like a browser would.
+fn decode_url(url: &str) -> String {
+    htmlescape::decode_html(url).unwrap_or_else(|_| url.to_owned())
+}
+
+fn is_trackable(url: &str) -> bool {
+    let url = url.to_ascii_lowercase();
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tracking::{add_tracking, extract_links};
+
+    #[test]
+    fn links_are_rewritten_to_the_click_redirector() {
+        let html = r#"<p>Read <a href="https://example.com/a?b=c">this</a></p>"#;
+        let tracked = add_tracking(html, "http://localhost", "token");
+        assert!(tracked.contains(
+            r#"<a href="http://localhost/t/click/token?url=https%3A%2F%2Fexample.com%2Fa%3Fb%3Dc">this</a>"#
+        ));
+    }
+
+    #[test]
+    fn html_entities_in_links_are_decoded() {
+        let html = r#"<a href="https://example.com/?a=1&amp;b=2">x</a>"#;
+        let tracked = add_tracking(html, "http://localhost", "token");
+        assert!(tracked.starts_with(
+            r#"<a href="http://localhost/t/click/token?url=https%3A%2F%2Fexample.com%2F%3Fa%3D1%26b%3D2">x</a>"#
+        ));
+        assert_eq!(extract_links(html), vec!["https://example.com/?a=1&b=2"]);
+    }
+
+    #[test]
+    fn single_quoted_links_are_rewritten() {
+        let html = "<a HREF='http://example.com'>x</a>";
+        let tracked = add_tracking(html, "http://localhost", "token");
+        assert!(tracked.starts_with(
+            "<a HREF='http://localhost/t/click/token?url=http%3A%2F%2Fexample.com'>x</a>"
+        ));
+    }
+
+    #[test]
+    fn non_http_links_are_left_alone() {
+        let html = r##"<a href="mailto:me@example.com">mail</a> <a href="#top">top</a>"##;
+        let tracked = add_tracking(html, "http://localhost", "token");
+        assert!(tracked.starts_with(html));
+    }
+
+    #[test]
+    fn the_pixel_is_placed_before_the_closing_body_tag() {
+        let html = "<html><body><p>Hi</p></body></html>";
+        let tracked = add_tracking(html, "http://localhost", "token");
+        assert_eq!(
+            tracked,
+            r#"<html><body><p>Hi</p><img src="http://localhost/t/open/token" width="1" height="1" alt=""></body></html>"#
+        );
+    }
+
+    #[test]
+    fn the_pixel_is_appended_to_fragments() {
+        let tracked = add_tracking("plain content", "http://localhost", "token");
+        assert!(tracked.starts_with("plain content<img"));
+    }
+
+    #[test]
+    fn links_are_extracted_in_order() {
+        let html = r#"<a href="https://a.com">a</a><a href='https://b.com'>b</a><a href=c>c</a>"#;
+        assert_eq!(extract_links(html), vec!["https://a.com", "https://b.com"]);
+    }
+}
@@ -0,0 +1,276 @@
+use uuid::Uuid;
+use zero_2_prod::authentication::Role;
+
+use crate::helpers::{assert_is_redirect_to, new_api_client, spawn_app, TestApp, TestUser};
+
+/// Creates a token through the form and picks it from the page it is shown on.
+async fn create_token(app: &TestApp, scopes: &[&str]) -> String {
+    let mut body = serde_json::json!({
+        "name": "deploy script",
+        "valid_for_days": 30,
+    });
+    for scope in scopes {
+        body[format!("{scope}_scope")] = "on".into();
+    }
+    let response = app.post_api_token(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let start = html_page.find("z2p_").expect("the token is not shown");
+    html_page[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect()
+}
+
+async fn get_with_token(app: &TestApp, path: &str, token: &str) -> reqwest::Response {
+    new_api_client()
+        .get(format!("{}{path}", app.address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("failed to execute request")
+}
+
+async fn post_suppression_with_token(app: &TestApp, token: &str) -> reqwest::Response {
+    new_api_client()
+        .post(format!("{}/admin/suppressions", app.address))
+        .bearer_auth(token)
+        .form(&serde_json::json!({
+            "entry": "ursula_le_guin@gmail.com",
+            "reason": "manual"
+        }))
+        .send()
+        .await
+        .expect("failed to execute request")
+}
+
+async fn post_form_with_token(
+    app: &TestApp,
+    path: &str,
+    token: &str,
+    body: &serde_json::Value,
+) -> reqwest::Response {
+    new_api_client()
+        .post(format!("{}{path}", app.address))
+        .bearer_auth(token)
+        .form(body)
+        .send()
+        .await
+        .expect("failed to execute request")
+}
+
+async fn get_token_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .token_id
+}
+
+#[tokio::test]
+async fn a_token_authenticates_requests_without_a_session() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, &["read"]).await;
+
+    // Act
+    let response = get_with_token(&app, "/admin/dashboard", &token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!("Welcome, {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn only_the_hash_of_a_token_is_stored() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let token = create_token(&app, &["read"]).await;
+
+    // Assert
+    let token_hash = sqlx::query!("SELECT token_hash FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .token_hash;
+    assert_ne!(token_hash, token);
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("deploy script"));
+    assert!(!html_page.contains(&token));
+}
+
+#[tokio::test]
+async fn write_requests_need_the_write_scope_but_no_csrf_token() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let read_token = create_token(&app, &["read"]).await;
+    let write_token = create_token(&app, &["read", "write"]).await;
+
+    // Act
+    let read_response = post_suppression_with_token(&app, &read_token).await;
+    let write_response = post_suppression_with_token(&app, &write_token).await;
+
+    // Assert
+    assert_eq!(read_response.status().as_u16(), 403);
+    assert_is_redirect_to(&write_response, "/admin/suppressions");
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+}
+
+#[tokio::test]
+async fn the_role_of_the_user_still_applies_to_their_tokens() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::generate_with_role(Role::Viewer);
+    viewer.store(&app.db_pool).await;
+    viewer.login(&app).await;
+    let token = create_token(&app, &["read", "write"]).await;
+
+    // Act
+    let response = post_suppression_with_token(&app, &token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn unknown_revoked_and_expired_tokens_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let revoked_token = create_token(&app, &["read"]).await;
+    let response = app.post_revoke_api_token(get_token_id(&app).await).await;
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+    let expired_token = create_token(&app, &["read"]).await;
+    sqlx::query!("UPDATE api_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    for token in ["z2p_unknown", &revoked_token, &expired_token] {
+        // Act
+        let response = get_with_token(&app, "/admin/dashboard", token).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 401);
+        assert!(response.headers().contains_key("WWW-Authenticate"));
+    }
+}
+
+#[tokio::test]
+async fn tokens_cannot_manage_tokens() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, &["read", "write"]).await;
+
+    // Act
+    let response = get_with_token(&app, "/admin/api-tokens", &token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn tokens_need_a_scope_and_a_limited_validity() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "name": "no scope", "valid_for_days": 30 }),
+            "Choose at least one scope.",
+        ),
+        (
+            serde_json::json!({ "name": "forever", "read_scope": "on", "valid_for_days": 10000 }),
+            "Tokens can be valid for 1 to 365 days.",
+        ),
+        (
+            serde_json::json!({ "name": " ", "read_scope": "on", "valid_for_days": 30 }),
+            "The name must be between 1 and 100 characters long.",
+        ),
+    ];
+
+    for (body, error_message) in test_cases {
+        // Act
+        let response = app.post_api_token(&body).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/api-tokens");
+        assert!(app.get_api_tokens_html().await.contains(error_message));
+    }
+}
+
+#[tokio::test]
+async fn tokens_cannot_change_the_password() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, &["read", "write"]).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    let response = post_form_with_token(
+        &app,
+        "/admin/password",
+        &token,
+        &serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    app.test_user.login(&app).await;
+}
+
+#[tokio::test]
+async fn tokens_cannot_disable_two_factor_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, &["read", "write"]).await;
+
+    // Act
+    let response = post_form_with_token(
+        &app,
+        "/admin/two-factor/disable",
+        &token,
+        &serde_json::json!({ "current_password": &app.test_user.password }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn tokens_cannot_revoke_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = create_token(&app, &["read", "write"]).await;
+
+    // Act
+    let response = post_form_with_token(
+        &app,
+        "/admin/sessions/revoke-others",
+        &token,
+        &serde_json::json!({}),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,306 @@
+use uuid::Uuid;
+use zero_2_prod::authentication::{list_users, Role};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp, TestUser,
+};
+
+struct StoredEvent {
+    user_id: Option<Uuid>,
+    action: String,
+    payload: serde_json::Value,
+}
+
+async fn stored_events(app: &TestApp) -> Vec<StoredEvent> {
+    sqlx::query!(
+        r#"SELECT user_id, action, payload::text AS "payload!" FROM audit_events ORDER BY event_id"#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| StoredEvent {
+        user_id: r.user_id,
+        action: r.action,
+        payload: serde_json::from_str(&r.payload).unwrap(),
+    })
+    .collect()
+}
+
+#[tokio::test]
+async fn logins_failed_logins_and_logouts_are_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password",
+    }))
+    .await;
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+
+    // Assert
+    let events = stored_events(&app).await;
+    let actions: Vec<_> = events.iter().map(|e| e.action.as_str()).collect();
+    assert_eq!(actions, ["login_failed", "login", "logout"]);
+    assert_eq!(events[0].user_id, None);
+    assert_eq!(events[0].payload["username"], app.test_user.username);
+    assert_eq!(events[1].user_id, Some(app.test_user.user_id));
+    assert_eq!(events[2].user_id, Some(app.test_user.user_id));
+}
+
+#[tokio::test]
+async fn publishing_an_issue_is_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_newsletter_issue(&serde_json::json!({
+            "title": "newsletter title",
+            "content": "newsletter content",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    let events = stored_events(&app).await;
+    let event = events.last().unwrap();
+    assert_eq!(event.action, "issue_published");
+    assert_eq!(event.user_id, Some(app.test_user.user_id));
+    assert_eq!(event.payload["title"], "newsletter title");
+}
+
+#[tokio::test]
+async fn changing_the_password_is_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let new_password = "a much better passphrase";
+
+    // Act
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": new_password,
+            "new_password_check": new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    // Assert
+    let events = stored_events(&app).await;
+    let event = events.last().unwrap();
+    assert_eq!(event.action, "password_changed");
+    assert_eq!(event.user_id, Some(app.test_user.user_id));
+    assert_eq!(event.payload["via"], "settings");
+}
+
+#[tokio::test]
+async fn pausing_resuming_and_cancelling_a_delivery_are_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_newsletter_issue(&serde_json::json!({
+        "title": "newsletter title",
+        "content": "newsletter content",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    // Act
+    for action in ["pause", "resume", "cancel"] {
+        app.post_issue_delivery_action(issue_id, action).await;
+    }
+
+    // Assert
+    let events = stored_events(&app).await;
+    let actions: Vec<_> = events
+        .iter()
+        .rev()
+        .take(3)
+        .map(|e| e.action.as_str())
+        .collect();
+    assert_eq!(
+        actions,
+        [
+            "issue_delivery_cancelled",
+            "issue_delivery_resumed",
+            "issue_delivery_paused"
+        ]
+    );
+    let event = events.last().unwrap();
+    assert_eq!(event.user_id, Some(app.test_user.user_id));
+    assert_eq!(event.payload["issue_id"], issue_id.to_string());
+}
+
+#[tokio::test]
+async fn disabling_two_factor_authentication_and_revoking_sessions_are_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    sqlx::query!(
+        "UPDATE users SET totp_secret = 'JBSWY3DPEHPK3PXP' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.post_two_factor_disable(&app.test_user.password).await;
+    app.post_revoke_other_sessions().await;
+
+    // Assert
+    let events = stored_events(&app).await;
+    let actions: Vec<_> = events.iter().map(|e| e.action.as_str()).collect();
+    assert_eq!(
+        actions,
+        ["login", "two_factor_disabled", "other_sessions_revoked"]
+    );
+    assert!(events
+        .iter()
+        .all(|e| e.user_id == Some(app.test_user.user_id)));
+}
+
+#[tokio::test]
+async fn lockouts_are_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    for _ in 0..5 {
+        app.post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "wrong-password",
+        }))
+        .await;
+    }
+
+    // Assert
+    let events = stored_events(&app).await;
+    let event = events
+        .iter()
+        .find(|e| e.action == "login_locked_out")
+        .expect("the lockout has not been recorded");
+    assert_eq!(event.user_id, None);
+    assert_eq!(
+        event.payload["throttle_key"],
+        format!("username:{}", app.test_user.username)
+    );
+    assert_eq!(event.payload["n_failures"], 5);
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_filtered_by_action_and_user() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_user = TestUser::generate_with_role(Role::Owner);
+    other_user.store(&app.db_pool).await;
+    other_user.login(&app).await;
+    app.post_logout().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let all_html = app.get_audit_log_html("").await;
+    let logouts_html = app.get_audit_log_html("?action=logout&username=").await;
+    let own_html = app
+        .get_audit_log_html(&format!("?action=&username={}", app.test_user.username))
+        .await;
+
+    // Assert
+    assert!(all_html.contains("<td>logout</td>"));
+    assert!(all_html.contains(&other_user.username));
+    assert!(all_html.contains(&app.test_user.username));
+    assert!(logouts_html.contains("<td>logout</td>"));
+    assert!(!logouts_html.contains("<td>login</td>"));
+    assert!(own_html.contains("<td>login</td>"));
+    assert!(!own_html.contains("<td>logout</td>"));
+}
+
+#[tokio::test]
+async fn an_unknown_action_filter_is_ignored() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_audit_log("?action=nonsense&username=").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("<td>login</td>"));
+}
+
+#[tokio::test]
+async fn a_change_is_not_committed_without_its_audit_event() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role(Role::Editor);
+    editor.store(&app.db_pool).await;
+    app.test_user.login(&app).await;
+    sqlx::query!("ALTER TABLE audit_events RENAME TO audit_events_gone")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_user_role(&serde_json::json!({
+            "username": &editor.username,
+            "role": "viewer"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+    let users = list_users(&app.db_pool).await.unwrap();
+    let editor = users
+        .iter()
+        .find(|u| u.username == editor.username)
+        .unwrap();
+    assert_eq!(editor.role, Role::Editor);
+}
+
+#[tokio::test]
+async fn only_owners_can_see_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role(Role::Editor);
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+    // Act
+    let response = app.get_audit_log("").await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn audit_events_cannot_be_changed_or_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let update = sqlx::query!("UPDATE audit_events SET action = 'nothing'")
+        .execute(&app.db_pool)
+        .await;
+    let delete = sqlx::query!("DELETE FROM audit_events")
+        .execute(&app.db_pool)
+        .await;
+
+    // Assert
+    assert!(update.is_err());
+    assert!(delete.is_err());
+    assert_eq!(stored_events(&app).await[0].action, "login");
+}
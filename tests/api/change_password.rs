@@ -78,6 +78,60 @@ async fn password_must_have_more_than_12_characters() {
     assert!(html_page.contains("<p><i>The password must contain at least 12 characters.</i></p>"));
 }
 
+#[tokio::test]
+async fn common_passwords_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let common_pw = "Password1234";
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+
+    // Act
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": common_pw,
+            "new_password_check": common_pw
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains(
+        "<p><i>The password is too common - it appears in lists of leaked passwords.</i></p>"
+    ));
+}
+
+#[tokio::test]
+async fn passwords_containing_the_username_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let new_password = format!("my name is {}", app.test_user.username.to_uppercase());
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+
+    // Act
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("<p><i>The password must not contain the username.</i></p>"));
+}
+
 #[tokio::test]
 async fn current_password_must_be_valid() {
     // Arrange
@@ -5,7 +5,7 @@ async fn health_check_works() {
     let app = spawn_app().await;
     let client = reqwest::Client::new();
     let response = client
-        .get(&format!("{address}/health_check", address = app.address))
+        .get(format!("{address}/health_check", address = app.address))
         .send()
         .await
         .expect("failed to execute request");
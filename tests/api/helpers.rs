@@ -1,12 +1,20 @@
 use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::Fake;
 use once_cell::sync::Lazy;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, Request, ResponseTemplate,
+};
 use zero_2_prod::{
-    configuration::{get_configuration, DataBaseSettings},
+    authentication::Role,
+    configuration::{get_configuration, DataBaseSettings, PasswordHashingSettings},
     email_client::EmailClient,
     issue_delivery_worker::{try_execute_task, ExecutionOutcome},
+    rate_limit::DeliveryRateLimiter,
     startup::{get_connection_pool, Application},
     telemetry::{get_subscriber, init_subscriber},
 };
@@ -37,12 +45,17 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub base_url: String,
+    pub rate_limiter: DeliveryRateLimiter,
+    pub batch_size: i64,
+    pub password_hashing: PasswordHashingSettings,
 }
 
 pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
     pub password: String,
+    pub role: Role,
 }
 
 impl TestUser {
@@ -56,15 +69,20 @@ impl TestUser {
     }
 
     pub fn generate() -> Self {
+        Self::generate_with_role(Role::Owner)
+    }
+
+    pub fn generate_with_role(role: Role) -> Self {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
             // password: Uuid::new_v4().to_string(),
             password: "everythinghastostartsomewhere".into(),
+            role,
         }
     }
 
-    async fn store(&self, pool: &PgPool) {
+    pub async fn store(&self, pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
         let password_hash = Argon2::new(
             Algorithm::Argon2id,
@@ -76,11 +94,12 @@ impl TestUser {
         .to_string();
 
         sqlx::query!(
-            "INSERT INTO users (user_id, username, password_hash)
-            VALUES ($1, $2, $3)",
+            "INSERT INTO users (user_id, username, password_hash, role)
+            VALUES ($1, $2, $3, $4)",
             self.user_id,
             self.username,
-            password_hash
+            password_hash,
+            self.role.as_str()
         )
         .execute(pool)
         .await
@@ -89,21 +108,130 @@ impl TestUser {
 }
 
 impl TestApp {
+    /// The CSRF token of the current session, taken from the login form like a browser would.
+    pub async fn csrf_token(&self) -> String {
+        let html = self.get_login_html().await;
+        let marker = r#"name="csrf_token" value=""#;
+        let start = html.find(marker).expect("the login form has no CSRF token") + marker.len();
+        html[start..].split('"').next().unwrap().to_owned()
+    }
+
+    /// Adds the CSRF token of the current session to a form.
+    async fn with_csrf_token<Body>(&self, body: &Body) -> serde_json::Value
+    where
+        Body: serde::Serialize,
+    {
+        let mut body = serde_json::to_value(body).unwrap();
+        body["csrf_token"] = self.csrf_token().await.into();
+        body
+    }
+
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            match try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.rate_limiter,
+                &self.base_url,
+                self.batch_size,
+            )
+            .await
+            .unwrap()
             {
-                break;
+                ExecutionOutcome::EmptyQueue | ExecutionOutcome::RateLimited { .. } => break,
+                ExecutionOutcome::TaskCompleted => {}
             }
         }
     }
 
+    pub async fn get_sessions_html(&self) -> String {
+        self.api_client
+            .get(format!("{address}/admin/sessions", address = &self.address))
+            .send()
+            .await
+            .expect("failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_revoke_session(&self, session_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{address}/admin/sessions/revoke",
+                address = &self.address
+            ))
+            .form(
+                &self
+                    .with_csrf_token(&serde_json::json!({ "session_id": session_id }))
+                    .await,
+            )
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn post_revoke_other_sessions(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{address}/admin/sessions/revoke-others",
+                address = &self.address
+            ))
+            .form(&self.with_csrf_token(&serde_json::json!({})).await)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn get_api_tokens_html(&self) -> String {
+        self.api_client
+            .get(format!(
+                "{address}/admin/api-tokens",
+                address = &self.address
+            ))
+            .send()
+            .await
+            .expect("failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_api_token<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{address}/admin/api-tokens",
+                address = &self.address
+            ))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn post_revoke_api_token(&self, token_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{address}/admin/api-tokens/revoke",
+                address = &self.address
+            ))
+            .form(
+                &self
+                    .with_csrf_token(&serde_json::json!({ "token_id": token_id }))
+                    .await,
+            )
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{address}/admin/logout", address = &self.address))
+            .post(format!("{address}/admin/logout", address = &self.address))
+            .form(&self.with_csrf_token(&serde_json::json!({})).await)
             .send()
             .await
             .expect("failed to execute request")
@@ -111,7 +239,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{address}/admin/password", address = self.address))
+            .get(format!("{address}/admin/password", address = self.address))
             .send()
             .await
             .expect("failed to send request")
@@ -126,11 +254,11 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!(
+            .post(format!(
                 "{address}/admin/newsletters",
                 address = self.address
             ))
-            .form(body)
+            .form(&self.with_csrf_token(body).await)
             .send()
             .await
             .expect("failed to execute request")
@@ -141,8 +269,8 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{address}/admin/password", address = self.address))
-            .form(body)
+            .post(format!("{address}/admin/password", address = self.address))
+            .form(&self.with_csrf_token(body).await)
             .send()
             .await
             .expect("failed to execute request")
@@ -150,7 +278,7 @@ impl TestApp {
 
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!(
+            .get(format!(
                 "{address}/admin/newsletters",
                 address = &self.address,
             ))
@@ -163,9 +291,267 @@ impl TestApp {
         self.get_publish_newsletter().await.text().await.unwrap()
     }
 
+    pub async fn get_issues_html(&self) -> String {
+        self.api_client
+            .get(format!("{address}/admin/issues", address = &self.address))
+            .send()
+            .await
+            .expect("failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_issue_status(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{address}/admin/issues/{issue_id}",
+                address = &self.address
+            ))
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn get_issue_status_html(&self, issue_id: Uuid) -> String {
+        self.get_issue_status(issue_id).await.text().await.unwrap()
+    }
+
+    pub async fn post_issue_delivery_action(
+        &self,
+        issue_id: Uuid,
+        action: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{address}/admin/issues/{issue_id}/{action}",
+                address = &self.address
+            ))
+            .form(&self.with_csrf_token(&serde_json::json!({})).await)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn get_drafts_html(&self) -> String {
+        self.api_client
+            .get(format!("{address}/admin/drafts", address = &self.address))
+            .send()
+            .await
+            .expect("failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_draft<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{address}/admin/drafts", address = &self.address))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn post_publish_draft(&self, draft_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{address}/admin/drafts/{draft_id}/publish",
+                address = &self.address
+            ))
+            .form(&self.with_csrf_token(&serde_json::json!({})).await)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn get_users(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{address}/admin/users", address = &self.address))
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn post_user_role<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{address}/admin/users/role",
+                address = &self.address
+            ))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn post_user_invite<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{address}/admin/users/invite",
+                address = &self.address
+            ))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn post_accept_invite<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{address}/invites/accept", address = &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn post_password_reset_request<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{address}/password-reset", address = &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn post_password_reset<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{address}/password-reset/confirm",
+                address = &self.address
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn get_subscribers_html(&self) -> String {
+        self.api_client
+            .get(format!(
+                "{address}/admin/subscribers",
+                address = &self.address
+            ))
+            .send()
+            .await
+            .expect("failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_subscriber_tracking<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{address}/admin/subscribers/tracking",
+                address = &self.address
+            ))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn get_suppressions(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{address}/admin/suppressions",
+                address = &self.address
+            ))
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn get_suppressions_html(&self) -> String {
+        self.get_suppressions().await.text().await.unwrap()
+    }
+
+    pub async fn post_suppression<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{address}/admin/suppressions",
+                address = &self.address
+            ))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn post_suppression_import<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{address}/admin/suppressions/import",
+                address = &self.address
+            ))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn post_suppression_removal<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{address}/admin/suppressions/remove",
+                address = &self.address
+            ))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn get_audit_log(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{address}/admin/audit{query}",
+                address = &self.address
+            ))
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn get_audit_log_html(&self, query: &str) -> String {
+        self.get_audit_log(query).await.text().await.unwrap()
+    }
+
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!(
+            .get(format!(
                 "{address}/admin/dashboard",
                 address = &self.address,
             ))
@@ -194,8 +580,70 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{address}/login", address = &self.address))
-            .form(body)
+            .post(format!("{address}/login", address = &self.address))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn post_login_second_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{address}/login/two-factor",
+                address = &self.address
+            ))
+            .form(
+                &self
+                    .with_csrf_token(&serde_json::json!({ "code": code }))
+                    .await,
+            )
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn get_two_factor_html(&self) -> String {
+        self.api_client
+            .get(format!(
+                "{address}/admin/two-factor",
+                address = &self.address
+            ))
+            .send()
+            .await
+            .expect("failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_two_factor_enable(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{address}/admin/two-factor/enable",
+                address = &self.address
+            ))
+            .form(
+                &self
+                    .with_csrf_token(&serde_json::json!({ "code": code }))
+                    .await,
+            )
+            .send()
+            .await
+            .expect("failed to execute request")
+    }
+
+    pub async fn post_two_factor_disable(&self, current_password: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{address}/admin/two-factor/disable",
+                address = &self.address
+            ))
+            .form(
+                &self
+                    .with_csrf_token(&serde_json::json!({ "current_password": current_password }))
+                    .await,
+            )
             .send()
             .await
             .expect("failed to execute request")
@@ -204,7 +652,7 @@ impl TestApp {
     pub async fn post_subscriptions(&self, body: impl Into<String>) -> reqwest::Response {
         let body = body.into();
         self.api_client
-            .post(&format!("{address}/subscriptions", address = self.address))
+            .post(format!("{address}/subscriptions", address = self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -227,8 +675,8 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
 }
@@ -238,6 +686,15 @@ pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.headers().get("Location").unwrap(), location);
 }
 
+/// A client with a cookie store of its own - like another browser.
+pub fn new_api_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap()
+}
+
 pub async fn spawn_app() -> TestApp {
     Lazy::force(&TRACING);
 
@@ -259,13 +716,9 @@ pub async fn spawn_app() -> TestApp {
         .expect("failed to build application");
     let port = application.port();
     let address = format!("http://127.0.0.1:{port}");
-    let _ = tokio::spawn(application.run_until_stopped());
+    tokio::spawn(application.run_until_stopped());
 
-    let client = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .cookie_store(true)
-        .build()
-        .unwrap();
+    let client = new_api_client();
 
     let test_app = TestApp {
         db_pool: get_connection_pool(&configuration.database).await,
@@ -275,6 +728,10 @@ pub async fn spawn_app() -> TestApp {
         test_user: TestUser::generate(),
         api_client: client,
         email_client: configuration.email_client.client(),
+        base_url: configuration.application.base_url.clone(),
+        rate_limiter: DeliveryRateLimiter::new(&configuration.delivery_rate_limit),
+        batch_size: configuration.delivery_worker.batch_size,
+        password_hashing: configuration.password_hashing,
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
@@ -305,3 +762,61 @@ async fn configure_database(config: &DataBaseSettings) -> PgPool {
 
     connection_pool
 }
+
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let name: String = Name().fake();
+    let email: String = SafeEmail().fake();
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": name,
+        "email": email
+    }))
+    .unwrap();
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request)
+}
+
+/// Accepts every email of a batch request, answering like the email provider does.
+pub fn accept_all_emails(request: &Request) -> ResponseTemplate {
+    let emails: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+    let results: Vec<_> = emails
+        .iter()
+        .map(|email| {
+            serde_json::json!({
+                "To": email["To"],
+                "MessageID": Uuid::new_v4().to_string(),
+                "ErrorCode": 0,
+                "Message": "OK"
+            })
+        })
+        .collect();
+    ResponseTemplate::new(200).set_body_json(results)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
@@ -0,0 +1,221 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero_2_prod::authentication::{list_users, Role};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+
+const INVITEE_EMAIL: &str = "teammate@example.com";
+
+/// Invites a teammate as the owner and returns the link from the invite email.
+async fn invite_teammate(app: &TestApp, role: &str) -> reqwest::Url {
+    app.test_user.login(app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_user_invite(&serde_json::json!({
+            "email": INVITEE_EMAIL,
+            "role": role
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    app.post_logout().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    app.get_confirmation_links(email_request).plain_text
+}
+
+fn invite_token(invite_link: &reqwest::Url) -> String {
+    invite_link
+        .query_pairs()
+        .find(|(k, _)| k == "invite_token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+fn acceptance(invite_link: &reqwest::Url, password_check: &str) -> serde_json::Value {
+    serde_json::json!({
+        "invite_token": invite_token(invite_link),
+        "username": "teammate",
+        "password": "a long enough password",
+        "password_check": password_check
+    })
+}
+
+#[tokio::test]
+async fn invited_users_can_log_in_with_the_role_they_were_invited_as() {
+    // Arrange
+    let app = spawn_app().await;
+    let invite_link = invite_teammate(&app, "editor").await;
+
+    // Act - Part 1 - open the invite
+    let response = app
+        .api_client
+        .get(invite_link.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("invited as editor"));
+
+    // Act - Part 2 - accept it
+    let response = app
+        .post_accept_invite(&acceptance(&invite_link, "a long enough password"))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Assert
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "teammate",
+            "password": "a long enough password"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let users = list_users(&app.db_pool).await.unwrap();
+    let teammate = users.iter().find(|u| u.username == "teammate").unwrap();
+    assert_eq!(teammate.role, Role::Editor);
+}
+
+#[tokio::test]
+async fn invite_tokens_are_not_stored_in_plain_text() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let invite_link = invite_teammate(&app, "viewer").await;
+
+    // Assert
+    let stored = sqlx::query!("SELECT token_hash FROM user_invites")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored.token_hash, invite_token(&invite_link));
+}
+
+#[tokio::test]
+async fn invites_can_only_be_accepted_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let invite_link = invite_teammate(&app, "viewer").await;
+    app.post_accept_invite(&acceptance(&invite_link, "a long enough password"))
+        .await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(invite_link.clone())
+        .send()
+        .await
+        .unwrap();
+    let mut second_acceptance = acceptance(&invite_link, "a long enough password");
+    second_acceptance["username"] = "someone else".into();
+    app.post_accept_invite(&second_acceptance).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("the invite is invalid or has expired"));
+    assert_eq!(list_users(&app.db_pool).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn expired_invites_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let invite_link = invite_teammate(&app, "viewer").await;
+    sqlx::query!("UPDATE user_invites SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_accept_invite(&acceptance(&invite_link, "a long enough password"))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(list_users(&app.db_pool).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn invitees_have_to_follow_the_password_rules() {
+    // Arrange
+    let app = spawn_app().await;
+    let invite_link = invite_teammate(&app, "viewer").await;
+
+    // Act
+    let response = app
+        .post_accept_invite(&acceptance(&invite_link, "a different password"))
+        .await;
+
+    // Assert
+    let form_location = format!("{}?{}", invite_link.path(), invite_link.query().unwrap());
+    assert_is_redirect_to(&response, &form_location);
+    let html_page = app
+        .api_client
+        .get(invite_link)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("You entered two different new passwords"));
+    assert_eq!(list_users(&app.db_pool).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn an_invite_with_an_invalid_role_is_rejected_with_a_flash_message() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_user_invite(&serde_json::json!({
+            "email": INVITEE_EMAIL,
+            "role": "emperor"
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users().await.text().await.unwrap();
+    assert!(html_page.contains("emperor is not a valid role"));
+    let n_invites = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM user_invites"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_invites, 0);
+}
+
+#[tokio::test]
+async fn only_owners_can_invite_users() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role(Role::Editor);
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    // Act
+    let response = app
+        .post_user_invite(&serde_json::json!({
+            "email": INVITEE_EMAIL,
+            "role": "owner"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
@@ -0,0 +1,430 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use zero_2_prod::{
+    configuration::DeliveryRateLimitSettings, issue_delivery_worker::try_execute_task,
+    rate_limit::DeliveryRateLimiter,
+};
+
+use crate::helpers::{
+    accept_all_emails, assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp,
+};
+
+async fn publish_issue(app: &TestApp) -> Uuid {
+    let response = app
+        .post_newsletter_issue(&serde_json::json!({
+            "title": "newsletter title",
+            "content": "newsletter content",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_issue_status() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app.get_issue_status(Uuid::new_v4()).await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_status_of_an_unknown_issue_is_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // Act
+    let response = app.get_issue_status(Uuid::new_v4()).await;
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn successful_deliveries_are_recorded_with_the_provider_message_id() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!([{
+                "MessageID": "0a129aee-e1cd-480d-b08d-4f48548ff48d",
+                "ErrorCode": 0,
+                "Message": "OK"
+            }])),
+        )
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act I - Publish
+    let issue_id = publish_issue(&app).await;
+    let html_page = app.get_issue_status_html(issue_id).await;
+    assert!(html_page.contains("Pending: 1"));
+    assert!(html_page.contains("0 of 1 recipients processed"));
+
+    // Act II - Deliver
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery = sqlx::query!(
+        "SELECT status, n_attempts, provider_message_id FROM issue_deliveries \
+        WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivery.status, "sent");
+    assert_eq!(delivery.n_attempts, 1);
+    assert_eq!(
+        delivery.provider_message_id.as_deref(),
+        Some("0a129aee-e1cd-480d-b08d-4f48548ff48d")
+    );
+    let html_page = app.get_issue_status_html(issue_id).await;
+    assert!(html_page.contains("Sent: 1"));
+    assert!(html_page.contains("1 of 1 recipients processed (100.0%)"));
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains(&format!("/admin/issues/{issue_id}")));
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_before_they_are_recorded_as_failed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_issue(&app).await;
+
+    // Act I - The first attempt fails and the task is postponed
+    app.dispatch_all_pending_emails().await;
+    let delivery = sqlx::query!(
+        "SELECT status, n_attempts FROM issue_deliveries WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivery.status, "pending");
+    assert_eq!(delivery.n_attempts, 1);
+
+    // Act II - Make the remaining retries due right away
+    for _ in 0..2 {
+        sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+        app.dispatch_all_pending_emails().await;
+    }
+
+    // Assert
+    let delivery = sqlx::query!(
+        "SELECT status, n_attempts FROM issue_deliveries WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivery.status, "failed");
+    assert_eq!(delivery.n_attempts, 3);
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+    let html_page = app.get_issue_status_html(issue_id).await;
+    assert!(html_page.contains("Failed: 1"));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_pause_a_delivery() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app
+        .post_issue_delivery_action(Uuid::new_v4(), "pause")
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn paused_deliveries_are_not_dispatched_until_resumed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_issue(&app).await;
+
+    // Act I - Pause
+    let response = app.post_issue_delivery_action(issue_id, "pause").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{issue_id}"));
+    let html_page = app.get_issue_status_html(issue_id).await;
+    assert!(html_page.contains("The delivery of the issue has been paused."));
+    assert!(html_page.contains("Delivery state: paused"));
+
+    // Act II - Nothing is sent while paused
+    let guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_all_emails)
+        .expect(0)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    drop(guard);
+    let html_page = app.get_issue_status_html(issue_id).await;
+    assert!(html_page.contains("Pending: 1"));
+
+    // Act III - Resume
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_all_emails)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_issue_delivery_action(issue_id, "resume").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{issue_id}"));
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html_page = app.get_issue_status_html(issue_id).await;
+    assert!(html_page.contains("Delivery state: active"));
+    assert!(html_page.contains("Sent: 1"));
+}
+
+#[tokio::test]
+async fn cancelling_a_delivery_drops_the_remaining_recipients() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_all_emails)
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_issue(&app).await;
+
+    // Act
+    let response = app.post_issue_delivery_action(issue_id, "cancel").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{issue_id}"));
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+    let html_page = app.get_issue_status_html(issue_id).await;
+    assert!(html_page.contains("The delivery of the issue has been cancelled."));
+    assert!(html_page.contains("Delivery state: cancelled"));
+    assert!(html_page.contains("Cancelled: 1"));
+    assert!(html_page.contains("1 of 1 recipients processed"));
+    // sent, failed, skipped, cancelled and pending add up to the number of recipients
+    let html_page: String = app.get_issues_html().await.split_whitespace().collect();
+    assert!(
+        html_page.contains("<td>cancelled</td><td>0</td><td>0</td><td>0</td><td>1</td><td>0</td>")
+    );
+}
+
+#[tokio::test]
+async fn a_cancelled_delivery_cannot_be_resumed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_issue(&app).await;
+    app.post_issue_delivery_action(issue_id, "cancel").await;
+
+    // Act
+    let response = app.post_issue_delivery_action(issue_id, "resume").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{issue_id}"));
+
+    // Assert
+    let html_page = app.get_issue_status_html(issue_id).await;
+    assert!(html_page.contains("Only paused deliveries can be resumed."));
+    assert!(html_page.contains("Delivery state: cancelled"));
+}
+
+/// Moves every issue into `delivery_state` as soon as a worker asks the returned rate limiter for
+/// a token, as if an admin had stopped the issue while the worker was working on the batch.
+async fn change_delivery_state_once_dequeued(
+    app: &TestApp,
+    delivery_state: &str,
+) -> DeliveryRateLimiter {
+    sqlx::query(&format!(
+        r#"
+        CREATE FUNCTION change_delivery_state() RETURNS trigger AS $$
+        BEGIN
+            UPDATE newsletter_issues SET delivery_state = '{delivery_state}';
+            RETURN NULL;
+        END
+        $$ LANGUAGE plpgsql
+        "#
+    ))
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        r#"
+        CREATE TRIGGER change_delivery_state
+        AFTER INSERT ON delivery_rate_limits
+        FOR EACH STATEMENT
+        EXECUTE FUNCTION change_delivery_state()
+        "#,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    DeliveryRateLimiter::new(&DeliveryRateLimitSettings {
+        per_day: Some(1000),
+        ..Default::default()
+    })
+}
+
+async fn execute_batch(app: &TestApp, rate_limiter: &DeliveryRateLimiter) {
+    try_execute_task(
+        &app.db_pool,
+        &app.email_client,
+        rate_limiter,
+        &app.base_url,
+        app.batch_size,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn dequeued_deliveries_of_a_cancelled_issue_are_not_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_all_emails)
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_issue(&app).await;
+    let rate_limiter = change_delivery_state_once_dequeued(&app, "cancelled").await;
+
+    // Act
+    execute_batch(&app, &rate_limiter).await;
+
+    // Assert
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+    let html_page = app.get_issue_status_html(issue_id).await;
+    assert!(html_page.contains("Cancelled: 1"));
+}
+
+#[tokio::test]
+async fn dequeued_deliveries_of_a_paused_issue_are_released() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_all_emails)
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_issue(&app).await;
+    let rate_limiter = change_delivery_state_once_dequeued(&app, "paused").await;
+
+    // Act
+    execute_batch(&app, &rate_limiter).await;
+
+    // Assert
+    let n_due = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue WHERE execute_after <= now()"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_due, 1);
+    let html_page = app.get_issue_status_html(issue_id).await;
+    assert!(html_page.contains("Pending: 1"));
+}
+
+#[tokio::test]
+async fn emails_rejected_within_a_batch_are_retried_individually() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {
+                "MessageID": "0a129aee-e1cd-480d-b08d-4f48548ff48d",
+                "ErrorCode": 0,
+                "Message": "OK"
+            },
+            {
+                "ErrorCode": 300,
+                "Message": "Invalid email request"
+            }
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_issue(&app).await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let deliveries = sqlx::query!(
+        "SELECT status, last_error FROM issue_deliveries \
+        WHERE newsletter_issue_id = $1 ORDER BY status",
+        issue_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(deliveries.len(), 2);
+    assert_eq!(deliveries[0].status, "pending");
+    assert!(deliveries[0]
+        .last_error
+        .as_deref()
+        .unwrap()
+        .contains("Invalid email request"));
+    assert_eq!(deliveries[1].status, "sent");
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 1);
+}
@@ -0,0 +1,263 @@
+use chrono::Duration;
+use uuid::Uuid;
+use zero_2_prod::authentication::{create_api_token, ApiScope, Role};
+
+use crate::helpers::{create_confirmed_subscriber, new_api_client, spawn_app, TestApp, TestUser};
+
+async fn api_token(app: &TestApp, user: &TestUser) -> String {
+    create_api_token(
+        user.user_id,
+        "test",
+        &[ApiScope::Read, ApiScope::Write],
+        Duration::days(1),
+        &app.db_pool,
+    )
+    .await
+    .unwrap()
+}
+
+fn draft_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "newsletter title",
+        "content": "newsletter content",
+    })
+}
+
+async fn post_draft(app: &TestApp, token: &str, idempotency_key: &str) -> reqwest::Response {
+    new_api_client()
+        .post(format!("{}/api/v1/issues", app.address))
+        .bearer_auth(token)
+        .header("Idempotency-Key", idempotency_key)
+        .json(&draft_body())
+        .send()
+        .await
+        .expect("failed to execute request")
+}
+
+async fn post_publish(
+    app: &TestApp,
+    token: &str,
+    draft_id: &str,
+    idempotency_key: &str,
+) -> reqwest::Response {
+    new_api_client()
+        .post(format!("{}/api/v1/issues/{draft_id}/publish", app.address))
+        .bearer_auth(token)
+        .header("Idempotency-Key", idempotency_key)
+        .send()
+        .await
+        .expect("failed to execute request")
+}
+
+async fn get_json(app: &TestApp, token: &str, path: &str) -> (u16, serde_json::Value) {
+    let response = new_api_client()
+        .get(format!("{}{path}", app.address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("failed to execute request");
+    (
+        response.status().as_u16(),
+        response.json().await.unwrap_or_default(),
+    )
+}
+
+async fn create_draft(app: &TestApp, token: &str) -> String {
+    let response = post_draft(app, token, &Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let draft: serde_json::Value = response.json().await.unwrap();
+    draft["issue_id"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn the_api_requires_an_api_token() {
+    // Arrange
+    let app = spawn_app().await;
+    // a session is not enough
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/api/v1/issues", app.address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.headers().contains_key("WWW-Authenticate"));
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "the request carries no api token");
+}
+
+#[tokio::test]
+async fn drafts_can_be_created_updated_and_published() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = api_token(&app, &app.test_user).await;
+
+    // Act I - Create a draft
+    let response = post_draft(&app, &token, "create").await;
+    assert_eq!(response.status().as_u16(), 201);
+    let draft: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(draft["status"], "draft");
+    let draft_id = draft["issue_id"].as_str().unwrap();
+
+    // Act II - Update it
+    let response = new_api_client()
+        .put(format!("{}/api/v1/issues/{draft_id}", app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "title": "better title",
+            "content": "newsletter content",
+            "tracking_enabled": true,
+        }))
+        .send()
+        .await
+        .expect("failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    let (_, draft) = get_json(&app, &token, &format!("/api/v1/issues/{draft_id}")).await;
+    assert_eq!(draft["title"], "better title");
+    assert_eq!(draft["tracking_enabled"], true);
+
+    // Act III - Publish it
+    let response = post_publish(&app, &token, draft_id, "publish").await;
+    assert_eq!(response.status().as_u16(), 201);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["status"], "published");
+    assert_eq!(issue["title"], "better title");
+    assert_eq!(issue["delivery"]["state"], "active");
+    assert_eq!(issue["delivery"]["n_pending"], 1);
+
+    // Act IV - Get its status and list the issues
+    let issue_id = issue["issue_id"].as_str().unwrap();
+    let (status, issue) = get_json(&app, &token, &format!("/api/v1/issues/{issue_id}")).await;
+    assert_eq!(status, 200);
+    assert_eq!(issue["status"], "published");
+    let (_, list) = get_json(&app, &token, "/api/v1/issues").await;
+    let issues = list["issues"].as_array().unwrap();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0]["issue_id"], issue_id);
+    let (status, _) = get_json(&app, &token, &format!("/api/v1/issues/{draft_id}")).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn publishing_is_idempotent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = api_token(&app, &app.test_user).await;
+    let draft_id = create_draft(&app, &token).await;
+
+    // Act
+    let response1 = post_publish(&app, &token, &draft_id, "publish").await;
+    let response2 = post_publish(&app, &token, &draft_id, "publish").await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 201);
+    assert_eq!(response2.status().as_u16(), 201);
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 1);
+}
+
+#[tokio::test]
+async fn creating_and_publishing_require_an_idempotency_key() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = api_token(&app, &app.test_user).await;
+
+    // Act
+    let response = new_api_client()
+        .post(format!("{}/api/v1/issues", app.address))
+        .bearer_auth(&token)
+        .json(&draft_body())
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let error: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(error["error"], "the Idempotency-Key header is missing");
+}
+
+#[tokio::test]
+async fn unknown_drafts_and_published_issues_cannot_be_changed() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = api_token(&app, &app.test_user).await;
+    let draft_id = create_draft(&app, &token).await;
+    let response = post_publish(&app, &token, &draft_id, "publish").await;
+    let issue: serde_json::Value = response.json().await.unwrap();
+    let issue_id = issue["issue_id"].as_str().unwrap();
+
+    // Act
+    let publish_response = post_publish(&app, &token, &Uuid::new_v4().to_string(), "other").await;
+    let update_response = new_api_client()
+        .put(format!("{}/api/v1/issues/{issue_id}", app.address))
+        .bearer_auth(&token)
+        .json(&draft_body())
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    // Assert
+    assert_eq!(publish_response.status().as_u16(), 404);
+    assert_eq!(update_response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn editors_can_create_drafts_but_not_publish_them() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role(Role::Editor);
+    editor.store(&app.db_pool).await;
+    let token = api_token(&app, &editor).await;
+    let draft_id = create_draft(&app, &token).await;
+
+    // Act
+    let response = post_publish(&app, &token, &draft_id, "publish").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["error"],
+        "a user with the role editor needs to be at least owner"
+    );
+    let (_, draft) = get_json(&app, &token, &format!("/api/v1/issues/{draft_id}")).await;
+    assert_eq!(draft["status"], "draft");
+}
+
+#[tokio::test]
+async fn malformed_bodies_are_rejected_with_a_json_error() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = api_token(&app, &app.test_user).await;
+
+    // Act
+    let response = new_api_client()
+        .post(format!("{}/api/v1/issues", app.address))
+        .bearer_auth(&token)
+        .header("Idempotency-Key", "create")
+        .json(&serde_json::json!({ "title": "newsletter title" }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("content"));
+}
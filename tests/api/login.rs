@@ -1,3 +1,7 @@
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher, Version,
+};
+
 use crate::helpers::{assert_is_redirect_to, spawn_app};
 
 #[tokio::test]
@@ -51,3 +55,59 @@ async fn an_error_flash_message_is_set_on_failure() {
     // Assert 3
     assert!(!html_page.contains(r#"Authentication failed"#));
 }
+
+#[tokio::test]
+async fn password_hashes_with_outdated_parameters_are_upgraded_on_login() {
+    // Arrange
+    let app = spawn_app().await;
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let weak_password_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(4096, 1, 1, None).unwrap(),
+    )
+    .hash_password(app.test_user.password.as_bytes(), &salt)
+    .unwrap()
+    .to_string();
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE user_id = $2",
+        weak_password_hash,
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let stored_password_hash = sqlx::query!(
+        "SELECT password_hash FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .password_hash;
+    let params = Params::try_from(&PasswordHash::new(&stored_password_hash).unwrap()).unwrap();
+    assert_eq!(params.m_cost(), app.password_hashing.memory_cost_kib);
+    assert_eq!(params.t_cost(), app.password_hashing.time_cost);
+    assert_eq!(params.p_cost(), app.password_hashing.parallelism);
+
+    // the upgraded hash still matches the password
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
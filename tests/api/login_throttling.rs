@@ -0,0 +1,143 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn fail_login(app: &TestApp, username: &str) {
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": username,
+            "password": "wrong-password"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+async fn login_correctly(app: &TestApp) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn a_username_is_locked_out_after_too_many_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..5 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+
+    // Act
+    let response = login_correctly(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Too many failed login attempts - please try again in 15 minutes."));
+    let lockout = sqlx::query!("SELECT throttle_key, n_failures FROM login_lockouts")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        lockout.throttle_key,
+        format!("username:{}", app.test_user.username)
+    );
+    assert_eq!(lockout.n_failures, 5);
+}
+
+#[tokio::test]
+async fn an_ip_is_locked_out_after_too_many_failures_across_usernames() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..20 {
+        fail_login(&app, &Uuid::new_v4().to_string()).await;
+    }
+
+    // Act
+    let response = login_correctly(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let lockout = sqlx::query!("SELECT throttle_key FROM login_lockouts")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(lockout.throttle_key, "ip:127.0.0.1");
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_failures_of_the_username() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..4 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    login_correctly(&app).await;
+    app.post_logout().await;
+    for _ in 0..4 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+
+    // Act
+    let response = login_correctly(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn logins_are_accepted_again_once_the_lockout_is_over() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..5 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    sqlx::query!("UPDATE login_throttles SET locked_until = now() - interval '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = login_correctly(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn expired_throttles_are_pruned() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO login_throttles (throttle_key, n_failures, window_started_at, locked_until)
+        VALUES
+            ('username:expired', 3, now() - interval '1 day', NULL),
+            ('username:unlocked', 0, now() - interval '1 day', now() - interval '1 hour'),
+            ('username:locked', 0, now() - interval '1 day', now() + interval '1 hour')
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    fail_login(&app, "somebody").await;
+
+    // Assert
+    let mut keys: Vec<_> = sqlx::query!("SELECT throttle_key FROM login_throttles")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.throttle_key)
+        .collect();
+    keys.sort();
+    assert_eq!(
+        keys,
+        vec!["ip:127.0.0.1", "username:locked", "username:somebody"]
+    );
+}
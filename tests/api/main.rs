@@ -1,8 +1,23 @@
 mod admin_dashboard;
+mod api_tokens;
+mod audit;
 mod change_password;
+mod csrf;
 mod health_check;
 mod helpers;
+mod invites;
+mod issues;
+mod issues_api;
 mod login;
+mod login_throttling;
+mod migrations;
 mod newsletter;
+mod password_reset;
+mod roles;
+mod sessions;
 mod subscriptions;
 mod subscriptions_confirm;
+mod suppressions;
+mod tracking;
+mod two_factor;
+mod users;
@@ -0,0 +1,46 @@
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+use uuid::Uuid;
+use zero_2_prod::{
+    configuration::get_configuration,
+    migrations::{ensure_schema_is_up_to_date, migration_status, run_migrations},
+};
+
+use crate::helpers::spawn_app;
+
+async fn empty_database() -> PgPool {
+    let mut configuration = get_configuration().unwrap().database;
+    configuration.database_name = Uuid::new_v4().to_string();
+    let mut connection = PgConnection::connect_with(&configuration.without_db())
+        .await
+        .unwrap();
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, configuration.database_name).as_str())
+        .await
+        .unwrap();
+    PgPool::connect_with(configuration.with_db()).await.unwrap()
+}
+
+#[tokio::test]
+async fn all_migrations_are_applied_to_the_test_database() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let status = migration_status(&app.db_pool).await.unwrap();
+    // Assert
+    assert!(!status.is_empty());
+    assert!(status.iter().all(|m| m.applied));
+    assert!(ensure_schema_is_up_to_date(&app.db_pool).await.is_ok());
+}
+
+#[tokio::test]
+async fn an_outdated_schema_is_refused_until_migrated() {
+    // Arrange
+    let pool = empty_database().await;
+    assert!(ensure_schema_is_up_to_date(&pool).await.is_err());
+
+    // Act
+    run_migrations(&pool).await.unwrap();
+
+    // Assert
+    assert!(ensure_schema_is_up_to_date(&pool).await.is_ok());
+}
@@ -1,15 +1,21 @@
 use std::time::Duration;
 
+use sqlx::postgres::PgListener;
 use wiremock::{
     matchers::{any, method, path},
-    Mock, ResponseTemplate,
+    Mock, Request, ResponseTemplate,
 };
 
-use fake::faker::internet::en::SafeEmail;
-use fake::faker::name::en::Name;
-use fake::Fake;
+use zero_2_prod::{
+    configuration::DeliveryRateLimitSettings,
+    issue_delivery_worker::{try_execute_task, ExecutionOutcome},
+    rate_limit::DeliveryRateLimiter,
+};
 
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+use crate::helpers::{
+    accept_all_emails, assert_is_redirect_to, create_confirmed_subscriber,
+    create_unconfirmed_subscriber, spawn_app,
+};
 
 #[tokio::test]
 async fn concurrent_form_submission_is_handled_gracefully() {
@@ -18,9 +24,11 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .respond_with(|request: &Request| {
+            accept_all_emails(request).set_delay(Duration::from_secs(2))
+        })
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -51,9 +59,9 @@ async fn newsletter_creation_is_idempotent() {
     let app = spawn_app().await;
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(accept_all_emails)
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -145,9 +153,9 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     let app = spawn_app().await;
     create_confirmed_subscriber(&app).await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(accept_all_emails)
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -211,43 +219,365 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     // mock verifies that we have not received the newsletter email
 }
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let name: String = Name().fake();
-    let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&serde_json::json!({
-        "name": name,
-        "email": email
-    }))
-    .unwrap();
+#[tokio::test]
+async fn deliveries_beyond_the_rate_limit_are_postponed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_all_emails)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletter_issue(&serde_json::json!({
+            "title": "newsletter title",
+            "content": "newsletter content",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let rate_limiter = DeliveryRateLimiter::new(&DeliveryRateLimitSettings {
+        per_hour: Some(1),
+        ..Default::default()
+    });
+
+    // Act
+    let mut outcomes = Vec::new();
+    for _ in 0..2 {
+        outcomes.push(
+            try_execute_task(
+                &app.db_pool,
+                &app.email_client,
+                &rate_limiter,
+                &app.base_url,
+                1,
+            )
+            .await
+            .unwrap(),
+        );
+    }
 
-    let _mock_guard = Mock::given(path("/email"))
+    // Assert
+    assert!(matches!(outcomes[0], ExecutionOutcome::TaskCompleted));
+    let ExecutionOutcome::RateLimited { retry_after } = outcomes[1] else {
+        panic!("the second delivery should have been rate limited");
+    };
+    assert!(retry_after > Duration::from_secs(60 * 59));
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 1);
+    // the delivery which has not been worked on is due again right away
+    let n_due = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue WHERE execute_after <= now()"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_due, 1);
+}
+
+#[tokio::test]
+async fn a_failure_to_record_one_delivery_keeps_the_other_outcomes() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..2 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("create unconfirmed subscriber")
+        .respond_with(accept_all_emails)
         .expect(1)
-        .mount_as_scoped(&app.email_server)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletter_issue(&serde_json::json!({
+            "title": "newsletter title",
+            "content": "newsletter content",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
         .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let broken_email = sqlx::query!("SELECT email FROM subscriptions LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    // the outcome of one of the two deliveries cannot be stored
+    sqlx::query(
+        r#"
+        CREATE FUNCTION fail_delivery() RETURNS trigger AS $$
+        BEGIN
+            RAISE EXCEPTION 'the delivery cannot be recorded';
+        END
+        $$ LANGUAGE plpgsql
+        "#,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query(&format!(
+        r#"
+        CREATE TRIGGER fail_delivery
+        BEFORE UPDATE ON issue_deliveries
+        FOR EACH ROW
+        WHEN (NEW.subscriber_email = '{broken_email}')
+        EXECUTE FUNCTION fail_delivery()
+        "#
+    ))
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let outcome = try_execute_task(
+        &app.db_pool,
+        &app.email_client,
+        &app.rate_limiter,
+        &app.base_url,
+        2,
+    )
+    .await;
 
-    app.post_subscriptions(body)
+    // Assert
+    assert!(outcome.is_err());
+    let delivery = sqlx::query!(
+        "SELECT status FROM issue_deliveries WHERE subscriber_email != $1",
+        broken_email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivery.status, "sent");
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
         .await
-        .error_for_status()
         .unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].subscriber_email, broken_email);
+}
 
-    let email_request = &app
-        .email_server
-        .received_requests()
+#[tokio::test]
+async fn a_failure_while_preparing_a_batch_releases_its_unsent_tasks() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..2 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_all_emails)
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletter_issue(&serde_json::json!({
+            "title": "newsletter title",
+            "content": "newsletter content",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    // skipping the suppressed subscriber cannot be recorded
+    let broken_email = sqlx::query!("SELECT email FROM subscriptions LIMIT 1")
+        .fetch_one(&app.db_pool)
         .await
         .unwrap()
-        .pop()
-        .unwrap();
-    app.get_confirmation_links(&email_request)
+        .email;
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressions (suppression_id, kind, value, reason, created_at)
+        VALUES ($1, 'address', $2, 'manual', now())
+        "#,
+        uuid::Uuid::new_v4(),
+        broken_email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        r#"
+        CREATE FUNCTION fail_delivery() RETURNS trigger AS $$
+        BEGIN
+            RAISE EXCEPTION 'the delivery cannot be recorded';
+        END
+        $$ LANGUAGE plpgsql
+        "#,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        r#"
+        CREATE TRIGGER fail_delivery
+        BEFORE UPDATE ON issue_deliveries
+        FOR EACH ROW
+        WHEN (NEW.status = 'skipped')
+        EXECUTE FUNCTION fail_delivery()
+        "#,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let outcome = try_execute_task(
+        &app.db_pool,
+        &app.email_client,
+        &app.rate_limiter,
+        &app.base_url,
+        2,
+    )
+    .await;
+
+    // Assert
+    assert!(outcome.is_err());
+    let n_due = sqlx::query!(
+        r#"SELECT COUNT(*) AS "n_due!" FROM issue_delivery_queue WHERE execute_after <= now()"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .n_due;
+    assert_eq!(n_due, 2);
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
-    reqwest::get(confirmation_link.html)
+#[tokio::test]
+async fn concurrent_workers_deliver_each_email_exactly_once() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_all_emails)
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletter_issue(&serde_json::json!({
+            "title": "newsletter title",
+            "content": "newsletter content",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - two workers claim batches of two deliveries at the same time
+    let worker = || {
+        try_execute_task(
+            &app.db_pool,
+            &app.email_client,
+            &app.rate_limiter,
+            &app.base_url,
+            2,
+        )
+    };
+    let (first, second) = tokio::join!(worker(), worker());
+    first.unwrap();
+    second.unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let n_sent =
+        sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_deliveries WHERE status = 'sent'"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(n_sent, 3);
+    let mut recipients = Vec::new();
+    for request in app.email_server.received_requests().await.unwrap() {
+        if request.url.path() == "/email/batch" {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            for email in body.as_array().unwrap() {
+                recipients.push(email["To"].as_str().unwrap().to_owned());
+            }
+        }
+    }
+    // every subscriber got the issue exactly once
+    assert_eq!(recipients.len(), 3);
+    recipients.sort();
+    recipients.dedup();
+    assert_eq!(recipients.len(), 3);
+}
+
+#[tokio::test]
+async fn a_batch_only_holds_deliveries_of_a_single_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_all_emails)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    for title in ["first issue", "second issue"] {
+        let response = app
+            .post_newsletter_issue(&serde_json::json!({
+                "title": title,
+                "content": "newsletter content",
+                "idempotency_key": uuid::Uuid::new_v4().to_string()
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/newsletters");
+    }
+
+    // Act
+    try_execute_task(
+        &app.db_pool,
+        &app.email_client,
+        &app.rate_limiter,
+        &app.base_url,
+        10,
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
         .await
         .unwrap()
-        .error_for_status()
+        .count;
+    assert_eq!(n_queued, 1);
+}
+
+#[tokio::test]
+async fn publishing_an_issue_notifies_the_delivery_workers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let mut listener = PgListener::connect_with(&app.db_pool).await.unwrap();
+    listener.listen("issue_delivery_queue").await.unwrap();
+
+    // Act
+    let response = app
+        .post_newsletter_issue(&serde_json::json!({
+            "title": "newsletter title",
+            "content": "newsletter content",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    tokio::time::timeout(Duration::from_secs(5), listener.recv())
+        .await
+        .expect("the workers were not notified")
         .unwrap();
 }
@@ -0,0 +1,261 @@
+use std::time::Duration;
+
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+const NEW_PASSWORD: &str = "a brand new password";
+
+async fn set_test_user_email(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE users SET email = 'admin@example.com' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// Requests a reset for the test user and returns the link from the reset email.
+async fn request_reset_link(app: &TestApp) -> reqwest::Url {
+    set_test_user_email(app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_password_reset_request(&serde_json::json!({
+            "username": &app.test_user.username
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let email_request = received_email(app).await;
+    app.get_confirmation_links(&email_request).plain_text
+}
+
+/// The reset email is sent in the background, so it may arrive after the response.
+async fn received_email(app: &TestApp) -> wiremock::Request {
+    for _ in 0..50 {
+        if let Some(request) = app.email_server.received_requests().await.unwrap().pop() {
+            return request;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("no email has been sent");
+}
+
+fn reset_body(reset_link: &reqwest::Url) -> serde_json::Value {
+    let token = reset_link
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned();
+    serde_json::json!({
+        "token": token,
+        "new_password": NEW_PASSWORD,
+        "new_password_check": NEW_PASSWORD
+    })
+}
+
+#[tokio::test]
+async fn the_login_form_links_to_the_password_reset() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let html_page = app.get_login_html().await;
+    // Assert
+    assert!(html_page.contains(r#"href="/password-reset""#));
+}
+
+#[tokio::test]
+async fn the_reset_link_sets_a_new_password() {
+    // Arrange
+    let app = spawn_app().await;
+    let reset_link = request_reset_link(&app).await;
+
+    // Act - Part 1 - open the link
+    let response = app.api_client.get(reset_link.clone()).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act - Part 2 - set the new password
+    let response = app.post_password_reset(&reset_body(&reset_link)).await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Your password has been reset - you can log in now."));
+
+    // Assert
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": NEW_PASSWORD
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn reset_links_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let reset_link = request_reset_link(&app).await;
+    app.post_password_reset(&reset_body(&reset_link)).await;
+
+    // Act
+    let response = app.api_client.get(reset_link.clone()).send().await.unwrap();
+    let mut second_reset = reset_body(&reset_link);
+    second_reset["new_password"] = "yet another password".into();
+    second_reset["new_password_check"] = "yet another password".into();
+    app.post_password_reset(&second_reset).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": NEW_PASSWORD
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn tampered_reset_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let reset_link = request_reset_link(&app).await;
+    let mut body = reset_body(&reset_link);
+    let token = body["token"].as_str().unwrap().to_owned();
+    // pushes the expiry date into the future without updating the signature
+    let (payload, signature) = token.rsplit_once('.').unwrap();
+    let (user_id, _) = payload.split_once('.').unwrap();
+    body["token"] = format!("{user_id}.99999999999.{signature}").into();
+
+    // Act
+    let response = app.post_password_reset(&body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("The reset link is invalid, has expired or has already been used."));
+}
+
+#[tokio::test]
+async fn resetting_the_password_logs_out_existing_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let reset_link = request_reset_link(&app).await;
+
+    // Act
+    app.post_password_reset(&reset_body(&reset_link)).await;
+
+    // Assert
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_response_does_not_reveal_whether_the_username_exists() {
+    // Arrange
+    let app = spawn_app().await;
+    set_test_user_email(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let known = app
+        .post_password_reset_request(&serde_json::json!({
+            "username": &app.test_user.username
+        }))
+        .await;
+    let known_html = app.get_login_html().await;
+    let unknown = app
+        .post_password_reset_request(&serde_json::json!({ "username": "nobody" }))
+        .await;
+    let unknown_html = app.get_login_html().await;
+
+    // Assert
+    assert_is_redirect_to(&known, "/login");
+    assert_is_redirect_to(&unknown, "/login");
+    assert_eq!(known_html, unknown_html);
+    received_email(&app).await;
+}
+
+#[tokio::test]
+async fn the_response_does_not_reveal_a_failure_to_send_the_email() {
+    // Arrange
+    let app = spawn_app().await;
+    set_test_user_email(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_password_reset_request(&serde_json::json!({
+            "username": &app.test_user.username
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("a link to reset its password has been sent"));
+    received_email(&app).await;
+}
+
+#[tokio::test]
+async fn reset_emails_are_limited_per_username() {
+    // Arrange
+    let app = spawn_app().await;
+    set_test_user_email(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act - one request more than allowed
+    for _ in 0..4 {
+        let response = app
+            .post_password_reset_request(&serde_json::json!({
+                "username": &app.test_user.username
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/login");
+    }
+
+    // Assert - the emails are sent in the background, so we give the last one time to arrive
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let n_emails = app.email_server.received_requests().await.unwrap().len();
+    assert_eq!(n_emails, 3);
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("a link to reset its password has been sent"));
+}
@@ -0,0 +1,242 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+use zero_2_prod::authentication::{list_users, Role};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp, TestUser,
+};
+
+async fn login_with_role(app: &TestApp, role: Role) -> TestUser {
+    let user = TestUser::generate_with_role(role);
+    user.store(&app.db_pool).await;
+    user.login(app).await;
+    user
+}
+
+fn draft_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "newsletter title",
+        "content": "newsletter content",
+    })
+}
+
+async fn get_draft_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT draft_id FROM newsletter_drafts")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .draft_id
+}
+
+#[tokio::test]
+async fn editors_can_save_drafts_but_not_publish_them() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    login_with_role(&app, Role::Editor).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - save a draft
+    let response = app.post_draft(&draft_body()).await;
+    assert_is_redirect_to(&response, "/admin/drafts");
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("The draft has been saved."));
+    assert!(html_page.contains("newsletter title"));
+    assert!(!html_page.contains("Publish"));
+
+    // Act - Part 2 - try to publish it
+    let draft_id = get_draft_id(&app).await;
+    let response = app.post_publish_draft(draft_id).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    // Act - Part 3 - try to publish directly
+    let response = app
+        .post_newsletter_issue(&serde_json::json!({
+            "title": "newsletter title",
+            "content": "newsletter content",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    // Assert
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn owners_can_publish_drafts() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    login_with_role(&app, Role::Editor).await;
+    app.post_draft(&draft_body()).await;
+    let draft_id = get_draft_id(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {"ErrorCode": 0, "Message": "OK", "MessageID": Uuid::new_v4().to_string()}
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - publish the draft
+    let response = app.post_publish_draft(draft_id).await;
+    assert_is_redirect_to(&response, "/admin/drafts");
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("The newsletter issue has been published."));
+    assert!(!html_page.contains("newsletter title"));
+
+    // Act - Part 2 - publishing it again does nothing
+    app.post_publish_draft(draft_id).await;
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("The draft does not exist or has already been published."));
+
+    // Assert
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn viewers_cannot_draft_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    login_with_role(&app, Role::Viewer).await;
+
+    // Act
+    let form_response = app.get_publish_newsletter().await;
+    let draft_response = app.post_draft(&draft_body()).await;
+
+    // Assert
+    assert_eq!(form_response.status().as_u16(), 403);
+    assert_eq!(draft_response.status().as_u16(), 403);
+    assert!(app.get_drafts_html().await.contains("<th>Title</th>"));
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    login_with_role(&app, Role::Editor).await;
+
+    // Act
+    let tracking_response = app
+        .post_subscriber_tracking(&serde_json::json!({
+            "subscriber_id": Uuid::new_v4().to_string(),
+            "tracking_enabled": "false"
+        }))
+        .await;
+    let suppression_response = app
+        .post_suppression(&serde_json::json!({
+            "entry": "someone@example.com",
+            "reason": "manual"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(tracking_response.status().as_u16(), 403);
+    assert_eq!(suppression_response.status().as_u16(), 403);
+    assert!(app.get_subscribers_html().await.contains("<th>Email</th>"));
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_users() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = login_with_role(&app, Role::Editor).await;
+
+    // Act - Part 1 - editors are turned away
+    let response = app.get_users().await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app
+        .post_user_role(&serde_json::json!({
+            "username": &editor.username,
+            "role": "owner"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    // Act - Part 2 - owners demote the editor
+    app.test_user.login(&app).await;
+    let response = app.get_users().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .post_user_role(&serde_json::json!({
+            "username": &editor.username,
+            "role": "viewer"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    // Assert
+    let users = list_users(&app.db_pool).await.unwrap();
+    let editor = users
+        .iter()
+        .find(|u| u.username == editor.username)
+        .unwrap();
+    assert_eq!(editor.role, Role::Viewer);
+}
+
+#[tokio::test]
+async fn owners_cannot_change_their_own_role() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_user_role(&serde_json::json!({
+        "username": &app.test_user.username,
+        "role": "viewer"
+    }))
+    .await;
+
+    // Assert
+    let html_page = app.get_users().await.text().await.unwrap();
+    assert!(html_page.contains("You cannot change your own role."));
+    let users = list_users(&app.db_pool).await.unwrap();
+    assert_eq!(users[0].role, Role::Owner);
+}
+
+#[tokio::test]
+async fn an_invalid_role_is_rejected_with_a_flash_message() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::generate_with_role(Role::Editor);
+    editor.store(&app.db_pool).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_user_role(&serde_json::json!({
+            "username": &editor.username,
+            "role": "emperor"
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = app.get_users().await.text().await.unwrap();
+    assert!(html_page.contains("emperor is not a valid role"));
+}
+
+#[tokio::test]
+async fn the_dashboard_only_offers_what_the_role_allows() {
+    // Arrange
+    let app = spawn_app().await;
+    login_with_role(&app, Role::Viewer).await;
+
+    // Act
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // Assert
+    assert!(html_page.contains("You are signed in as viewer."));
+    assert!(!html_page.contains(r#"href="/admin/newsletters""#));
+    assert!(!html_page.contains(r#"href="/admin/users""#));
+}
@@ -0,0 +1,256 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, new_api_client, spawn_app, TestApp, TestUser};
+
+/// Logs the test user in from a second browser and returns the client of the first one.
+async fn log_in_twice(app: &mut TestApp) -> reqwest::Client {
+    app.test_user.login(app).await;
+    let first_browser = std::mem::replace(&mut app.api_client, new_api_client());
+    app.test_user.login(app).await;
+    first_browser
+}
+
+async fn session_ids(app: &TestApp) -> Vec<Uuid> {
+    sqlx::query!("SELECT session_id FROM user_sessions ORDER BY created_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.session_id)
+        .collect()
+}
+
+async fn is_logged_in(app: &TestApp) -> bool {
+    app.get_admin_dashboard().await.status().as_u16() == 200
+}
+
+#[tokio::test]
+async fn the_sessions_of_the_user_are_listed() {
+    // Arrange
+    let mut app = spawn_app().await;
+    app.api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .user_agent("the first browser")
+        .build()
+        .unwrap();
+    log_in_twice(&mut app).await;
+
+    // Act
+    let html_page = app.get_sessions_html().await;
+
+    // Assert
+    assert!(html_page.contains("the first browser"));
+    assert!(html_page.contains("127.0.0.1"));
+    assert!(html_page.contains("This session"));
+    assert_eq!(
+        html_page
+            .matches("<button type=\"submit\">Log out</button>")
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn another_session_can_be_logged_out() {
+    // Arrange
+    let mut app = spawn_app().await;
+    let first_browser = log_in_twice(&mut app).await;
+    let first_session_id = session_ids(&app).await[0];
+
+    // Act
+    let response = app.post_revoke_session(first_session_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    assert!(app
+        .get_sessions_html()
+        .await
+        .contains("The session has been logged out."));
+    assert!(is_logged_in(&app).await);
+    app.api_client = first_browser;
+    assert!(!is_logged_in(&app).await);
+}
+
+#[tokio::test]
+async fn sessions_of_other_users_cannot_be_logged_out() {
+    // Arrange
+    let mut app = spawn_app().await;
+    let first_browser = log_in_twice(&mut app).await;
+    let first_session_id = session_ids(&app).await[0];
+    sqlx::query!(
+        "DELETE FROM user_sessions WHERE session_id <> $1",
+        first_session_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let other_user = TestUser::generate();
+    other_user.store(&app.db_pool).await;
+    app.api_client = new_api_client();
+    other_user.login(&app).await;
+
+    // Act
+    app.post_revoke_session(first_session_id).await;
+
+    // Assert
+    assert!(app
+        .get_sessions_html()
+        .await
+        .contains("The session does not exist anymore."));
+    app.api_client = first_browser;
+    assert!(is_logged_in(&app).await);
+}
+
+#[tokio::test]
+async fn all_other_sessions_can_be_logged_out() {
+    // Arrange
+    let mut app = spawn_app().await;
+    let first_browser = log_in_twice(&mut app).await;
+
+    // Act
+    let response = app.post_revoke_other_sessions().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    assert_eq!(session_ids(&app).await.len(), 1);
+    assert!(is_logged_in(&app).await);
+    app.api_client = first_browser;
+    assert!(!is_logged_in(&app).await);
+}
+
+#[tokio::test]
+async fn changing_the_password_logs_out_the_other_sessions() {
+    // Arrange
+    let mut app = spawn_app().await;
+    let first_browser = log_in_twice(&mut app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/password");
+    assert!(is_logged_in(&app).await);
+    app.api_client = first_browser;
+    assert!(!is_logged_in(&app).await);
+}
+
+#[tokio::test]
+async fn logging_out_removes_the_session() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_logout().await;
+
+    // Assert
+    assert!(session_ids(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn idle_sessions_expire() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    sqlx::query!("UPDATE user_sessions SET last_seen_at = now() - interval '1 day'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.get_admin_dashboard().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Your session has expired - please log in again."));
+    assert!(session_ids(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn expired_sessions_are_not_listed_and_removed_on_the_next_login() {
+    // Arrange
+    let mut app = spawn_app().await;
+    log_in_twice(&mut app).await;
+    let [first_session_id, second_session_id] = session_ids(&app).await[..] else {
+        panic!("there should be two sessions");
+    };
+    sqlx::query!(
+        "UPDATE user_sessions SET last_seen_at = now() - interval '1 day' WHERE session_id = $1",
+        first_session_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act - Part 1 - the expired session is not listed
+    let html_page = app.get_sessions_html().await;
+    assert!(!html_page.contains(&first_session_id.to_string()));
+    assert!(html_page.contains("This session"));
+
+    // Act - Part 2 - log in again
+    app.api_client = new_api_client();
+    app.test_user.login(&app).await;
+
+    // Assert
+    let session_ids = session_ids(&app).await;
+    assert_eq!(session_ids.len(), 2);
+    assert!(!session_ids.contains(&first_session_id));
+    assert!(session_ids.contains(&second_session_id));
+}
+
+#[tokio::test]
+async fn active_sessions_expire_after_their_maximum_lifetime() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    sqlx::query!("UPDATE user_sessions SET created_at = now() - interval '2 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.get_admin_dashboard().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Your session has expired - please log in again."));
+}
+
+#[tokio::test]
+async fn using_a_session_keeps_it_alive() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    sqlx::query!("UPDATE user_sessions SET last_seen_at = now() - interval '10 minutes'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.get_admin_dashboard().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let last_seen_at = sqlx::query!(
+        "SELECT now() - last_seen_at < interval '1 minute' AS recent FROM user_sessions"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .recent;
+    assert_eq!(last_seen_at, Some(true));
+}
@@ -0,0 +1,239 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{
+    accept_all_emails, assert_is_redirect_to, create_confirmed_subscriber, spawn_app,
+};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_suppression_list() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app.get_suppressions().await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_add_a_suppression_entry() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let response = app
+        .post_suppression(&serde_json::json!({
+            "entry": "ursula_le_guin@gmail.com",
+            "reason": "manual"
+        }))
+        .await;
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn suppression_entries_can_be_added_imported_and_removed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act I - Add a single address
+    let response = app
+        .post_suppression(&serde_json::json!({
+            "entry": "ursula_le_guin@gmail.com",
+            "reason": "manual"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains("&#x27;ursula_le_guin@gmail.com&#x27; has been added"));
+
+    // Act II - Import a list with an invalid line
+    let response = app
+        .post_suppression_import(&serde_json::json!({
+            "entries": "example.com\n\nnot a domain\nursula_le_guin@gmail.com",
+            "reason": "legal"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains("Imported 1 entries (1 were already on the suppression list)."));
+    assert!(html_page.contains("Skipped 1 invalid lines: not a domain"));
+
+    // Act III - Remove an entry
+    let saved = sqlx::query!("SELECT suppression_id FROM suppressions WHERE value = 'example.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let response = app
+        .post_suppression_removal(&serde_json::json!({
+            "suppression_id": saved.suppression_id
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    // Assert
+    let remaining: Vec<_> = sqlx::query!("SELECT value FROM suppressions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.value)
+        .collect();
+    assert_eq!(remaining, vec!["ursula_le_guin@gmail.com".to_string()]);
+}
+
+#[tokio::test]
+async fn removing_an_entry_twice_reports_an_error() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_suppression(&serde_json::json!({
+        "entry": "example.com",
+        "reason": "manual"
+    }))
+    .await;
+    let saved = sqlx::query!("SELECT suppression_id FROM suppressions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let body = serde_json::json!({ "suppression_id": saved.suppression_id });
+    app.post_suppression_removal(&body).await;
+    app.get_suppressions_html().await;
+
+    // Act
+    let response = app.post_suppression_removal(&body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains("The entry is not on the suppression list anymore."));
+    assert!(!html_page.contains("The entry has been removed from the suppression list."));
+}
+
+#[tokio::test]
+async fn only_the_first_invalid_lines_of_an_import_are_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let entries: Vec<_> = (0..300).map(|i| format!("not a domain {i}")).collect();
+
+    // Act
+    let response = app
+        .post_suppression_import(&serde_json::json!({
+            "entries": entries.join("\n"),
+            "reason": "legal"
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains(
+        "Skipped 300 invalid lines: not a domain 0, not a domain 1, not a domain 2, \
+        not a domain 3, not a domain 4 and 295 more"
+    ));
+}
+
+#[tokio::test]
+async fn subscribe_does_not_send_a_confirmation_to_a_suppressed_domain() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_suppression(&serde_json::json!({
+        "entry": "gmail.com",
+        "reason": "legal"
+    }))
+    .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40GMail.com")
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 0);
+}
+
+#[tokio::test]
+async fn subscribe_does_not_send_a_confirmation_to_a_subdomain_of_a_suppressed_domain() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_suppression(&serde_json::json!({
+        "entry": "example.com",
+        "reason": "legal"
+    }))
+    .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40mail.example.com")
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 0);
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_suppressed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let subscriber = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    app.post_suppression(&serde_json::json!({
+        "entry": subscriber.email,
+        "reason": "bounce"
+    }))
+    .await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_all_emails)
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletter_issue(&serde_json::json!({
+            "title": "newsletter title",
+            "content": "newsletter content",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    // Mock verifies that no email was sent
+}
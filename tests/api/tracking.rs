@@ -0,0 +1,188 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock,
+};
+
+use crate::helpers::{
+    accept_all_emails, assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp,
+};
+
+const ISSUE_CONTENT: &str = r#"<p>Read <a href="https://example.com/article">this</a></p>"#;
+
+async fn publish_and_deliver_issue(app: &TestApp, tracking_enabled: bool) -> Uuid {
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(accept_all_emails)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let mut body = serde_json::json!({
+        "title": "newsletter title",
+        "content": ISSUE_CONTENT,
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+    if tracking_enabled {
+        body["tracking_enabled"] = "on".into();
+    }
+    let response = app.post_newsletter_issue(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+async fn last_html_body(app: &TestApp) -> String {
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    body[0]["HtmlBody"].as_str().unwrap().to_owned()
+}
+
+fn tracking_link(app: &TestApp, html_body: &str, route: &str) -> reqwest::Url {
+    let link = linkify::LinkFinder::new()
+        .links(html_body)
+        .map(|l| l.as_str().to_owned())
+        .find(|l| l.contains(route))
+        .unwrap();
+    let mut link = reqwest::Url::parse(&link).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+    link
+}
+
+#[tokio::test]
+async fn opens_and_clicks_are_recorded_for_tracked_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_and_deliver_issue(&app, true).await;
+    let html_body = last_html_body(&app).await;
+    assert!(!html_body.contains(r#"href="https://example.com/article""#));
+
+    // Act I - Open the email
+    let response = app
+        .api_client
+        .get(tracking_link(&app, &html_body, "/t/open/"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+
+    // Act II - Click the link
+    let response = app
+        .api_client
+        .get(tracking_link(&app, &html_body, "/t/click/"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/article"
+    );
+
+    // Assert
+    let html_page = app.get_issue_status_html(issue_id).await;
+    assert!(html_page.contains("Opened: 1 (100.0%)"));
+    assert!(html_page.contains("Clicked: 1 (100.0%)"));
+}
+
+#[tokio::test]
+async fn the_pixel_is_served_even_if_the_open_cannot_be_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_and_deliver_issue(&app, true).await;
+    let html_body = last_html_body(&app).await;
+    // sabotage the recording of the event
+    sqlx::query!("ALTER TABLE delivery_events RENAME TO delivery_events_gone")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .api_client
+        .get(tracking_link(&app, &html_body, "/t/open/"))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+}
+
+#[tokio::test]
+async fn the_click_redirector_only_redirects_to_links_of_the_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_and_deliver_issue(&app, true).await;
+    let html_body = last_html_body(&app).await;
+    let mut link = tracking_link(&app, &html_body, "/t/click/");
+    link.set_query(Some("url=https%3A%2F%2Fevil.com"));
+
+    // Act
+    let response = app.api_client.get(link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn issues_without_tracking_are_sent_unchanged() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let issue_id = publish_and_deliver_issue(&app, false).await;
+
+    // Assert
+    assert_eq!(last_html_body(&app).await, ISSUE_CONTENT);
+    let html_page = app.get_issue_status_html(issue_id).await;
+    assert!(html_page.contains("Open and click tracking is disabled for this issue."));
+}
+
+#[tokio::test]
+async fn subscribers_who_opted_out_are_not_tracked() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act I - Opt out
+    let response = app
+        .post_subscriber_tracking(&serde_json::json!({
+            "subscriber_id": subscriber.id,
+            "tracking_enabled": false
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_subscribers_html().await;
+    assert!(html_page.contains("Open and click tracking has been disabled for the subscriber."));
+
+    // Act II - Publish a tracked issue
+    publish_and_deliver_issue(&app, true).await;
+
+    // Assert
+    assert_eq!(last_html_body(&app).await, ISSUE_CONTENT);
+}
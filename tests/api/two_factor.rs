@@ -0,0 +1,180 @@
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+struct Enrolment {
+    totp: TOTP,
+    recovery_codes: Vec<String>,
+}
+
+fn text_between<'a>(html: &'a str, start: &str, end: &str) -> &'a str {
+    let from = html.find(start).unwrap() + start.len();
+    let to = from + html[from..].find(end).unwrap();
+    &html[from..to]
+}
+
+/// Sets up two-factor authentication for the test user and logs them out again.
+async fn enrol(app: &TestApp) -> Enrolment {
+    app.test_user.login(app).await;
+    let html_page = app.get_two_factor_html().await;
+    let secret = text_between(&html_page, "<p><code>", "</code></p>").to_owned();
+    let totp = TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        30,
+        Secret::Encoded(secret).to_bytes().unwrap(),
+        None,
+        String::new(),
+    )
+    .unwrap();
+
+    let response = app
+        .post_two_factor_enable(&totp.generate_current().unwrap())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let recovery_codes = html_page
+        .split("<li><code>")
+        .skip(1)
+        .map(|s| s[..s.find("</code>").unwrap()].to_owned())
+        .collect();
+    app.post_logout().await;
+    Enrolment {
+        totp,
+        recovery_codes,
+    }
+}
+
+async fn post_password(app: &TestApp) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn enrolled_users_need_a_code_after_their_password() {
+    // Arrange
+    let app = spawn_app().await;
+    let enrolment = enrol(&app).await;
+
+    // Act - Part 1 - the password alone does not log in
+    let response = post_password(&app).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - the code does
+    let code = enrolment.totp.generate_current().unwrap();
+    let response = app.post_login_second_factor(&code).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn codes_cannot_be_used_twice() {
+    // Arrange
+    let app = spawn_app().await;
+    let enrolment = enrol(&app).await;
+    let code = enrolment.totp.generate_current().unwrap();
+    post_password(&app).await;
+    app.post_login_second_factor(&code).await;
+    app.post_logout().await;
+
+    // Act
+    post_password(&app).await;
+    let response = app.post_login_second_factor(&code).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn recovery_codes_can_be_used_once_instead_of_a_code() {
+    // Arrange
+    let app = spawn_app().await;
+    let enrolment = enrol(&app).await;
+    assert_eq!(enrolment.recovery_codes.len(), 10);
+    let recovery_code = &enrolment.recovery_codes[0];
+
+    // Act - Part 1 - use the recovery code
+    post_password(&app).await;
+    let response = app.post_login_second_factor(recovery_code).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert!(app
+        .get_two_factor_html()
+        .await
+        .contains("You have 9 unused recovery codes left."));
+    app.post_logout().await;
+
+    // Act - Part 2 - use it again
+    post_password(&app).await;
+    let response = app.post_login_second_factor(recovery_code).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn too_many_wrong_codes_require_the_password_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let enrolment = enrol(&app).await;
+    post_password(&app).await;
+
+    // Act
+    for _ in 0..5 {
+        app.post_login_second_factor("not a code").await;
+    }
+    let code = enrolment.totp.generate_current().unwrap();
+    let response = app.post_login_second_factor(&code).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn enrolment_needs_a_valid_code() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.get_two_factor_html().await;
+
+    // Act
+    let response = app.post_two_factor_enable("000000x").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    let response = post_password(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn disabling_two_factor_authentication_needs_the_password() {
+    // Arrange
+    let app = spawn_app().await;
+    let enrolment = enrol(&app).await;
+    post_password(&app).await;
+    app.post_login_second_factor(&enrolment.recovery_codes[0])
+        .await;
+
+    // Act - Part 1 - wrong password
+    app.post_two_factor_disable("wrong password").await;
+    assert!(app
+        .get_two_factor_html()
+        .await
+        .contains("The current password is incorrect."));
+
+    // Act - Part 2 - right password
+    app.post_two_factor_disable(&app.test_user.password).await;
+    app.post_logout().await;
+
+    // Assert
+    let response = post_password(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -0,0 +1,141 @@
+use secrecy::Secret;
+use uuid::Uuid;
+use zero_2_prod::authentication::{
+    create_user, delete_user, list_users, set_role, Role, UserError,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestUser};
+
+#[tokio::test]
+async fn there_is_no_seeded_admin_with_a_known_password() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let users = list_users(&app.db_pool).await.unwrap();
+    // Assert
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].username, app.test_user.username);
+}
+
+#[tokio::test]
+async fn created_users_can_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+    create_user(
+        &username,
+        None,
+        Secret::new(password.clone()),
+        Role::Viewer,
+        &app.password_hashing,
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": username,
+            "password": password
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn usernames_must_be_unique() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let outcome = create_user(
+        &app.test_user.username,
+        None,
+        Secret::new(Uuid::new_v4().to_string()),
+        Role::Owner,
+        &app.password_hashing,
+        &app.db_pool,
+    )
+    .await;
+    // Assert
+    assert!(matches!(outcome, Err(UserError::UsernameTaken(_))));
+}
+
+#[tokio::test]
+async fn deleted_users_can_no_longer_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+    // the last owner cannot be deleted
+    let other_owner = TestUser::generate();
+    other_owner.store(&app.db_pool).await;
+    app.test_user.login(&app).await;
+    // leaves a saved response behind
+    app.post_newsletter_issue(&serde_json::json!({
+        "title": "newsletter title",
+        "content": "newsletter content",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.post_logout().await;
+
+    // Act
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    delete_user(&app.test_user.username, &mut transaction)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+
+    // Assert
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let users = list_users(&app.db_pool).await.unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].username, other_owner.username);
+}
+
+#[tokio::test]
+async fn deleting_an_unknown_user_fails() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    let outcome = delete_user("nobody", &mut transaction).await;
+    // Assert
+    assert!(matches!(outcome, Err(UserError::UnknownUser(_))));
+}
+
+#[tokio::test]
+async fn the_last_owner_cannot_be_demoted() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    let outcome = set_role(&app.test_user.username, Role::Editor, &mut transaction).await;
+    drop(transaction);
+    // Assert
+    assert!(matches!(outcome, Err(UserError::LastOwner(_))));
+    let users = list_users(&app.db_pool).await.unwrap();
+    assert_eq!(users[0].role, Role::Owner);
+}
+
+#[tokio::test]
+async fn the_last_owner_cannot_be_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    // Act
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    let outcome = delete_user(&app.test_user.username, &mut transaction).await;
+    transaction.commit().await.unwrap();
+    // Assert
+    assert!(matches!(outcome, Err(UserError::LastOwner(_))));
+    let users = list_users(&app.db_pool).await.unwrap();
+    assert_eq!(users.len(), 1);
+}